@@ -0,0 +1,73 @@
+//! Compares how fast each engine runs the examples that finish without input.
+//!
+//! The constants pass is disabled, as it would run most of these examples at compile time and
+//! leave nothing for the engines to do.
+
+use std::{
+    fs, io,
+    time::{Duration, Instant},
+};
+
+use brainfuck::{
+    compile, compile_bytecode_with, compile_optimized_with,
+    config::Config,
+    error::Error,
+    passes::{Pass, PassManager},
+};
+
+const EXAMPLES: [&str; 7] = [
+    "brainfuck.org/squares",
+    "brainfuck.org/400genuine",
+    "brainfuck.org/dgenuine",
+    "brainfuck.org/sierpinski",
+    "brainfuck.org/hello_world",
+    "brainfuck.org/jahb",
+    "prime_generator",
+];
+
+/// The number of times each example is run by each engine.
+const RUNS: u32 = 20;
+
+/// Returns the average time it takes to run `execute`.
+fn time(mut execute: impl FnMut() -> Result<(), Error>) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        execute().expect("The example doesn't use input");
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let config = Config::default();
+    let passes = PassManager::default().without_pass(Pass::Constants);
+    println!("|example|plain|optimized|bytecode|jit|");
+    println!("|-|-|-|-|-|");
+    for example in EXAMPLES {
+        let path = format!(
+            "{}/brainfuck_examples/{example}.b",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let source = fs::read(path).expect("The example exists");
+        let tokens = || brainfuck::parse(source.iter().copied());
+
+        let mut plain = compile::<u8, _>(tokens(), config).expect("The example is valid");
+        let mut optimized = compile_optimized_with::<u8, _>(tokens(), config, &passes)
+            .expect("The example is valid");
+        let mut bytecode = compile_bytecode_with::<u8, _>(tokens(), config, &passes)
+            .expect("The example is valid");
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        let jit = {
+            let mut jit = brainfuck::compile_jit_with::<u8, _>(tokens(), config, &passes)
+                .expect("The example is valid");
+            format!("{:?}", time(|| jit.execute(io::empty(), io::sink())))
+        };
+        #[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+        let jit = "-";
+        println!(
+            "|{example}|{:?}|{:?}|{:?}|{jit}|",
+            time(|| plain.execute(io::empty(), io::sink())),
+            time(|| optimized.execute(io::empty(), io::sink())),
+            time(|| bytecode.execute(io::empty(), io::sink())),
+        );
+    }
+}
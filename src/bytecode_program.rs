@@ -0,0 +1,534 @@
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use core::mem;
+use std::io::{Read, Write};
+
+use crate::{
+    cell::Cell,
+    config::{Config, Overflow},
+    error::Error,
+    optimized_command::OptimizedCommand,
+    optimized_program::OptimizedProgram,
+    tape::Tape,
+};
+
+// Every instruction is an opcode word followed by its operand words.
+// Cell values of up to 32 bits are stored in their operand. Wider values are stored once in a
+// table of constants and referred to by their index, and the bytes of constant output are stored
+// in a table of bytes and referred to by their range.
+
+/// Operand: amount.
+const ADD_POINTER: u32 = 0;
+/// Operand: amount.
+const SUBTRACT_POINTER: u32 = 1;
+/// Operand: constant.
+const ADD_VALUE: u32 = 2;
+/// Operand: constant.
+const SUBTRACT_VALUE: u32 = 3;
+/// Operand: constant.
+const SET_VALUE: u32 = 4;
+/// Operands: offset, constant.
+const MULTIPLY_ADD: u32 = 5;
+/// Operands: offset, constant.
+const MULTIPLY_SUBTRACT: u32 = 6;
+/// Operand: stride.
+const SCAN_RIGHT: u32 = 7;
+/// Operand: stride.
+const SCAN_LEFT: u32 = 8;
+/// Operands: offset, constant.
+const ADD_AT: u32 = 9;
+/// Operands: offset, constant.
+const SUBTRACT_AT: u32 = 10;
+/// Operands: offset, constant.
+const SET_AT: u32 = 11;
+/// Operand: offset.
+const OUTPUT_AT: u32 = 12;
+const OUTPUT: u32 = 13;
+/// Operands: start and end of the bytes.
+const OUTPUT_BYTES: u32 = 14;
+const INPUT: u32 = 15;
+/// Operand: the instruction after the end of the loop.
+const JUMP_IF_ZERO: u32 = 16;
+/// Operand: the first instruction of the loop body.
+const JUMP_IF_NOT_ZERO: u32 = 17;
+
+/// Returns the number of words of an instruction, including the opcode.
+const fn length(opcode: u32) -> usize {
+    match opcode {
+        OUTPUT | INPUT => 1,
+        MULTIPLY_ADD | MULTIPLY_SUBTRACT | ADD_AT | SUBTRACT_AT | SET_AT | OUTPUT_BYTES => 3,
+        _ => 2,
+    }
+}
+
+/// Converts an operand to a word, if it fits.
+fn word<Operand: TryInto<u32>>(operand: Operand) -> Result<u32, Error> {
+    operand.try_into().ok().ok_or(Error::ProgramTooLarge)
+}
+
+/// Converts an offset to a word, if it fits in 32 bits.
+fn offset_word(offset: isize) -> Result<u32, Error> {
+    i32::try_from(offset)
+        .map(i32::cast_unsigned)
+        .ok()
+        .ok_or(Error::ProgramTooLarge)
+}
+
+/// Reads an operand that is an amount or an index.
+fn index(word: u32) -> usize {
+    usize::try_from(word).expect("Operands fit in a pointer")
+}
+
+/// Reads an operand that is an offset.
+fn signed(word: u32) -> isize {
+    isize::try_from(word.cast_signed()).expect("Offsets fit in a pointer")
+}
+
+/// A brainfuck program compiled to a compact bytecode.
+///
+/// Every instruction takes 4 to 12 bytes, which keeps more of the program in the cache than
+/// [`OptimizedCommand`]s do, and jumps go straight to the instruction after the other end of the
+/// loop.
+#[derive(Debug)]
+pub struct BytecodeProgram<C: Cell = u8> {
+    code: Vec<u32>,
+    constants: Vec<C>,
+    /// The index of every value in `constants`, so each value is only stored once.
+    constant_indices: BTreeMap<C, u32>,
+    bytes: Vec<u8>,
+    tape: Tape<C>,
+    config: Config,
+    /// The number of instructions an execution can run, which is unlimited by default.
+    step_limit: u64,
+}
+
+impl<C: Cell> TryFrom<&OptimizedProgram<C>> for BytecodeProgram<C> {
+    type Error = Error;
+
+    fn try_from(optimized: &OptimizedProgram<C>) -> Result<Self, Self::Error> {
+        let mut program = Self {
+            code: Vec::new(),
+            constants: Vec::new(),
+            constant_indices: BTreeMap::new(),
+            bytes: Vec::new(),
+            tape: Tape::new(optimized.config().tape),
+            config: optimized.config(),
+            step_limit: u64::MAX,
+        };
+
+        // The starts of the loops that are still open, to fill in their jumps once they end
+        let mut open_loops = Vec::new();
+        for command in optimized.commands() {
+            match command {
+                OptimizedCommand::AddPointer(value) => program.emit(ADD_POINTER, &[word(*value)?]),
+                OptimizedCommand::SubtractPointer(value) => {
+                    program.emit(SUBTRACT_POINTER, &[word(*value)?]);
+                }
+                OptimizedCommand::AddValue(value) => {
+                    let constant = program.constant(*value)?;
+                    program.emit(ADD_VALUE, &[constant]);
+                }
+                OptimizedCommand::SubtractValue(value) => {
+                    let constant = program.constant(*value)?;
+                    program.emit(SUBTRACT_VALUE, &[constant]);
+                }
+                OptimizedCommand::SetValue(value) => {
+                    let constant = program.constant(*value)?;
+                    program.emit(SET_VALUE, &[constant]);
+                }
+                OptimizedCommand::MultiplyAdd { offset, factor } => {
+                    let constant = program.constant(*factor)?;
+                    program.emit(MULTIPLY_ADD, &[offset_word(*offset)?, constant]);
+                }
+                OptimizedCommand::MultiplySubtract { offset, factor } => {
+                    let constant = program.constant(*factor)?;
+                    program.emit(MULTIPLY_SUBTRACT, &[offset_word(*offset)?, constant]);
+                }
+                OptimizedCommand::ScanRight(stride) => program.emit(SCAN_RIGHT, &[word(*stride)?]),
+                OptimizedCommand::ScanLeft(stride) => program.emit(SCAN_LEFT, &[word(*stride)?]),
+                OptimizedCommand::AddAt { offset, value } => {
+                    let constant = program.constant(*value)?;
+                    program.emit(ADD_AT, &[offset_word(*offset)?, constant]);
+                }
+                OptimizedCommand::SubtractAt { offset, value } => {
+                    let constant = program.constant(*value)?;
+                    program.emit(SUBTRACT_AT, &[offset_word(*offset)?, constant]);
+                }
+                OptimizedCommand::SetAt { offset, value } => {
+                    let constant = program.constant(*value)?;
+                    program.emit(SET_AT, &[offset_word(*offset)?, constant]);
+                }
+                OptimizedCommand::OutputAt { offset } => {
+                    program.emit(OUTPUT_AT, &[offset_word(*offset)?]);
+                }
+                OptimizedCommand::Output => program.emit(OUTPUT, &[]),
+                OptimizedCommand::OutputBytes(bytes) => {
+                    let start = word(program.bytes.len())?;
+                    program.bytes.extend(bytes);
+                    program.emit(OUTPUT_BYTES, &[start, word(program.bytes.len())?]);
+                }
+                OptimizedCommand::Input => program.emit(INPUT, &[]),
+                OptimizedCommand::StartOfLoop { .. } => {
+                    open_loops.push(program.code.len());
+                    program.emit(JUMP_IF_ZERO, &[0]);
+                }
+                OptimizedCommand::EndOfLoop { .. } => {
+                    let start = open_loops.pop().ok_or(Error::UnmatchedEndOfLoop(None))?;
+                    program.emit(JUMP_IF_NOT_ZERO, &[word(start + length(JUMP_IF_ZERO))?]);
+                    program.code[start + 1] = word(program.code.len())?;
+                }
+            }
+        }
+
+        if open_loops.is_empty() {
+            Ok(program)
+        } else {
+            Err(Error::UnmatchedStartOfLoop(None))
+        }
+    }
+}
+
+impl<C: Cell> BytecodeProgram<C> {
+    /// Stops the program with [`Error::StepLimit`] once it has run `limit` instructions, which
+    /// are as many steps as [`OptimizedProgram::with_step_limit`] counts.
+    #[must_use]
+    pub const fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = limit;
+        self
+    }
+
+    /// Returns the tape as it was left by the last execution.
+    #[must_use]
+    pub const fn tape(&self) -> &Tape<C> {
+        &self.tape
+    }
+
+    /// Returns the encoded instructions.
+    #[must_use]
+    pub fn code(&self) -> &[u32] {
+        &self.code
+    }
+
+    fn emit(&mut self, opcode: u32, operands: &[u32]) {
+        self.code.push(opcode);
+        self.code.extend(operands);
+    }
+
+    /// Returns the operand for `value`: the value itself if cells fit in a word, and otherwise
+    /// the index of `value` in the table of constants, adding it if it's new.
+    fn constant(&mut self, value: C) -> Result<u32, Error> {
+        if C::BITS <= u32::BITS {
+            return word(value.to_u64());
+        }
+        if let Some(index) = self.constant_indices.get(&value) {
+            return Ok(*index);
+        }
+        let index = word(self.constants.len())?;
+        self.constants.push(value);
+        self.constant_indices.insert(value, index);
+        Ok(index)
+    }
+
+    /// Reads an operand that is a cell value, see [`Self::constant`].
+    fn value(&self, word: u32) -> C {
+        if C::BITS <= u32::BITS {
+            C::truncate(u64::from(word))
+        } else {
+            self.constants[index(word)]
+        }
+    }
+
+    /// Returns the index of the command the instruction at `pc` was compiled from.
+    fn command_index(&self, pc: usize) -> usize {
+        let mut start = 0;
+        let mut index = 0;
+        while start < pc {
+            start += length(self.code[start]);
+            index += 1;
+        }
+        index
+    }
+
+    /// Runs the instructions in `code`, errors report the index of the instruction instead of the
+    /// command.
+    fn run<Input: Read, Output: Write>(
+        &mut self,
+        code: &[u32],
+        input: &mut Input,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        let overflow = self.config.overflow;
+        // The instructions that are left to run, so the opcode and its operands are read by
+        // matching them without checking the program counter
+        let mut rest = code;
+        let mut steps = 0;
+        loop {
+            let pc = code.len() - rest.len();
+            if steps == self.step_limit && !rest.is_empty() {
+                return Err(Error::StepLimit { pc });
+            }
+            steps += 1;
+
+            rest = match rest {
+                [] => return Ok(()),
+                [ADD_POINTER, amount, next @ ..] => {
+                    self.tape.add_pointer(pc, index(*amount))?;
+                    next
+                }
+                [SUBTRACT_POINTER, amount, next @ ..] => {
+                    self.tape.subtract_pointer(pc, index(*amount))?;
+                    next
+                }
+                [ADD_VALUE, value, next @ ..] => {
+                    self.tape
+                        .change(pc, self.value(*value), overflow, Overflow::add)?;
+                    next
+                }
+                [SUBTRACT_VALUE, value, next @ ..] => {
+                    self.tape
+                        .change(pc, self.value(*value), overflow, Overflow::subtract)?;
+                    next
+                }
+                [SET_VALUE, value, next @ ..] => {
+                    self.tape.set(self.value(*value));
+                    next
+                }
+                [MULTIPLY_ADD, offset, factor, next @ ..] => {
+                    let (offset, factor) = (signed(*offset), self.value(*factor));
+                    self.tape
+                        .multiply(pc, offset, factor, overflow, Overflow::add)?;
+                    next
+                }
+                [MULTIPLY_SUBTRACT, offset, factor, next @ ..] => {
+                    let (offset, factor) = (signed(*offset), self.value(*factor));
+                    self.tape
+                        .multiply(pc, offset, factor, overflow, Overflow::subtract)?;
+                    next
+                }
+                [SCAN_RIGHT, stride, next @ ..] => {
+                    self.tape
+                        .checked_scan_right(pc, index(*stride), self.step_limit)?;
+                    next
+                }
+                [SCAN_LEFT, stride, next @ ..] => {
+                    self.tape
+                        .checked_scan_left(pc, index(*stride), self.step_limit)?;
+                    next
+                }
+                [ADD_AT, offset, value, next @ ..] => {
+                    let (offset, value) = (signed(*offset), self.value(*value));
+                    self.tape
+                        .change_at(pc, offset, value, overflow, Overflow::add)?;
+                    next
+                }
+                [SUBTRACT_AT, offset, value, next @ ..] => {
+                    let (offset, value) = (signed(*offset), self.value(*value));
+                    self.tape
+                        .change_at(pc, offset, value, overflow, Overflow::subtract)?;
+                    next
+                }
+                [SET_AT, offset, value, next @ ..] => {
+                    let (offset, value) = (signed(*offset), self.value(*value));
+                    *self.tape.checked_cell_at(pc, offset)? = value;
+                    next
+                }
+                [OUTPUT_AT, offset, next @ ..] => {
+                    let byte = self.tape.checked_cell_at(pc, signed(*offset))?.to_byte();
+                    output.write_all(&[byte])?;
+                    next
+                }
+                [OUTPUT, next @ ..] => {
+                    output.write_all(&[self.tape.get().to_byte()])?;
+                    next
+                }
+                [OUTPUT_BYTES, start, end, next @ ..] => {
+                    output.write_all(&self.bytes[index(*start)..index(*end)])?;
+                    next
+                }
+                [INPUT, next @ ..] => {
+                    self.tape.input(input, self.config.end_of_input)?;
+                    next
+                }
+                [JUMP_IF_ZERO, target, ..] if self.tape.get() == C::ZERO => &code[index(*target)..],
+                [JUMP_IF_NOT_ZERO, target, ..] if self.tape.get() != C::ZERO => {
+                    &code[index(*target)..]
+                }
+                // Jumps that aren't taken
+                _ => rest.get(2..).unwrap_or_default(),
+            };
+        }
+    }
+
+    /// Runs the program, reading bytes from `input` and writing bytes to `output`.
+    /// Errors report the index of the optimized command, like [`OptimizedProgram::execute`].
+    ///
+    /// # Errors
+    /// Returns an error if the input runs out, the program runs past its step limit or reading
+    /// input or writing output fails.
+    pub fn execute<Input: Read, Output: Write>(
+        &mut self,
+        mut input: Input,
+        mut output: Output,
+    ) -> Result<(), Error> {
+        // Take the code out of the program, so the running program can change its state
+        self.tape = Tape::new(self.config.tape);
+        let code = mem::take(&mut self.code);
+        let result = self.run(&code, &mut input, &mut output);
+        self.code = code;
+        let result = result.map_err(|error| match error {
+            Error::Overflow { pc, cell } => Error::Overflow {
+                pc: self.command_index(pc),
+                cell,
+            },
+            Error::TapeUnderflow { pc } => Error::TapeUnderflow {
+                pc: self.command_index(pc),
+            },
+            Error::TapeOverflow { pc } => Error::TapeOverflow {
+                pc: self.command_index(pc),
+            },
+            Error::StepLimit { pc } => Error::StepLimit {
+                pc: self.command_index(pc),
+            },
+            other => other,
+        });
+
+        // Flush the output
+        output.flush()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        compile_optimized, compile_optimized_with,
+        config::{Config, Edge, EndOfInput, Overflow, TapeMode},
+        parse,
+        passes::{Pass, PassManager},
+    };
+
+    use super::BytecodeProgram;
+
+    #[test]
+    fn same_as_optimized() {
+        let passes = PassManager::default().without_pass(Pass::Constants);
+        let configs = [
+            Config {
+                end_of_input: EndOfInput::Zero,
+                ..Config::default()
+            },
+            Config {
+                overflow: Overflow::Trap,
+                tape: TapeMode::Bounded {
+                    size: 3,
+                    edge: Edge::Trap,
+                },
+                ..Config::default()
+            },
+            Config {
+                overflow: Overflow::Trap,
+                end_of_input: EndOfInput::Unchanged,
+                tape: TapeMode::Bounded {
+                    size: 3,
+                    edge: Edge::Wrap,
+                },
+            },
+            Config {
+                overflow: Overflow::Saturate,
+                end_of_input: EndOfInput::MinusOne,
+                tape: TapeMode::RightInfinite,
+            },
+        ];
+        let sources: [&[u8]; 5] = [
+            b"++>+++[<.>>+<-]>>-<<.",
+            b"+++++[->++>+++<<]>>[-<+>]<.[>]+<[<]>.",
+            b">>+>+<<<,[>,]<[.<]",
+            b"++++++++[>+++++++++<-]>.<+++[>+++++++++++<-]>.",
+            b"++++++++++[>++++++++++<-]>[->>+++<<]",
+        ];
+        for source in sources {
+            for config in configs {
+                // Scans on the wrapping tape might never end
+                let mut expected =
+                    compile_optimized_with::<u8, _>(parse(source.iter().copied()), config, &passes)
+                        .expect("The loops are balanced")
+                        .with_step_limit(1000);
+                let mut program = BytecodeProgram::try_from(&expected)
+                    .expect("The program is small enough")
+                    .with_step_limit(1000);
+                let (mut expected_output, mut output) = (Vec::new(), Vec::new());
+                let expected_result = expected
+                    .execute(&b"abc"[..], &mut expected_output)
+                    .map_err(|error| error.to_string());
+                let result = program
+                    .execute(&b"abc"[..], &mut output)
+                    .map_err(|error| error.to_string());
+                assert_eq!(
+                    (output, result),
+                    (expected_output, expected_result),
+                    "{} with {config:?}",
+                    String::from_utf8_lossy(source)
+                );
+                assert_eq!(program.tape().cells(), expected.tape().cells());
+                assert_eq!(program.tape().position(), expected.tape().position());
+            }
+        }
+    }
+
+    #[test]
+    fn errors_point_at_commands() {
+        let trapping = Config {
+            overflow: Overflow::Trap,
+            ..Config::default()
+        };
+        let right_infinite = Config {
+            tape: TapeMode::RightInfinite,
+            ..Config::default()
+        };
+        let programs: [(&[u8], Config); 3] = [
+            (b">++<+++[->--<]", trapping),
+            (b",[>-<-]", trapping),
+            (b",[<+]", right_infinite),
+        ];
+        for (source, config) in programs {
+            let mut expected = compile_optimized::<u8, _>(parse(source.iter().copied()), config)
+                .expect("The loops are balanced");
+            let mut program =
+                BytecodeProgram::try_from(&expected).expect("The program is small enough");
+            let expected_error = expected
+                .execute(&[1, 1][..], io::sink())
+                .expect_err("The program fails");
+            let error = program
+                .execute(&[1, 1][..], io::sink())
+                .expect_err("The program fails");
+            assert_eq!(error.to_string(), expected_error.to_string());
+        }
+    }
+
+    #[test]
+    fn same_step_limit_as_optimized() {
+        let passes = PassManager::default().without_pass(Pass::Constants);
+        for limit in [0, 1, 3, 6, 7, 30] {
+            let mut expected = compile_optimized_with::<u8, _>(
+                parse(*b"++[>+++[>+<-]<-]>>[>]"),
+                Config::default(),
+                &passes,
+            )
+            .expect("The loops are balanced")
+            .with_step_limit(limit);
+            let mut program = BytecodeProgram::try_from(&expected)
+                .expect("The program is small enough")
+                .with_step_limit(limit);
+            let expected_result = expected
+                .execute(io::empty(), io::sink())
+                .map_err(|error| error.to_string());
+            let result = program
+                .execute(io::empty(), io::sink())
+                .map_err(|error| error.to_string());
+            assert_eq!(result, expected_result);
+        }
+    }
+}
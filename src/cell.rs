@@ -0,0 +1,113 @@
+use core::{
+    fmt::Debug,
+    ops::{Add, Sub},
+};
+
+/// An unsigned integer type that can be used as a cell on the tape.
+pub trait Cell:
+    Copy + Debug + Default + Eq + Ord + Add<Output = Self> + Sub<Output = Self>
+{
+    /// The number of bits in a cell.
+    const BITS: u32;
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+
+    /// Converts an input byte to a cell.
+    fn from_byte(byte: u8) -> Self;
+
+    /// Returns the lowest byte of the cell, which is what gets written to the output.
+    fn to_byte(self) -> u8;
+
+    /// Returns the value of the cell as a `u64`, which every cell fits in.
+    fn to_u64(self) -> u64;
+
+    /// Returns the lowest bits of `value` that fit in a cell.
+    fn truncate(value: u64) -> Self;
+
+    #[must_use]
+    fn wrapping_add(self, other: Self) -> Self;
+    #[must_use]
+    fn wrapping_sub(self, other: Self) -> Self;
+    #[must_use]
+    fn wrapping_mul(self, other: Self) -> Self;
+    #[must_use]
+    fn saturating_add(self, other: Self) -> Self;
+    #[must_use]
+    fn saturating_sub(self, other: Self) -> Self;
+    #[must_use]
+    fn saturating_mul(self, other: Self) -> Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+}
+
+/// Implements [`Cell`] for unsigned integer types.
+macro_rules! impl_cell {
+    ($($cell:ty),*) => {
+        $(
+            impl Cell for $cell {
+                const BITS: u32 = <$cell>::BITS;
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+                const MAX: Self = <$cell>::MAX;
+
+                fn from_byte(byte: u8) -> Self {
+                    Self::from(byte)
+                }
+
+                fn to_byte(self) -> u8 {
+                    // Masking off the higher bytes makes the conversion infallible
+                    u8::try_from(self & Self::from(u8::MAX)).unwrap_or_default()
+                }
+
+                fn to_u64(self) -> u64 {
+                    u64::from(self)
+                }
+
+                fn truncate(value: u64) -> Self {
+                    // Masking off the higher bits makes the conversion infallible
+                    Self::try_from(value & u64::from(Self::MAX)).unwrap_or_default()
+                }
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$cell>::wrapping_add(self, other)
+                }
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$cell>::wrapping_sub(self, other)
+                }
+
+                fn wrapping_mul(self, other: Self) -> Self {
+                    <$cell>::wrapping_mul(self, other)
+                }
+
+                fn saturating_add(self, other: Self) -> Self {
+                    <$cell>::saturating_add(self, other)
+                }
+
+                fn saturating_sub(self, other: Self) -> Self {
+                    <$cell>::saturating_sub(self, other)
+                }
+
+                fn saturating_mul(self, other: Self) -> Self {
+                    <$cell>::saturating_mul(self, other)
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$cell>::checked_add(self, other)
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$cell>::checked_sub(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$cell>::checked_mul(self, other)
+                }
+            }
+        )*
+    };
+}
+
+impl_cell!(u8, u16, u32, u64);
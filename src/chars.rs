@@ -1,9 +1,112 @@
-/// Converts a byte iterator to a char iterator
-pub struct Chars<T: Iterator>(T);
+use core::ops::RangeInclusive;
+
+/// What to do with bytes that aren't part of a valid UTF-8 sequence.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidUtf8 {
+    /// Every invalid sequence becomes a single U+FFFD replacement character.
+    #[default]
+    Replace,
+    /// Invalid sequences are dropped.
+    Skip,
+}
+
+/// How the bytes of the source code are turned into chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoding {
+    /// The source code is decoded as UTF-8.
+    Utf8(InvalidUtf8),
+    /// Every byte is a char, which is enough to find the commands as they are all ASCII.
+    Bytes,
+}
+
+impl Default for Decoding {
+    fn default() -> Self {
+        Self::Utf8(InvalidUtf8::default())
+    }
+}
+
+/// Converts a byte iterator to a char iterator.
+pub struct Chars<T: Iterator> {
+    bytes: T,
+    decoding: Decoding,
+    /// A byte that was read, but turned out not to be part of the previous char.
+    pending: Option<u8>,
+    /// The number of bytes that have been decoded.
+    decoded: usize,
+    /// The number of bytes before the char that was returned last.
+    start: usize,
+}
 
 impl<T: Iterator<Item = u8>> From<T> for Chars<T> {
     fn from(value: T) -> Self {
-        Self(value)
+        Self::new(value, Decoding::default())
+    }
+}
+
+impl<T: Iterator<Item = u8>> Chars<T> {
+    /// Creates a char iterator decoding `bytes` as specified by `decoding`.
+    pub const fn new(bytes: T, decoding: Decoding) -> Self {
+        Self {
+            bytes,
+            decoding,
+            pending: None,
+            decoded: 0,
+            start: 0,
+        }
+    }
+
+    /// Returns the number of bytes before the char returned last, including the invalid bytes
+    /// skipped right before it.
+    pub const fn offset(&self) -> usize {
+        self.start
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.pending.take().or_else(|| self.bytes.next())?;
+        self.decoded += 1;
+        Some(byte)
+    }
+
+    const fn unread(&mut self, byte: u8) {
+        self.pending = Some(byte);
+        self.decoded -= 1;
+    }
+
+    /// Decodes the next UTF-8 sequence, returning an error if the sequence is invalid.
+    /// A byte that can't continue the sequence isn't consumed, so it can start the next one.
+    fn decode(&mut self) -> Option<Result<char, ()>> {
+        // The first byte determines the length of the sequence and the valid range of the second
+        // byte, which excludes overlong encodings, surrogates and values above U+10FFFF
+        const CONTINUATION: RangeInclusive<u8> = 0x80..=0xBF;
+        let first = self.next_byte()?;
+        let (length, mut range): (usize, RangeInclusive<u8>) = match first {
+            0x00..=0x7F => return Some(Ok(char::from(first))),
+            0xC2..=0xDF => (2, CONTINUATION),
+            0xE0 => (3, 0xA0..=0xBF),
+            0xE1..=0xEC | 0xEE..=0xEF => (3, CONTINUATION),
+            0xED => (3, 0x80..=0x9F),
+            0xF0 => (4, 0x90..=0xBF),
+            0xF1..=0xF3 => (4, CONTINUATION),
+            0xF4 => (4, 0x80..=0x8F),
+            _ => return Some(Err(())),
+        };
+
+        // Remove the length bits from the first byte and add 6 bits for every following byte
+        let mut value = u32::from(first & (0x7F >> length));
+        for _ in 1..length {
+            match self.next_byte() {
+                Some(byte) if range.contains(&byte) => {
+                    value = (value << 6) | u32::from(byte & 0x3F);
+                }
+                Some(byte) => {
+                    self.unread(byte);
+                    return Some(Err(()));
+                }
+                None => return Some(Err(())),
+            }
+            range = CONTINUATION;
+        }
+        Some(char::from_u32(value).ok_or(()))
     }
 }
 
@@ -11,21 +114,43 @@ impl<T: Iterator<Item = u8>> Iterator for Chars<T> {
     type Item = char;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Stores the value of the char if valid
-        let mut value = 0;
-
-        // A utf-8 char can't be larger than 32-bit
-        for byte in self.0.by_ref().take(4) {
-            // Add the current byte to the char value
-            value = (value << 8) + u32::from(byte);
-
-            // Try to convert the value to a character, return it if valid
-            if let Some(value) = char::from_u32(value) {
-                return Some(value);
+        match self.decoding {
+            Decoding::Bytes => {
+                self.start = self.decoded;
+                self.next_byte().map(char::from)
             }
+            Decoding::Utf8(invalid) => loop {
+                self.start = self.decoded;
+                match (self.decode()?, invalid) {
+                    (Ok(character), _) => return Some(character),
+                    (Err(()), InvalidUtf8::Replace) => return Some(char::REPLACEMENT_CHARACTER),
+                    (Err(()), InvalidUtf8::Skip) => {}
+                }
+            },
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chars, Decoding, InvalidUtf8};
+
+    #[test]
+    fn multibyte_chars() {
+        let source = "\u{e9}+\u{20ac}[\u{1f600}]";
+        assert_eq!(Chars::from(source.bytes()).collect::<String>(), source);
+    }
 
-        // No character was found
-        None
+    #[test]
+    fn invalid_sequences() {
+        // A truncated 3-byte sequence, a lone continuation byte and an invalid first byte
+        let source = [b'+', 0xE2, 0x82, b'[', 0x80, 0xFF, b']'];
+        let decode = |decoding| Chars::new(source.into_iter(), decoding).collect::<String>();
+        assert_eq!(
+            decode(Decoding::Utf8(InvalidUtf8::Replace)),
+            "+\u{fffd}[\u{fffd}\u{fffd}]"
+        );
+        assert_eq!(decode(Decoding::Utf8(InvalidUtf8::Skip)), "+[]");
+        assert_eq!(decode(Decoding::Bytes), "+\u{e2}\u{82}[\u{80}\u{ff}]");
     }
 }
@@ -1,7 +1,9 @@
 use core::fmt::{self, Display, Formatter};
 use std::error;
 
-/// Contains a invalid brainfuck command (will just be ignored)
+use crate::{error::Error, position::Position};
+
+/// Contains a invalid brainfuck command (will just be ignored).
 #[expect(clippy::module_name_repetitions, reason = "Makes the error clearer")]
 #[derive(Debug)]
 pub struct InvalidCommand(char);
@@ -14,7 +16,8 @@ impl Display for InvalidCommand {
     }
 }
 
-#[derive(Debug)]
+/// A single brainfuck command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
     IncrementPointer,
     DecrementPointer,
@@ -44,3 +47,44 @@ impl TryFrom<char> for Command {
         }
     }
 }
+
+/// A command together with its position in the source code.
+#[derive(Debug)]
+pub struct Token {
+    pub command: Command,
+    pub position: Position,
+}
+
+impl Token {
+    /// Makes sure every loop is opened and closed, pointing at the offending bracket if not.
+    ///
+    /// # Errors
+    /// Returns an error with the position of the first unmatched bracket.
+    pub fn check_loops(tokens: &[Self]) -> Result<(), Error> {
+        let mut active_loops = Vec::new();
+
+        // Store the position of every loop start until it's closed.
+        // Fail if the end of a loop is found, while there are no active loops.
+        for token in tokens {
+            match token.command {
+                Command::StartOfLoop => active_loops.push(token.position),
+                Command::EndOfLoop => {
+                    if active_loops.pop().is_none() {
+                        return Err(Error::UnmatchedEndOfLoop(Some(token.position)));
+                    }
+                }
+                Command::IncrementPointer
+                | Command::DecrementPointer
+                | Command::IncrementValue
+                | Command::DecrementValue
+                | Command::Output
+                | Command::Input => {}
+            }
+        }
+
+        // Fail on the outermost loop that wasn't closed
+        active_loops.first().map_or(Ok(()), |position| {
+            Err(Error::UnmatchedStartOfLoop(Some(*position)))
+        })
+    }
+}
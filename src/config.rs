@@ -0,0 +1,107 @@
+use crate::cell::Cell;
+
+/// What happens when a cell is incremented above its maximum or decremented below 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// The value wraps around, like in most brainfuck implementations.
+    #[default]
+    Wrap,
+    /// The value stays at its maximum or 0.
+    Saturate,
+    /// The program stops with an error.
+    Trap,
+}
+
+impl Overflow {
+    /// Adds `value` to `cell`, returns `None` if the program should trap.
+    #[must_use]
+    pub fn add<C: Cell>(self, cell: C, value: C) -> Option<C> {
+        match self {
+            Self::Wrap => Some(cell.wrapping_add(value)),
+            Self::Saturate => Some(cell.saturating_add(value)),
+            Self::Trap => cell.checked_add(value),
+        }
+    }
+
+    /// Subtracts `value` from `cell`, returns `None` if the program should trap.
+    #[must_use]
+    pub fn subtract<C: Cell>(self, cell: C, value: C) -> Option<C> {
+        match self {
+            Self::Wrap => Some(cell.wrapping_sub(value)),
+            Self::Saturate => Some(cell.saturating_sub(value)),
+            Self::Trap => cell.checked_sub(value),
+        }
+    }
+
+    /// Multiplies `cell` by `factor`, returns `None` if the program should trap.
+    #[must_use]
+    pub fn multiply<C: Cell>(self, cell: C, factor: C) -> Option<C> {
+        match self {
+            Self::Wrap => Some(cell.wrapping_mul(factor)),
+            Self::Saturate => Some(cell.saturating_mul(factor)),
+            Self::Trap => cell.checked_mul(factor),
+        }
+    }
+}
+
+/// What the `,` command stores in the current cell once the input is exhausted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EndOfInput {
+    /// The cell is set to 0.
+    Zero,
+    /// The cell is set to -1, which is the maximum value of the cell (255 for 8-bit cells).
+    MinusOne,
+    /// The cell keeps its current value.
+    Unchanged,
+    /// The program stops with an error.
+    #[default]
+    Trap,
+}
+
+impl EndOfInput {
+    /// Returns the new value of `cell`, returns `None` if the program should trap.
+    #[must_use]
+    pub const fn value<C: Cell>(self, cell: C) -> Option<C> {
+        match self {
+            Self::Zero => Some(C::ZERO),
+            Self::MinusOne => Some(C::MAX),
+            Self::Unchanged => Some(cell),
+            Self::Trap => None,
+        }
+    }
+}
+
+/// What happens when the pointer moves past an edge of a bounded tape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Edge {
+    /// The program stops with an error.
+    #[default]
+    Trap,
+    /// The pointer continues at the other edge of the tape.
+    Wrap,
+}
+
+/// The size of the tape and what happens at its edges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TapeMode {
+    /// A tape with a fixed number of cells, at least 1.
+    Bounded { size: usize, edge: Edge },
+    /// A tape that starts at cell 0 and grows to the right, moving left of cell 0 is an error.
+    RightInfinite,
+    /// A tape that grows in both directions.
+    #[default]
+    BiInfinite,
+}
+
+impl TapeMode {
+    /// The number of cells of the tape in the original brainfuck implementation.
+    pub const CLASSIC_SIZE: usize = 30_000;
+}
+
+/// The runtime behavior of a program.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Config {
+    pub overflow: Overflow,
+    pub end_of_input: EndOfInput,
+    pub tape: TapeMode,
+}
@@ -0,0 +1,68 @@
+//! Backends that translate an optimized program into other languages, so it can be built into a
+//! native executable with their toolchains.
+//!
+//! The [`elf`] backend writes the executable directly instead, and the [`wasm`] and [`wat`]
+//! backends write a WebAssembly module for browsers and other runtimes to host.
+//!
+//! The [`c`] and [`rust`] backends write the helpers the commands call for the config, so the
+//! commands are translated the same way for every config.
+
+pub mod asm;
+pub mod c;
+pub mod elf;
+#[cfg(test)]
+mod harness;
+pub mod rust;
+pub mod wasm;
+pub mod wat;
+mod webassembly;
+#[expect(
+    clippy::pub_with_shorthand,
+    reason = "rustfmt writes `pub(in crate)` as `pub(crate)`"
+)]
+pub(crate) mod x86_64;
+
+use core::fmt::{self, Formatter};
+
+use crate::{cell::Cell, optimized_command::OptimizedCommand};
+
+/// Writes a line for every command with `write_command`, indented by 4 spaces for every loop it's
+/// in and the body of the function it's in.
+fn write_lines<C: Cell>(
+    f: &mut Formatter<'_>,
+    commands: &[OptimizedCommand<C>],
+    write_command: fn(&mut Formatter<'_>, usize, &OptimizedCommand<C>) -> fmt::Result,
+) -> fmt::Result {
+    let mut depth = 1_usize;
+    for (pc, command) in commands.iter().enumerate() {
+        if matches!(command, OptimizedCommand::EndOfLoop { .. }) {
+            depth -= 1;
+        }
+        write!(f, "{:width$}", "", width = depth * 4)?;
+        write_command(f, pc, command)?;
+        writeln!(f)?;
+        if matches!(command, OptimizedCommand::StartOfLoop { .. }) {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Returns how far right of the current cell the cell `offset` cells right of it is, on a tape of
+/// `cells` cells that wraps around.
+/// Every offset is the same as one to the right within the tape, so the backends only have to
+/// wrap moves at the end of the tape.
+const fn wrapped_offset(offset: isize, cells: usize) -> usize {
+    offset.rem_euclid(cells.cast_signed()).unsigned_abs()
+}
+
+/// Returns the bytes `cells` cells of `width` bytes take, if they're within the `reserved` bytes
+/// of the tape.
+/// Moving further can't end within the tape, so the backends fail at such moves without checking
+/// the pointer, which only matters if the command ever runs.
+const fn tape_bytes(cells: usize, width: usize, reserved: usize) -> Option<usize> {
+    match cells.checked_mul(width) {
+        Some(bytes) if bytes <= reserved => Some(bytes),
+        _ => None,
+    }
+}
@@ -0,0 +1,133 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::{cell::Cell, error::Error, optimized_program::OptimizedProgram};
+
+use super::x86_64::{Assembly, Symbol, BUFFER_SIZE};
+
+/// A brainfuck program translated into GNU assembler syntax for x86-64 Linux.
+///
+/// The program doesn't need libc, so it's built with
+/// `as -o program.o program.s && ld -o program program.o`.
+/// Tapes that grow reserve 256 MiB up front and report moving past it as an error instead.
+#[derive(Debug)]
+pub struct Source {
+    assembly: Assembly,
+}
+
+impl Source {
+    /// Translates `program`, which keeps its config.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TapeTooLarge`] if the bounded tape takes more than 256 MiB.
+    pub fn new<C: Cell>(program: &OptimizedProgram<C>) -> Result<Self, Error> {
+        Ok(Self {
+            assembly: Assembly::new(program)?,
+        })
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    .globl _start")?;
+        writeln!(f, "    .text")?;
+        writeln!(f, "_start:")?;
+        for instruction in &self.assembly.instructions {
+            writeln!(f, "{instruction}")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "    .section .rodata")?;
+        for (index, message) in self.assembly.messages.iter().enumerate() {
+            writeln!(f, "{}:", Symbol::Message(index))?;
+            f.write_str("    .ascii \"")?;
+            for byte in message {
+                match byte {
+                    b'"' | b'\\' => write!(f, "\\{}", char::from(*byte))?,
+                    b' '..=b'~' => write!(f, "{}", char::from(*byte))?,
+                    _ => write!(f, "\\{byte:03o}")?,
+                }
+            }
+            writeln!(f, "\"")?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "    .bss")?;
+        writeln!(f, "    .balign 4096")?;
+        for (symbol, bytes) in [
+            (Symbol::Tape, self.assembly.tape_bytes),
+            (Symbol::Buffer, BUFFER_SIZE),
+            (Symbol::InputByte, 1),
+        ] {
+            writeln!(f, "{symbol}:")?;
+            writeln!(f, "    .zero {bytes}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use crate::config::{Config, Edge, Overflow, TapeMode};
+
+    use super::{
+        super::harness::{self, config_program},
+        Source,
+    };
+
+    #[test]
+    fn config_in_source() {
+        let program = config_program::<u16>(Config {
+            overflow: Overflow::Saturate,
+            tape: TapeMode::Bounded {
+                size: 10,
+                edge: Edge::Wrap,
+            },
+            ..Config::default()
+        });
+        let source = Source::new(&program)
+            .expect("The tape is small")
+            .to_string();
+        assert!(source.contains("    movzwl (%rbx), %eax\n"));
+        assert!(source.contains("    add %ax, (%rcx)\n"));
+        assert!(source.contains("    sub $20, %rcx\n"));
+        assert!(source.contains("tape:\n    .zero 20\n"));
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn assembles_and_runs() {
+        harness::same_as_interpreter(|program, input| {
+            let path = harness::temp_path("asm");
+            let (source_path, object_path) = (path.with_extension("s"), path.with_extension("o"));
+            let source = Source::new(program).expect("The tape fits");
+            fs::write(&source_path, source.to_string()).expect("The file is writable");
+
+            // Only check the source if there are no binutils to build it with
+            let Ok(status) = Command::new("as")
+                .arg("-o")
+                .arg(&object_path)
+                .arg(&source_path)
+                .status()
+            else {
+                fs::remove_file(&source_path).expect("The source was written");
+                return Err("binutils");
+            };
+            assert!(status.success(), "The assembler rejected the source");
+            let linked = Command::new("ld")
+                .arg("-o")
+                .arg(&path)
+                .arg(&object_path)
+                .status()
+                .expect("The linker comes with the assembler");
+            assert!(linked.success(), "The linker rejected the object");
+            let result = harness::run_with_input(&mut Command::new(&path), input);
+            for file in [&source_path, &object_path, &path] {
+                fs::remove_file(file).expect("The file was written");
+            }
+            Ok(result)
+        });
+    }
+}
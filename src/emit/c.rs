@@ -0,0 +1,466 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::{
+    cell::Cell,
+    config::{Edge, EndOfInput, Overflow, TapeMode},
+    emit,
+    optimized_command::OptimizedCommand,
+    optimized_program::OptimizedProgram,
+};
+
+/// The includes and the functions that report errors with the messages of
+/// [`Error`](crate::error::Error).
+const HEADER: &str = r#"#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static inline void fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(EXIT_FAILURE);
+}
+
+static inline void fail_at(const char *message, size_t pc) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s at command %zu\n", message, pc);
+    exit(EXIT_FAILURE);
+}
+
+static inline void overflow(size_t pc, ptrdiff_t index) {
+    fflush(stdout);
+    fprintf(stderr, "error: Cell %td overflowed at command %zu\n", index, pc);
+    exit(EXIT_FAILURE);
+}
+"#;
+
+/// The tape, with `origin` as the index of the cell the pointer started at.
+/// Output is flushed after every line like in Rust, so programs that run for long show progress.
+const TAPE: &str = r#"
+static cell *cells;
+static size_t length;
+static size_t origin;
+static size_t pointer;
+
+static inline void start(size_t size) {
+    length = size;
+    origin = 0;
+    pointer = 0;
+    setvbuf(stdout, NULL, _IOLBF, BUFSIZ);
+    cells = calloc(length, sizeof(cell));
+    if (cells == NULL) {
+        fail("Out of memory");
+    }
+}
+"#;
+
+/// Grows the tape to at least twice its length, so it doesn't have to grow often.
+const GROW: &str = r#"
+static inline void grow_right(size_t index) {
+    size_t grown = index < length * 2 ? length * 2 : index + 1;
+    cells = realloc(cells, grown * sizeof(cell));
+    if (cells == NULL) {
+        fail("Out of memory");
+    }
+    memset(cells + length, 0, (grown - length) * sizeof(cell));
+    length = grown;
+}
+
+static inline void grow_left(size_t missing) {
+    size_t added = missing < length ? length : missing;
+    cell *grown = calloc(length + added, sizeof(cell));
+    if (grown == NULL) {
+        fail("Out of memory");
+    }
+    memcpy(grown + added, cells, length * sizeof(cell));
+    free(cells);
+    cells = grown;
+    length += added;
+    origin += added;
+    pointer += added;
+}
+"#;
+
+const RIGHT_GROWING: &str = "
+static inline size_t right(size_t pc, size_t amount) {
+    (void)pc;
+    if (amount >= length - pointer) {
+        grow_right(pointer + amount);
+    }
+    return pointer + amount;
+}
+";
+
+const RIGHT_TRAPPING: &str = r#"
+static inline size_t right(size_t pc, size_t amount) {
+    if (amount >= length - pointer) {
+        fail_at("Moved right of the end of the tape", pc);
+    }
+    return pointer + amount;
+}
+"#;
+
+const RIGHT_WRAPPING: &str = "
+static inline size_t right(size_t pc, size_t amount) {
+    (void)pc;
+    return (pointer + amount % length) % length;
+}
+";
+
+const LEFT_GROWING: &str = "
+static inline size_t left(size_t pc, size_t amount) {
+    (void)pc;
+    if (amount > pointer) {
+        grow_left(amount - pointer);
+    }
+    return pointer - amount;
+}
+";
+
+const LEFT_TRAPPING: &str = r#"
+static inline size_t left(size_t pc, size_t amount) {
+    if (amount > pointer) {
+        fail_at("Moved left of the start of the tape", pc);
+    }
+    return pointer - amount;
+}
+"#;
+
+const LEFT_WRAPPING: &str = "
+static inline size_t left(size_t pc, size_t amount) {
+    (void)pc;
+    return (pointer + length - amount % length) % length;
+}
+";
+
+/// Returns the cell `offset` cells right of the current cell, moving there and back like a real
+/// move would.
+const AT: &str = "
+static inline cell *at(size_t pc, ptrdiff_t offset) {
+    size_t index = offset < 0 ? left(pc, (size_t)0 - (size_t)offset) : right(pc, (size_t)offset);
+    return &cells[index];
+}
+";
+
+const CHANGE_WRAPPING: &str = "
+static inline void add(size_t pc, ptrdiff_t offset, cell value) {
+    cell *target = at(pc, offset);
+    *target = (cell)(*target + value);
+}
+
+static inline void subtract(size_t pc, ptrdiff_t offset, cell value) {
+    cell *target = at(pc, offset);
+    *target = (cell)(*target - value);
+}
+
+static inline void multiply_add(size_t pc, ptrdiff_t offset, cell factor) {
+    cell value = cells[pointer];
+    if (value != 0) {
+        add(pc, offset, (cell)((uint64_t)value * factor));
+    }
+}
+
+static inline void multiply_subtract(size_t pc, ptrdiff_t offset, cell factor) {
+    cell value = cells[pointer];
+    if (value != 0) {
+        subtract(pc, offset, (cell)((uint64_t)value * factor));
+    }
+}
+";
+
+const CHANGE_SATURATING: &str = "
+static inline void add(size_t pc, ptrdiff_t offset, cell value) {
+    cell *target = at(pc, offset);
+    *target = *target > CELL_MAX - value ? CELL_MAX : (cell)(*target + value);
+}
+
+static inline void subtract(size_t pc, ptrdiff_t offset, cell value) {
+    cell *target = at(pc, offset);
+    *target = *target < value ? 0 : (cell)(*target - value);
+}
+
+static inline cell product(cell value, cell factor) {
+    return factor != 0 && value > CELL_MAX / factor ? CELL_MAX : (cell)((uint64_t)value * factor);
+}
+
+static inline void multiply_add(size_t pc, ptrdiff_t offset, cell factor) {
+    cell value = cells[pointer];
+    if (value != 0) {
+        add(pc, offset, product(value, factor));
+    }
+}
+
+static inline void multiply_subtract(size_t pc, ptrdiff_t offset, cell factor) {
+    cell value = cells[pointer];
+    if (value != 0) {
+        subtract(pc, offset, product(value, factor));
+    }
+}
+";
+
+/// Changes cells like [`CHANGE_SATURATING`], but reports overflows instead.
+/// The target cell is looked up first, so leaving the tape is reported before an overflow.
+const CHANGE_TRAPPING: &str = "
+static inline void add(size_t pc, ptrdiff_t offset, cell value) {
+    cell *target = at(pc, offset);
+    ptrdiff_t index = (ptrdiff_t)((size_t)(target - cells) - origin);
+    if (*target > CELL_MAX - value) {
+        overflow(pc, index);
+    }
+    *target = (cell)(*target + value);
+}
+
+static inline void subtract(size_t pc, ptrdiff_t offset, cell value) {
+    cell *target = at(pc, offset);
+    ptrdiff_t index = (ptrdiff_t)((size_t)(target - cells) - origin);
+    if (*target < value) {
+        overflow(pc, index);
+    }
+    *target = (cell)(*target - value);
+}
+
+static inline void multiply_add(size_t pc, ptrdiff_t offset, cell factor) {
+    cell value = cells[pointer];
+    if (value == 0) {
+        return;
+    }
+    cell *target = at(pc, offset);
+    ptrdiff_t index = (ptrdiff_t)((size_t)(target - cells) - origin);
+    cell product = (cell)((uint64_t)value * factor);
+    if ((factor != 0 && value > CELL_MAX / factor) || *target > CELL_MAX - product) {
+        overflow(pc, index);
+    }
+    *target = (cell)(*target + product);
+}
+
+static inline void multiply_subtract(size_t pc, ptrdiff_t offset, cell factor) {
+    cell value = cells[pointer];
+    if (value == 0) {
+        return;
+    }
+    cell *target = at(pc, offset);
+    ptrdiff_t index = (ptrdiff_t)((size_t)(target - cells) - origin);
+    cell product = (cell)((uint64_t)value * factor);
+    if ((factor != 0 && value > CELL_MAX / factor) || *target < product) {
+        overflow(pc, index);
+    }
+    *target = (cell)(*target - product);
+}
+";
+
+const OUTPUT: &str = "
+static inline void output(cell value) {
+    putchar((unsigned char)value);
+}
+";
+
+/// A brainfuck program translated into a self-contained C program.
+///
+/// The C program keeps the cells in an array that grows, traps or wraps like the configured
+/// [`TapeMode`], reads bytes with `getchar` and writes them with `putchar`.
+/// Errors are reported with the same messages as the interpreters before exiting with a failure.
+#[derive(Debug)]
+pub struct Source<'program, C: Cell> {
+    program: &'program OptimizedProgram<C>,
+}
+
+impl<'program, C: Cell> Source<'program, C> {
+    /// Translates `program`, which keeps its config.
+    #[must_use]
+    pub const fn new(program: &'program OptimizedProgram<C>) -> Self {
+        Self { program }
+    }
+
+    /// Writes the functions that move the pointer and change cells, as configured.
+    fn write_runtime(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let config = self.program.config();
+        f.write_str(TAPE)?;
+        let (right, left) = match config.tape {
+            TapeMode::BiInfinite => (RIGHT_GROWING, LEFT_GROWING),
+            TapeMode::RightInfinite => (RIGHT_GROWING, LEFT_TRAPPING),
+            TapeMode::Bounded {
+                edge: Edge::Trap, ..
+            } => (RIGHT_TRAPPING, LEFT_TRAPPING),
+            TapeMode::Bounded {
+                edge: Edge::Wrap, ..
+            } => (RIGHT_WRAPPING, LEFT_WRAPPING),
+        };
+        if matches!(config.tape, TapeMode::BiInfinite | TapeMode::RightInfinite) {
+            f.write_str(GROW)?;
+        }
+        f.write_str(right)?;
+        f.write_str(left)?;
+        f.write_str(AT)?;
+        f.write_str(match config.overflow {
+            Overflow::Wrap => CHANGE_WRAPPING,
+            Overflow::Saturate => CHANGE_SATURATING,
+            Overflow::Trap => CHANGE_TRAPPING,
+        })?;
+
+        let end_of_input = match config.end_of_input {
+            EndOfInput::Zero => "cells[pointer] = 0;",
+            EndOfInput::MinusOne => "cells[pointer] = CELL_MAX;",
+            EndOfInput::Unchanged => "return;",
+            EndOfInput::Trap => "fail(\"Unexpected end of input\");",
+        };
+        writeln!(f)?;
+        writeln!(f, "static inline void input(void) {{")?;
+        writeln!(f, "    int byte = getchar();")?;
+        writeln!(f, "    if (byte == EOF) {{")?;
+        writeln!(f, "        {end_of_input}")?;
+        writeln!(f, "    }} else {{")?;
+        writeln!(f, "        cells[pointer] = (cell)byte;")?;
+        writeln!(f, "    }}")?;
+        writeln!(f, "}}")?;
+        f.write_str(OUTPUT)
+    }
+
+    /// Writes the statement for the command at `pc`, which isn't the start or end of a loop.
+    fn write_command(
+        f: &mut Formatter<'_>,
+        pc: usize,
+        command: &OptimizedCommand<C>,
+    ) -> fmt::Result {
+        match command {
+            OptimizedCommand::AddPointer(amount) => write!(f, "pointer = right({pc}, {amount}u);"),
+            OptimizedCommand::SubtractPointer(amount) => {
+                write!(f, "pointer = left({pc}, {amount}u);")
+            }
+            OptimizedCommand::AddValue(value) => write!(f, "add({pc}, 0, {}u);", value.to_u64()),
+            OptimizedCommand::SubtractValue(value) => {
+                write!(f, "subtract({pc}, 0, {}u);", value.to_u64())
+            }
+            OptimizedCommand::SetValue(value) => {
+                write!(f, "cells[pointer] = {}u;", value.to_u64())
+            }
+            OptimizedCommand::MultiplyAdd { offset, factor } => {
+                write!(f, "multiply_add({pc}, {offset}, {}u);", factor.to_u64())
+            }
+            OptimizedCommand::MultiplySubtract { offset, factor } => {
+                write!(
+                    f,
+                    "multiply_subtract({pc}, {offset}, {}u);",
+                    factor.to_u64()
+                )
+            }
+            OptimizedCommand::ScanRight(stride) => {
+                write!(
+                    f,
+                    "while (cells[pointer] != 0) pointer = right({pc}, {stride}u);"
+                )
+            }
+            OptimizedCommand::ScanLeft(stride) => {
+                write!(
+                    f,
+                    "while (cells[pointer] != 0) pointer = left({pc}, {stride}u);"
+                )
+            }
+            OptimizedCommand::AddAt { offset, value } => {
+                write!(f, "add({pc}, {offset}, {}u);", value.to_u64())
+            }
+            OptimizedCommand::SubtractAt { offset, value } => {
+                write!(f, "subtract({pc}, {offset}, {}u);", value.to_u64())
+            }
+            OptimizedCommand::SetAt { offset, value } => {
+                write!(f, "*at({pc}, {offset}) = {}u;", value.to_u64())
+            }
+            OptimizedCommand::OutputAt { offset } => write!(f, "output(*at({pc}, {offset}));"),
+            OptimizedCommand::Output => write!(f, "output(cells[pointer]);"),
+            OptimizedCommand::OutputBytes(bytes) => {
+                f.write_str("fwrite(\"")?;
+                for byte in bytes {
+                    // Octal escapes always end after 3 digits, unlike hexadecimal ones
+                    match byte {
+                        b'"' | b'\\' | b'?' => write!(f, "\\{}", char::from(*byte))?,
+                        b' '..=b'~' => write!(f, "{}", char::from(*byte))?,
+                        _ => write!(f, "\\{byte:03o}")?,
+                    }
+                }
+                write!(f, "\", 1, {}u, stdout);", bytes.len())
+            }
+            OptimizedCommand::Input => write!(f, "input();"),
+            OptimizedCommand::StartOfLoop { .. } => write!(f, "while (cells[pointer] != 0) {{"),
+            OptimizedCommand::EndOfLoop { .. } => write!(f, "}}"),
+        }
+    }
+}
+
+impl<C: Cell> Display for Source<'_, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(HEADER)?;
+        writeln!(f)?;
+        writeln!(f, "typedef uint{}_t cell;", C::BITS)?;
+        writeln!(f, "#define CELL_MAX UINT{}_MAX", C::BITS)?;
+        self.write_runtime(f)?;
+
+        let size = match self.program.config().tape {
+            TapeMode::Bounded { size, .. } => size.max(1),
+            TapeMode::RightInfinite | TapeMode::BiInfinite => 1,
+        };
+        writeln!(f)?;
+        writeln!(f, "int main(void) {{")?;
+        writeln!(f, "    start({size}u);")?;
+        emit::write_lines(f, self.program.commands(), Self::write_command)?;
+        writeln!(f, "    free(cells);")?;
+        writeln!(
+            f,
+            "    return fflush(stdout) == 0 ? EXIT_SUCCESS : EXIT_FAILURE;"
+        )?;
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use crate::config::{Config, EndOfInput, Overflow};
+
+    use super::{
+        super::harness::{self, config_program},
+        Source,
+    };
+
+    #[test]
+    fn config_in_source() {
+        let program = config_program::<u16>(Config {
+            overflow: Overflow::Trap,
+            end_of_input: EndOfInput::MinusOne,
+            ..Config::default()
+        });
+        let source = Source::new(&program).to_string();
+        assert!(source.contains("typedef uint16_t cell;"));
+        assert!(source.contains("cells[pointer] = CELL_MAX;"));
+        assert!(source.contains("overflow(pc, index);"));
+        assert!(source.contains("    input();\n    multiply_add(1, 1, 1u);\n"));
+    }
+
+    #[test]
+    fn compiles_and_runs() {
+        harness::same_as_interpreter(|program, input| {
+            let path = harness::temp_path("c");
+            let source_path = path.with_extension("c");
+            fs::write(&source_path, Source::new(program).to_string())
+                .expect("The file is writable");
+
+            // Only check the source if there is no C compiler to build it with
+            let Ok(status) = Command::new("cc")
+                .arg("-Wall")
+                .arg("-Werror")
+                .arg("-o")
+                .arg(&path)
+                .arg(&source_path)
+                .status()
+            else {
+                fs::remove_file(&source_path).expect("The source was written");
+                return Err("cc");
+            };
+            assert!(status.success(), "The C compiler rejected the source");
+            let result = harness::run_with_input(&mut Command::new(&path), input);
+            fs::remove_file(&source_path).expect("The source was written");
+            fs::remove_file(&path).expect("The program was built");
+            Ok(result)
+        });
+    }
+}
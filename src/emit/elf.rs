@@ -0,0 +1,150 @@
+use crate::{cell::Cell, error::Error, optimized_program::OptimizedProgram};
+
+use super::x86_64::{Assembly, Symbol, BUFFER_SIZE};
+
+/// The address the executable is loaded at, which is where `ld` puts executables too.
+const BASE_ADDRESS: u64 = 0x40_0000;
+
+/// The size of the ELF header.
+const HEADER_SIZE: u16 = 64;
+
+/// The size of a program header.
+const PROGRAM_HEADER_SIZE: u16 = 56;
+
+/// The alignment of segments, which is the size of a page.
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Appends a program header for a loadable segment.
+#[expect(
+    clippy::little_endian_bytes,
+    reason = "The ELF header says the file is little endian"
+)]
+fn segment(file: &mut Vec<u8>, flags: u32, address: u64, file_size: u64, memory_size: u64) {
+    file.extend_from_slice(&1_u32.to_le_bytes());
+    file.extend_from_slice(&flags.to_le_bytes());
+    // Every segment starts at the start of the file, which is page aligned like its address
+    file.extend_from_slice(&0_u64.to_le_bytes());
+    file.extend_from_slice(&address.to_le_bytes());
+    file.extend_from_slice(&address.to_le_bytes());
+    file.extend_from_slice(&file_size.to_le_bytes());
+    file.extend_from_slice(&memory_size.to_le_bytes());
+    file.extend_from_slice(&PAGE_SIZE.to_le_bytes());
+}
+
+/// Translates `program` into a static ELF64 executable for x86-64 Linux, which keeps its config.
+///
+/// The executable is the code of [`asm::Source`](super::asm::Source) and its messages in 1
+/// segment, with a second segment for the zeroed tape and output buffer.
+/// It doesn't need libc or any other file to run.
+///
+/// # Errors
+///
+/// Returns [`Error::TapeTooLarge`] if the bounded tape takes more than 256 MiB.
+#[expect(
+    clippy::little_endian_bytes,
+    reason = "The ELF header says the file is little endian"
+)]
+pub fn executable<C: Cell>(program: &OptimizedProgram<C>) -> Result<Vec<u8>, Error> {
+    let assembly = Assembly::new(program)?;
+    let to_u64 = |size: usize| u64::try_from(size).unwrap_or(u64::MAX);
+
+    // The code follows the headers and the messages follow the code, so find the code's length
+    let headers = u64::from(HEADER_SIZE) + 2 * u64::from(PROGRAM_HEADER_SIZE);
+    let entry = BASE_ADDRESS + headers;
+    let code_size = to_u64(assembly.encode(entry, &|_| entry).len());
+    let mut message_addresses = Vec::new();
+    let mut end = entry + code_size;
+    for message in &assembly.messages {
+        message_addresses.push(end);
+        end += to_u64(message.len());
+    }
+    let data = end.next_multiple_of(PAGE_SIZE);
+    let buffer = data + to_u64(assembly.tape_bytes);
+    let data_size = to_u64(assembly.tape_bytes + BUFFER_SIZE + 1);
+    let code = assembly.encode(entry, &|symbol| match symbol {
+        Symbol::Tape => data,
+        Symbol::Buffer => buffer,
+        Symbol::InputByte => buffer + to_u64(BUFFER_SIZE),
+        Symbol::Message(index) => message_addresses[index],
+    });
+
+    let mut file = Vec::new();
+    file.extend_from_slice(b"\x7FELF");
+    // 64-bit, little endian, version 1 and the System V ABI
+    file.extend_from_slice(&[2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    // An executable for x86-64
+    file.extend_from_slice(&2_u16.to_le_bytes());
+    file.extend_from_slice(&0x3E_u16.to_le_bytes());
+    file.extend_from_slice(&1_u32.to_le_bytes());
+    file.extend_from_slice(&entry.to_le_bytes());
+    // The program headers follow the ELF header and there are no section headers
+    file.extend_from_slice(&u64::from(HEADER_SIZE).to_le_bytes());
+    file.extend_from_slice(&0_u64.to_le_bytes());
+    file.extend_from_slice(&0_u32.to_le_bytes());
+    file.extend_from_slice(&HEADER_SIZE.to_le_bytes());
+    file.extend_from_slice(&PROGRAM_HEADER_SIZE.to_le_bytes());
+    file.extend_from_slice(&2_u16.to_le_bytes());
+    file.extend_from_slice(&[0; 6]);
+
+    // The code is readable and executable, the data is readable and writable
+    let file_size = end - BASE_ADDRESS;
+    segment(&mut file, 0b101, BASE_ADDRESS, file_size, file_size);
+    segment(&mut file, 0b110, data, 0, data_size);
+    file.extend_from_slice(&code);
+    for message in &assembly.messages {
+        file.extend_from_slice(message);
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt as _, process::Command};
+
+    use crate::{
+        cell::Cell, compile_optimized, config::Config, optimized_program::OptimizedProgram, parse,
+    };
+
+    use super::{super::harness, executable};
+
+    /// Runs `program` as an executable with `input`, returning its output and errors.
+    fn run<C: Cell>(program: &OptimizedProgram<C>, input: &[u8]) -> (Vec<u8>, String) {
+        let path = harness::temp_path("elf");
+        fs::write(&path, executable(program).expect("The tape fits"))
+            .expect("The file is writable");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .expect("The file was written");
+        let result = harness::run_with_input(&mut Command::new(&path), input);
+        fs::remove_file(&path).expect("The file was written");
+        result
+    }
+
+    #[test]
+    fn header() {
+        let program = compile_optimized::<u8, _>(parse(*b"+."), Config::default())
+            .expect("The loops are balanced");
+        let file = executable(&program).expect("The tape fits");
+        assert_eq!(
+            file[..20],
+            *b"\x7FELF\x02\x01\x01\0\0\0\0\0\0\0\0\0\x02\0\x3E\0"
+        );
+        assert!(file.len() < 1024);
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn same_as_interpreter() {
+        harness::same_as_interpreter(|program, input| Ok(run(program, input)));
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn reserved_tape() {
+        let program = compile_optimized::<u32, _>(parse(*b"-[>-]"), Config::default())
+            .expect("The loops are balanced");
+        assert_eq!(
+            run(&program, b"").1,
+            "error: Moved past the 67108864 cells reserved for the tape at command 2\n"
+        );
+    }
+}
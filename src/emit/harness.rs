@@ -0,0 +1,156 @@
+//! The fixtures the backends are tested with: a program to check how the config shows in the
+//! translation, and a table of programs each backend builds and runs like the interpreter.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    env,
+    io::{self, Write as _},
+    path::PathBuf,
+    process::{self, Command, Stdio},
+};
+
+use crate::{
+    cell::Cell,
+    compile_optimized,
+    config::{Config, Edge, EndOfInput, Overflow, TapeMode},
+    optimized_program::OptimizedProgram,
+    parse,
+};
+
+/// Reads input until it ends, writing it back.
+const ECHOING: Config = Config {
+    overflow: Overflow::Wrap,
+    end_of_input: EndOfInput::Zero,
+    tape: TapeMode::BiInfinite,
+};
+
+/// Stops at the first cell that overflows, input that ends or move left of the tape.
+const TRAPPING: Config = Config {
+    overflow: Overflow::Trap,
+    end_of_input: EndOfInput::Trap,
+    tape: TapeMode::RightInfinite,
+};
+
+/// Keeps cells at their limits, on a small tape that wraps around.
+const SATURATING: Config = Config {
+    overflow: Overflow::Saturate,
+    end_of_input: EndOfInput::Trap,
+    tape: TapeMode::Bounded {
+        size: 5,
+        edge: Edge::Wrap,
+    },
+};
+
+/// Programs with their config and input, which every backend runs like the interpreter.
+/// The first ones write "He" before echoing the input or failing.
+const PROGRAMS: [(&[u8], Config, &[u8]); 10] = [
+    (
+        b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.,[.,]",
+        ECHOING,
+        b"\"Quoted?\"\n",
+    ),
+    (
+        b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.,[.,]>>>>[-]-",
+        Config {
+            overflow: Overflow::Trap,
+            ..ECHOING
+        },
+        b"\"Quoted\"\n",
+    ),
+    (
+        b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.<<<<<<<",
+        TRAPPING,
+        b"",
+    ),
+    (b",[.,]<<+[>]++++++++++.", ECHOING, b"echo"),
+    (b"+.>-", TRAPPING, b""),
+    (b".,", TRAPPING, b""),
+    (b"+[>+<-]>[<<+>>-]", TRAPPING, b""),
+    (b"-->+++[<<->>-]<<[>+<-]>.", SATURATING, b""),
+    (
+        b"<->",
+        Config {
+            overflow: Overflow::Trap,
+            ..SATURATING
+        },
+        b"",
+    ),
+    (
+        b"+[<<-->>-]",
+        Config {
+            overflow: Overflow::Trap,
+            ..SATURATING
+        },
+        b"",
+    ),
+];
+
+/// Compiles the program the backends translate to check how `config` shows in the source, which
+/// reads, multiplies and writes.
+pub fn config_program<C: Cell>(config: Config) -> OptimizedProgram<C> {
+    compile_optimized(parse(*b",[->+<]>."), config).expect("The loops are balanced")
+}
+
+/// Checks that every program in [`PROGRAMS`] runs like in the interpreter when `run` builds and
+/// runs it with the input, returning its output and errors.
+/// `run` returns the name of the tools to build or run the program with if they aren't
+/// installed, which skips the check with a notice.
+pub fn same_as_interpreter(
+    mut run: impl FnMut(&OptimizedProgram<u8>, &[u8]) -> Result<(Vec<u8>, String), &'static str>,
+) {
+    for (source, config, input) in PROGRAMS {
+        let mut program = compile_optimized::<u8, _>(parse(source.iter().copied()), config)
+            .expect("The loops are balanced");
+        let actual = match run(&program, input) {
+            Ok(actual) => actual,
+            Err(tools) => {
+                // The test harness only captures the print macros, so the notice always shows
+                writeln!(io::stderr(), "skipped: no {tools} installed")
+                    .expect("The notice is written");
+                return;
+            }
+        };
+        let mut output = Vec::new();
+        let error = program
+            .execute(input, &mut output)
+            .map_or_else(|error| format!("error: {error}\n"), |()| String::new());
+        assert_eq!(
+            actual,
+            (output, error),
+            "{}",
+            String::from_utf8_lossy(source)
+        );
+    }
+}
+
+/// Returns a path in the temporary directory that no other test uses, to build the program of
+/// `backend` at.
+pub fn temp_path(backend: &str) -> PathBuf {
+    static PATHS: AtomicUsize = AtomicUsize::new(0);
+    env::temp_dir().join(format!(
+        "brainfuck-{backend}-{}-{}",
+        process::id(),
+        PATHS.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Runs `command` with `input`, returning its output and errors.
+pub fn run_with_input(command: &mut Command, input: &[u8]) -> (Vec<u8>, String) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("The program runs");
+    child
+        .stdin
+        .take()
+        .expect("The input is piped")
+        .write_all(input)
+        .expect("The program reads its input");
+    let output = child.wait_with_output().expect("The program finishes");
+    (
+        output.stdout,
+        String::from_utf8(output.stderr).expect("Errors are text"),
+    )
+}
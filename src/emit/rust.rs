@@ -0,0 +1,431 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::{
+    cell::Cell,
+    config::{Edge, EndOfInput, Overflow, TapeMode},
+    emit,
+    optimized_command::OptimizedCommand,
+    optimized_program::OptimizedProgram,
+};
+
+/// The imports and the error, which carries the message of [`Error`](crate::error::Error).
+const HEADER: &str = r#"//! A brainfuck program translated into Rust.
+
+// Not every program uses every part of the tape
+#![allow(unused)]
+
+use std::{
+    io::{self, Read, Write},
+    process::ExitCode,
+};
+
+/// An error that stops the program.
+struct Error(String);
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self(format!("I/O error: {error}"))
+    }
+}
+"#;
+
+/// The tape, with `origin` as the index of the cell the pointer started at.
+const TAPE: &str = "
+/// The cells of the program and the pointer to the current cell.
+struct Tape {
+    cells: Vec<Cell>,
+    origin: usize,
+    pointer: usize,
+}
+
+impl Tape {
+    fn new(size: usize) -> Self {
+        Self {
+            cells: vec![0; size],
+            origin: 0,
+            pointer: 0,
+        }
+    }
+
+    fn get(&self) -> Cell {
+        self.cells[self.pointer]
+    }
+
+    fn set(&mut self, value: Cell) {
+        self.cells[self.pointer] = value;
+    }
+";
+
+const RIGHT_GROWING: &str = "
+    fn right(&mut self, pc: usize, amount: usize) -> Result<usize, Error> {
+        let index = self.pointer + amount;
+        if index >= self.cells.len() {
+            self.cells.resize(index + 1, 0);
+        }
+        Ok(index)
+    }
+";
+
+const RIGHT_TRAPPING: &str = r#"
+    fn right(&mut self, pc: usize, amount: usize) -> Result<usize, Error> {
+        if amount >= self.cells.len() - self.pointer {
+            return Err(Error(format!("Moved right of the end of the tape at command {pc}")));
+        }
+        Ok(self.pointer + amount)
+    }
+"#;
+
+const RIGHT_WRAPPING: &str = "
+    fn right(&mut self, pc: usize, amount: usize) -> Result<usize, Error> {
+        Ok((self.pointer + amount % self.cells.len()) % self.cells.len())
+    }
+";
+
+/// Grows the tape by at least its length, so it doesn't have to grow often.
+const LEFT_GROWING: &str = "
+    fn left(&mut self, pc: usize, amount: usize) -> Result<usize, Error> {
+        if amount > self.pointer {
+            let added = (amount - self.pointer).max(self.cells.len());
+            let mut cells = vec![0; added];
+            cells.append(&mut self.cells);
+            self.cells = cells;
+            self.origin += added;
+            self.pointer += added;
+        }
+        Ok(self.pointer - amount)
+    }
+";
+
+const LEFT_TRAPPING: &str = r#"
+    fn left(&mut self, pc: usize, amount: usize) -> Result<usize, Error> {
+        if amount > self.pointer {
+            return Err(Error(format!("Moved left of the start of the tape at command {pc}")));
+        }
+        Ok(self.pointer - amount)
+    }
+"#;
+
+const LEFT_WRAPPING: &str = "
+    fn left(&mut self, pc: usize, amount: usize) -> Result<usize, Error> {
+        let length = self.cells.len();
+        Ok((self.pointer + length - amount % length) % length)
+    }
+";
+
+/// Accesses cells at offsets by moving there and back like a real move would, and scans a cell at
+/// a time.
+const CELLS: &str = r#"
+    fn at(&mut self, pc: usize, offset: isize) -> Result<usize, Error> {
+        if offset < 0 {
+            self.left(pc, offset.unsigned_abs())
+        } else {
+            self.right(pc, offset.unsigned_abs())
+        }
+    }
+
+    fn get_at(&mut self, pc: usize, offset: isize) -> Result<Cell, Error> {
+        let index = self.at(pc, offset)?;
+        Ok(self.cells[index])
+    }
+
+    fn set_at(&mut self, pc: usize, offset: isize, value: Cell) -> Result<(), Error> {
+        let index = self.at(pc, offset)?;
+        self.cells[index] = value;
+        Ok(())
+    }
+
+    fn scan_right(&mut self, pc: usize, stride: usize) -> Result<(), Error> {
+        while self.get() != 0 {
+            self.pointer = self.right(pc, stride)?;
+        }
+        Ok(())
+    }
+
+    fn scan_left(&mut self, pc: usize, stride: usize) -> Result<(), Error> {
+        while self.get() != 0 {
+            self.pointer = self.left(pc, stride)?;
+        }
+        Ok(())
+    }
+
+    /// Changes the cell `offset` cells right of the current cell, `change` returns `None` if it
+    /// overflows.
+    fn change(
+        &mut self,
+        pc: usize,
+        offset: isize,
+        change: impl FnOnce(Cell) -> Option<Cell>,
+    ) -> Result<(), Error> {
+        let index = self.at(pc, offset)?;
+        let cell = index.wrapping_sub(self.origin) as isize;
+        self.cells[index] = change(self.cells[index])
+            .ok_or_else(|| Error(format!("Cell {cell} overflowed at command {pc}")))?;
+        Ok(())
+    }
+"#;
+
+/// The main function, which runs the program and reports its errors.
+const MAIN: &str = r#"
+fn main() -> ExitCode {
+    let (mut input, mut output) = (io::stdin().lock(), io::stdout().lock());
+    if let Err(Error(message)) = run(&mut input, &mut output) {
+        eprintln!("error: {message}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+"#;
+
+/// A brainfuck program translated into a standalone Rust program, which only needs the standard
+/// library.
+///
+/// The Rust program keeps the cells in a vector that grows, traps or wraps like the configured
+/// [`TapeMode`], and reads and writes bytes through the locked standard input and output like the
+/// interpreters do.
+/// Errors are reported with the same messages as the interpreters before exiting with a failure.
+#[derive(Debug)]
+pub struct Source<'program, C: Cell> {
+    program: &'program OptimizedProgram<C>,
+}
+
+impl<'program, C: Cell> Source<'program, C> {
+    /// Translates `program`, which keeps its config.
+    #[must_use]
+    pub const fn new(program: &'program OptimizedProgram<C>) -> Self {
+        Self { program }
+    }
+
+    /// Writes the methods of the tape that change cells and read input, as configured.
+    fn write_changes(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Every change is checked, but only trapping ones fail
+        let config = self.program.config();
+        let (method, checked) = match config.overflow {
+            Overflow::Wrap => ("wrapping", false),
+            Overflow::Saturate => ("saturating", false),
+            Overflow::Trap => ("checked", true),
+        };
+        let change = |operation: &str, value: &str| {
+            if checked {
+                format!("cell.{method}_{operation}({value})")
+            } else {
+                format!("Some(cell.{method}_{operation}({value}))")
+            }
+        };
+        let product = if checked {
+            format!("value.{method}_mul(factor)")
+        } else {
+            format!("Some(value.{method}_mul(factor))")
+        };
+        for (name, operation) in [("add", "add"), ("subtract", "sub")] {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "    fn {name}(&mut self, pc: usize, offset: isize, value: Cell) -> Result<(), Error> {{"
+            )?;
+            writeln!(
+                f,
+                "        self.change(pc, offset, |cell| {})",
+                change(operation, "value")
+            )?;
+            writeln!(f, "    }}")?;
+            writeln!(f)?;
+            writeln!(
+                f,
+                "    fn multiply_{name}(&mut self, pc: usize, offset: isize, factor: Cell) -> Result<(), Error> {{"
+            )?;
+            writeln!(f, "        let value = self.get();")?;
+            writeln!(f, "        if value == 0 {{")?;
+            writeln!(f, "            return Ok(());")?;
+            writeln!(f, "        }}")?;
+            writeln!(f, "        self.change(pc, offset, |cell| {{")?;
+            writeln!(
+                f,
+                "            {product}.and_then(|product| {})",
+                change(operation, "product")
+            )?;
+            writeln!(f, "        }})")?;
+            writeln!(f, "    }}")?;
+        }
+
+        let end_of_input = match config.end_of_input {
+            EndOfInput::Zero => "self.set(0)",
+            EndOfInput::MinusOne => "self.set(Cell::MAX)",
+            EndOfInput::Unchanged => "{}",
+            EndOfInput::Trap => "return Err(Error(String::from(\"Unexpected end of input\")))",
+        };
+        writeln!(f)?;
+        writeln!(
+            f,
+            "    fn input(&mut self, input: &mut impl Read) -> Result<(), Error> {{"
+        )?;
+        writeln!(f, "        let mut byte = [0];")?;
+        writeln!(f, "        match input.read_exact(&mut byte) {{")?;
+        writeln!(f, "            Ok(()) => self.set(Cell::from(byte[0])),")?;
+        writeln!(
+            f,
+            "            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {end_of_input},"
+        )?;
+        writeln!(f, "            Err(error) => return Err(error.into()),")?;
+        writeln!(f, "        }}")?;
+        writeln!(f, "        Ok(())")?;
+        writeln!(f, "    }}")
+    }
+
+    /// Writes the statement for the command at `pc`.
+    fn write_command(
+        f: &mut Formatter<'_>,
+        pc: usize,
+        command: &OptimizedCommand<C>,
+    ) -> fmt::Result {
+        match command {
+            OptimizedCommand::AddPointer(amount) => {
+                write!(f, "tape.pointer = tape.right({pc}, {amount})?;")
+            }
+            OptimizedCommand::SubtractPointer(amount) => {
+                write!(f, "tape.pointer = tape.left({pc}, {amount})?;")
+            }
+            OptimizedCommand::AddValue(value) => {
+                write!(f, "tape.add({pc}, 0, {})?;", value.to_u64())
+            }
+            OptimizedCommand::SubtractValue(value) => {
+                write!(f, "tape.subtract({pc}, 0, {})?;", value.to_u64())
+            }
+            OptimizedCommand::SetValue(value) => write!(f, "tape.set({});", value.to_u64()),
+            OptimizedCommand::MultiplyAdd { offset, factor } => {
+                write!(
+                    f,
+                    "tape.multiply_add({pc}, {offset}, {})?;",
+                    factor.to_u64()
+                )
+            }
+            OptimizedCommand::MultiplySubtract { offset, factor } => {
+                write!(
+                    f,
+                    "tape.multiply_subtract({pc}, {offset}, {})?;",
+                    factor.to_u64()
+                )
+            }
+            OptimizedCommand::ScanRight(stride) => write!(f, "tape.scan_right({pc}, {stride})?;"),
+            OptimizedCommand::ScanLeft(stride) => write!(f, "tape.scan_left({pc}, {stride})?;"),
+            OptimizedCommand::AddAt { offset, value } => {
+                write!(f, "tape.add({pc}, {offset}, {})?;", value.to_u64())
+            }
+            OptimizedCommand::SubtractAt { offset, value } => {
+                write!(f, "tape.subtract({pc}, {offset}, {})?;", value.to_u64())
+            }
+            OptimizedCommand::SetAt { offset, value } => {
+                write!(f, "tape.set_at({pc}, {offset}, {})?;", value.to_u64())
+            }
+            OptimizedCommand::OutputAt { offset } => {
+                write!(
+                    f,
+                    "output.write_all(&[tape.get_at({pc}, {offset})? as u8])?;"
+                )
+            }
+            OptimizedCommand::Output => write!(f, "output.write_all(&[tape.get() as u8])?;"),
+            OptimizedCommand::OutputBytes(bytes) => {
+                f.write_str("output.write_all(b\"")?;
+                for byte in bytes {
+                    write!(f, "{}", byte.escape_ascii())?;
+                }
+                f.write_str("\")?;")
+            }
+            OptimizedCommand::Input => write!(f, "tape.input(input)?;"),
+            OptimizedCommand::StartOfLoop { .. } => write!(f, "while tape.get() != 0 {{"),
+            OptimizedCommand::EndOfLoop { .. } => write!(f, "}}"),
+        }
+    }
+}
+
+impl<C: Cell> Display for Source<'_, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(HEADER)?;
+        writeln!(f)?;
+        writeln!(f, "type Cell = u{};", C::BITS)?;
+        f.write_str(TAPE)?;
+        let (right, left) = match self.program.config().tape {
+            TapeMode::BiInfinite => (RIGHT_GROWING, LEFT_GROWING),
+            TapeMode::RightInfinite => (RIGHT_GROWING, LEFT_TRAPPING),
+            TapeMode::Bounded {
+                edge: Edge::Trap, ..
+            } => (RIGHT_TRAPPING, LEFT_TRAPPING),
+            TapeMode::Bounded {
+                edge: Edge::Wrap, ..
+            } => (RIGHT_WRAPPING, LEFT_WRAPPING),
+        };
+        f.write_str(right)?;
+        f.write_str(left)?;
+        f.write_str(CELLS)?;
+        self.write_changes(f)?;
+        writeln!(f, "}}")?;
+
+        let size = match self.program.config().tape {
+            TapeMode::Bounded { size, .. } => size.max(1),
+            TapeMode::RightInfinite | TapeMode::BiInfinite => 1,
+        };
+        writeln!(f)?;
+        writeln!(
+            f,
+            "fn run(input: &mut impl Read, output: &mut impl Write) -> Result<(), Error> {{"
+        )?;
+        writeln!(f, "    let mut tape = Tape::new({size});")?;
+        emit::write_lines(f, self.program.commands(), Self::write_command)?;
+        writeln!(f, "    output.flush()?;")?;
+        writeln!(f, "    Ok(())")?;
+        writeln!(f, "}}")?;
+        f.write_str(MAIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process::Command};
+
+    use crate::config::{Config, Edge, EndOfInput, Overflow, TapeMode};
+
+    use super::{
+        super::harness::{self, config_program},
+        Source,
+    };
+
+    #[test]
+    fn config_in_source() {
+        let program = config_program::<u32>(Config {
+            overflow: Overflow::Saturate,
+            end_of_input: EndOfInput::Unchanged,
+            tape: TapeMode::Bounded {
+                size: 10,
+                edge: Edge::Trap,
+            },
+        });
+        let source = Source::new(&program).to_string();
+        assert!(source.contains("type Cell = u32;"));
+        assert!(source.contains("Some(cell.saturating_add(value))"));
+        assert!(source.contains("Moved right of the end of the tape"));
+        assert!(source.contains("let mut tape = Tape::new(10);"));
+        assert!(source.contains("    tape.input(input)?;\n    tape.multiply_add(1, 1, 1)?;\n"));
+    }
+
+    #[test]
+    fn compiles_and_runs() {
+        harness::same_as_interpreter(|program, input| {
+            let path = harness::temp_path("rust");
+            let source_path = path.with_extension("rs");
+            fs::write(&source_path, Source::new(program).to_string())
+                .expect("The file is writable");
+
+            // The tests are built with rustc, so it's there to build the program with
+            let status = Command::new("rustc")
+                .args(["--edition", "2021", "-D", "warnings", "-o"])
+                .arg(&path)
+                .arg(&source_path)
+                .status()
+                .expect("rustc runs");
+            assert!(status.success(), "rustc rejected the source");
+            let result = harness::run_with_input(&mut Command::new(&path), input);
+            fs::remove_file(&source_path).expect("The source was written");
+            fs::remove_file(&path).expect("The program was built");
+            Ok(result)
+        });
+    }
+}
@@ -0,0 +1,344 @@
+use crate::{cell::Cell, error::Error, optimized_program::OptimizedProgram};
+
+use super::webassembly::{self, Function, Module, Type, OUTPUT_BYTES};
+
+/// The kinds of the exports: functions, memories and globals.
+const FUNCTION: u8 = 0x00;
+const MEMORY: u8 = 0x02;
+const GLOBAL: u8 = 0x03;
+
+/// Appends a size or count, which fits in a WebAssembly module.
+fn size(bytes: &mut Vec<u8>, size: usize) {
+    webassembly::unsigned(bytes, u64::try_from(size).unwrap_or(u64::MAX));
+}
+
+/// Appends a name, prefixed by its length.
+fn name(bytes: &mut Vec<u8>, name: &str) {
+    size(bytes, name.len());
+    bytes.extend_from_slice(name.as_bytes());
+}
+
+/// Appends a section with the `id`, prefixed by its size.
+fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    module.push(id);
+    size(module, contents.len());
+    module.extend_from_slice(contents);
+}
+
+/// Returns the body of a function with the `locals` and `instructions`, prefixed by its size.
+fn body(locals: &[(u8, Type)], instructions: &[webassembly::Instruction]) -> Vec<u8> {
+    let mut code = Vec::new();
+    size(&mut code, locals.len());
+    for (count, value_type) in locals {
+        code.extend_from_slice(&[*count, value_type.encoding().1]);
+    }
+    for instruction in instructions {
+        instruction.encode(&mut code);
+    }
+    let mut body = Vec::new();
+    size(&mut body, code.len());
+    body.extend_from_slice(&code);
+    body
+}
+
+/// Translates `program` into a binary WebAssembly module, which keeps its config.
+///
+/// The module imports `read_byte` and `write_byte` from `env` and exports `run`, which returns 0
+/// once the program finishes or why it failed: 1 at the end of the input, 2 if a cell overflowed,
+/// 3 or 4 if the pointer moved left or right of the tape, and 5 if it moved past the cells reserved
+/// for a tape that grows.
+/// The exported `pc` and `cell` globals then hold the command that failed and the cell that
+/// overflowed.
+/// Tapes that grow reserve 16 MiB of the linear memory up front.
+///
+/// # Errors
+///
+/// Returns [`Error::TapeTooLarge`] if the bounded tape takes more than 16 MiB.
+pub fn module<C: Cell>(program: &OptimizedProgram<C>) -> Result<Vec<u8>, Error> {
+    let module = Module::new(program)?;
+    let mut file = b"\0asm\x01\0\0\0".to_vec();
+
+    // () -> i32, (i32) -> () and (i32, i32) -> ()
+    section(
+        &mut file,
+        1,
+        &[
+            3, 0x60, 0, 1, 0x7F, 0x60, 1, 0x7F, 0, 0x60, 2, 0x7F, 0x7F, 0,
+        ],
+    );
+
+    let mut imports = vec![2];
+    for (function, type_index) in [(Function::ReadByte, 0), (Function::WriteByte, 1)] {
+        name(&mut imports, "env");
+        name(&mut imports, function.encoding().0.trim_start_matches('$'));
+        imports.extend_from_slice(&[FUNCTION, type_index]);
+    }
+    section(&mut file, 2, &imports);
+
+    // `run` and `output_bytes`
+    section(&mut file, 3, &[2, 0, 2]);
+
+    let mut memory = vec![1, 0];
+    size(&mut memory, module.pages);
+    section(&mut file, 5, &memory);
+
+    // 2 mutable `i32` globals that start at 0
+    section(
+        &mut file,
+        6,
+        &[2, 0x7F, 1, 0x41, 0, 0x0B, 0x7F, 1, 0x41, 0, 0x0B],
+    );
+
+    let mut exports = vec![4];
+    for (export, kind, index) in [
+        ("memory", MEMORY, 0),
+        ("run", FUNCTION, Function::Run.encoding().1),
+        ("pc", GLOBAL, 0),
+        ("cell", GLOBAL, 1),
+    ] {
+        name(&mut exports, export);
+        exports.extend_from_slice(&[kind, index]);
+    }
+    section(&mut file, 7, &exports);
+
+    let mut code = vec![2];
+    code.extend(body(&[(3, Type::I32), (2, module.cell_type)], &module.run));
+    code.extend(body(&[], &OUTPUT_BYTES));
+    section(&mut file, 10, &code);
+
+    if !module.data.is_empty() {
+        // 1 segment in memory 0, at an offset computed by a constant expression
+        let mut data = vec![1, 0, 0x41];
+        webassembly::signed(
+            &mut data,
+            i64::try_from(module.data_address).unwrap_or(i64::MAX),
+        );
+        data.push(0x0B);
+        size(&mut data, module.data.len());
+        data.extend_from_slice(&module.data);
+        section(&mut file, 11, &data);
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem;
+    use std::{fs, process::Command};
+
+    use crate::{
+        cell::Cell,
+        compile_optimized,
+        config::{Config, Edge, Overflow, TapeMode},
+        error::Error,
+        optimized_program::OptimizedProgram,
+        parse,
+    };
+
+    use super::{super::harness, module, webassembly::RESERVED_BYTES};
+
+    /// Reads an unsigned LEB128 number at `*index`, moving past it.
+    fn read(bytes: &[u8], index: &mut usize) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = bytes[*index];
+            *index += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    /// Returns the number of blocks the instruction at `*index` opens, minus 1 if it closes one,
+    /// moving past it.
+    fn nesting(code: &[u8], index: &mut usize) -> isize {
+        let opcode = code[*index];
+        *index += 1;
+        match opcode {
+            0x02..=0x04 => {
+                assert_eq!(code[*index], 0x40, "Blocks have no result");
+                *index += 1;
+                1
+            }
+            0x0B => -1,
+            // Branches, calls, locals, globals and constants have 1 immediate
+            0x0C | 0x0D | 0x10 | 0x20..=0x24 | 0x41 | 0x42 => {
+                read(code, index);
+                0
+            }
+            // Loads and stores have an alignment and an offset
+            0x28..=0x3E => {
+                read(code, index);
+                read(code, index);
+                0
+            }
+            0x05 | 0x0F | 0x1B | 0x45..=0x8A | 0xAD => 0,
+            _ => panic!("Unexpected opcode {opcode:#x}"),
+        }
+    }
+
+    /// Checks the structure of the module, returning the contents of its sections by ID.
+    fn sections(file: &[u8]) -> Vec<(u8, &[u8])> {
+        assert_eq!(file[..8], *b"\0asm\x01\0\0\0");
+        let mut sections = Vec::new();
+        let mut index = 8;
+        while index < file.len() {
+            let id = file[index];
+            index += 1;
+            let length = usize::try_from(read(file, &mut index)).expect("Sections are small");
+            sections.push((id, &file[index..index + length]));
+            index += length;
+        }
+        assert_eq!(index, file.len(), "The last section ends with the file");
+        assert!(
+            sections.is_sorted_by_key(|(id, _)| *id),
+            "The sections are in order"
+        );
+        sections
+    }
+
+    /// Checks that every body in the code section closes each block it opens, and ends with the
+    /// `end` of the function.
+    fn check_code(code: &[u8]) {
+        let mut index = 0;
+        assert_eq!(read(code, &mut index), 2);
+        for _ in 0..2 {
+            let length = usize::try_from(read(code, &mut index)).expect("Bodies are small");
+            let end = index + length;
+            let groups = read(code, &mut index);
+            for _ in 0..groups {
+                read(code, &mut index);
+                index += 1;
+            }
+            let mut depth = 1;
+            while index < end {
+                assert!(depth > 0, "Instructions follow the end of the function");
+                depth += nesting(code, &mut index);
+            }
+            assert_eq!((index, depth), (end, 0));
+        }
+        assert_eq!(index, code.len());
+    }
+
+    #[test]
+    fn structure() {
+        let program = compile_optimized::<u16, _>(
+            parse(*b"++++++++[>++++[>++>+++<<-]>+>+[<]<-]>>.>---.,[->+<]>."),
+            Config {
+                overflow: Overflow::Trap,
+                tape: TapeMode::Bounded {
+                    size: 100,
+                    edge: Edge::Wrap,
+                },
+                ..Config::default()
+            },
+        )
+        .expect("The loops are balanced");
+        let file = module(&program).expect("The tape is small");
+        let sections = sections(&file);
+        let ids: Vec<_> = sections.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [1, 2, 3, 5, 6, 7, 10, 11]);
+        assert_eq!(sections[3].1, [1, 0, 1]);
+        check_code(sections[6].1);
+    }
+
+    #[test]
+    fn known_output_in_data() {
+        let program =
+            compile_optimized::<u8, _>(parse(*b"+++[>++++++<-]>+++.."), Config::default())
+                .expect("The loops are balanced");
+        let file = module(&program).expect("The tape fits");
+        let sections = sections(&file);
+        let (id, data) = sections.last().expect("There are sections");
+        assert_eq!(*id, 11);
+        assert!(data.ends_with(&[2, 21, 21]));
+        check_code(sections[sections.len() - 2].1);
+    }
+
+    #[test]
+    fn tape_too_large() {
+        let program = compile_optimized::<u64, _>(
+            parse(*b"+"),
+            Config {
+                tape: TapeMode::Bounded {
+                    size: 1 << 22,
+                    edge: Edge::Trap,
+                },
+                ..Config::default()
+            },
+        )
+        .expect("The loops are balanced");
+        assert!(matches!(module(&program), Err(Error::TapeTooLarge)));
+    }
+
+    /// Runs `program` as a module with Node.js, returning its output and the error `run` failed
+    /// with, or `None` if Node.js isn't installed.
+    fn run<C: Cell>(program: &OptimizedProgram<C>, input: &[u8]) -> Option<(Vec<u8>, String)> {
+        const HOST: &str = "
+            const fs = require('fs');
+            const input = fs.readFileSync(0);
+            const output = [];
+            let index = 0;
+            const env = {
+                read_byte: () => (index < input.length ? input[index++] : -1),
+                write_byte: (byte) => output.push(byte),
+            };
+            WebAssembly.instantiate(fs.readFileSync(process.argv[1]), { env }).then(({ instance }) => {
+                const code = instance.exports.run();
+                process.stdout.write(Buffer.from(output));
+                process.stderr.write(`${code} ${instance.exports.pc.value} ${instance.exports.cell.value}`);
+            });
+        ";
+        Command::new("node").arg("--version").output().ok()?;
+        let path = harness::temp_path("wasm").with_extension("wasm");
+        fs::write(&path, module(program).expect("The tape fits")).expect("The file is writable");
+        let (output, result) =
+            harness::run_with_input(Command::new("node").arg("-e").arg(HOST).arg(&path), input);
+        fs::remove_file(&path).expect("The file was written");
+        let [code, pc, cell] = [0, 1, 2].map(|index| {
+            result
+                .split(' ')
+                .nth(index)
+                .and_then(|field| field.parse::<isize>().ok())
+                .unwrap_or_else(|| panic!("Node.js failed to run the module: {result}"))
+        });
+        let pc = pc.unsigned_abs();
+        let error = match code {
+            0 => return Some((output, String::new())),
+            1 => Error::EndOfInput,
+            2 => Error::Overflow { pc, cell },
+            3 => Error::TapeUnderflow { pc },
+            4 => Error::TapeOverflow { pc },
+            5 => {
+                let cells = RESERVED_BYTES.div_euclid(mem::size_of::<C>());
+                return Some((
+                    output,
+                    format!(
+                        "error: Moved past the {cells} cells reserved for the tape at command {pc}\n"
+                    ),
+                ));
+            }
+            _ => panic!("Unexpected failure {code}"),
+        };
+        Some((output, format!("error: {error}\n")))
+    }
+
+    #[test]
+    fn same_as_interpreter() {
+        harness::same_as_interpreter(|program, input| run(program, input).ok_or("Node.js"));
+    }
+
+    #[test]
+    fn reserved_tape() {
+        let program = compile_optimized::<u32, _>(parse(*b"-[>-]"), Config::default())
+            .expect("The loops are balanced");
+        if let Some((_, error)) = run(&program, b"") {
+            assert_eq!(
+                error,
+                "error: Moved past the 4194304 cells reserved for the tape at command 2\n"
+            );
+        }
+    }
+}
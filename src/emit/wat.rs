@@ -0,0 +1,174 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::{cell::Cell, error::Error, optimized_program::OptimizedProgram};
+
+use super::webassembly::{Function, Global, Instruction, Local, Module, OUTPUT_BYTES};
+
+/// A brainfuck program translated into the WebAssembly text format.
+///
+/// The module is built with `wat2wasm program.wat` and hosted like the one the
+/// [`wasm`](super::wasm) backend writes, which imports `read_byte` and `write_byte` from `env` and
+/// exports `run`.
+#[derive(Debug)]
+pub struct Source {
+    module: Module,
+}
+
+impl Source {
+    /// Translates `program`, which keeps its config.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TapeTooLarge`] if the bounded tape takes more than 16 MiB.
+    pub fn new<C: Cell>(program: &OptimizedProgram<C>) -> Result<Self, Error> {
+        Ok(Self {
+            module: Module::new(program)?,
+        })
+    }
+}
+
+/// Writes the body of a function without the `end` that closes it, indented by 2 spaces for every
+/// block it's in.
+fn write_body(f: &mut Formatter<'_>, instructions: &[Instruction]) -> fmt::Result {
+    let mut depth = 2_usize;
+    let body = instructions
+        .split_last()
+        .map_or(instructions, |(_, body)| body);
+    for instruction in body {
+        if matches!(instruction, Instruction::Else | Instruction::End) {
+            depth -= 1;
+        }
+        writeln!(f, "{:width$}{instruction}", "", width = depth * 2)?;
+        if matches!(
+            instruction,
+            Instruction::Block | Instruction::Loop | Instruction::If | Instruction::Else
+        ) {
+            depth += 1;
+        }
+    }
+    Ok(())
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let module = &self.module;
+        let (pc, cell) = (Global::Pc.encoding().0, Global::Cell.encoding().0);
+        let cell_type = module.cell_type.encoding().0;
+        writeln!(f, "(module")?;
+        writeln!(
+            f,
+            "  (import \"env\" \"read_byte\" (func {} (result i32)))",
+            Function::ReadByte.encoding().0
+        )?;
+        writeln!(
+            f,
+            "  (import \"env\" \"write_byte\" (func {} (param i32)))",
+            Function::WriteByte.encoding().0
+        )?;
+        writeln!(f, "  (memory (export \"memory\") {})", module.pages)?;
+        writeln!(f, "  (global {pc} (export \"pc\") (mut i32) (i32.const 0))")?;
+        writeln!(
+            f,
+            "  (global {cell} (export \"cell\") (mut i32) (i32.const 0))"
+        )?;
+        if !module.data.is_empty() {
+            write!(f, "  (data (i32.const {}) \"", module.data_address)?;
+            for byte in &module.data {
+                match byte {
+                    b'"' | b'\\' => write!(f, "\\{}", char::from(*byte))?,
+                    b' '..=b'~' => write!(f, "{}", char::from(*byte))?,
+                    _ => write!(f, "\\{byte:02x}")?,
+                }
+            }
+            writeln!(f, "\")")?;
+        }
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  (func {} (export \"run\") (result i32)",
+            Function::Run.encoding().0
+        )?;
+        for local in [Local::Pointer, Local::Target, Local::Byte] {
+            writeln!(f, "    (local {} i32)", local.encoding().0)?;
+        }
+        for local in [Local::Value, Local::Product] {
+            writeln!(f, "    (local {} {cell_type})", local.encoding().0)?;
+        }
+        write_body(f, &module.run)?;
+        writeln!(f, "  )")?;
+
+        writeln!(f)?;
+        writeln!(
+            f,
+            "  (func {} (param {} i32) (param {} i32)",
+            Function::OutputBytes.encoding().0,
+            Local::Address.encoding().0,
+            Local::Length.encoding().0
+        )?;
+        write_body(f, &OUTPUT_BYTES)?;
+        writeln!(f, "  )")?;
+        writeln!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compile_optimized,
+        config::{Config, Edge, Overflow, TapeMode},
+        error::Error,
+        parse,
+    };
+
+    use super::{super::harness::config_program, Source};
+
+    #[test]
+    fn config_in_source() {
+        let program = config_program::<u16>(Config {
+            overflow: Overflow::Saturate,
+            tape: TapeMode::Bounded {
+                size: 10,
+                edge: Edge::Wrap,
+            },
+            ..Config::default()
+        });
+        let source = Source::new(&program)
+            .expect("The tape is small")
+            .to_string();
+        assert!(source.contains("  (memory (export \"memory\") 1)\n"));
+        assert!(source.contains("    (local $value i32)\n"));
+        assert!(source.contains("      i32.load16_u\n"));
+        assert!(source.contains("      select\n"));
+        assert!(source.contains("      i32.const 20\n"));
+        assert!(source.ends_with("  )\n)\n"));
+    }
+
+    #[test]
+    fn known_output_in_data() {
+        let program = compile_optimized::<u64, _>(
+            parse(*b"++++++++++[>+++++++<-]>++.+++++++++++++++++++++++++++++++.<++++++++++."),
+            Config::default(),
+        )
+        .expect("The loops are balanced");
+        let source = Source::new(&program).expect("The tape fits").to_string();
+        assert!(source.contains("\"Hg\\0a\")\n"));
+        assert!(source.contains("    (local $product i64)\n"));
+    }
+
+    #[test]
+    fn tape_too_large() {
+        let program = compile_optimized::<u32, _>(
+            parse(*b"+"),
+            Config {
+                tape: TapeMode::Bounded {
+                    size: 1 << 23,
+                    edge: Edge::Trap,
+                },
+                ..Config::default()
+            },
+        )
+        .expect("The loops are balanced");
+        assert!(matches!(Source::new(&program), Err(Error::TapeTooLarge)));
+    }
+}
@@ -0,0 +1,820 @@
+//! The WebAssembly instructions shared by the [`wat`](super::wat) and [`wasm`](super::wasm)
+//! backends, which can be written as text or encoded as a binary module.
+//!
+//! The module imports `read_byte`, which returns a byte of input or -1 at the end of the input,
+//! and `write_byte` from `env`.
+//! It exports its `memory`, with the tape at address 0, and a `run` function that returns 0 once
+//! the program finishes or the code of a [`Failure`].
+//! After a failure the exported `pc` global holds the index of the command that failed, and the
+//! `cell` global the index of the cell that overflowed.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    mem,
+};
+
+use crate::{
+    cell::Cell,
+    config::{Config, Edge, EndOfInput, Overflow, TapeMode},
+    error::Error,
+    optimized_command::OptimizedCommand,
+    optimized_program::OptimizedProgram,
+};
+
+/// The number of bytes reserved for tapes that grow, and the most a bounded tape can use.
+pub const RESERVED_BYTES: usize = 1 << 24;
+
+/// The size of a page of linear memory.
+pub const PAGE_SIZE: usize = 1 << 16;
+
+/// Why `run` stopped early, which it returns as the code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    EndOfInput = 1,
+    Overflow = 2,
+    /// The pointer moved left of the tape.
+    Left = 3,
+    /// The pointer moved right of the tape.
+    Right = 4,
+    /// The pointer moved past the cells reserved for a tape that grows.
+    Reserved = 5,
+}
+
+impl Failure {
+    /// Returns the code `run` returns for the failure.
+    #[expect(clippy::as_conversions, reason = "The code is the discriminant")]
+    const fn code(self) -> i64 {
+        self as i64
+    }
+}
+
+/// A value type, of which cells of 64 bits use `i64` and others `i32`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    I32,
+    I64,
+}
+
+impl Type {
+    /// Returns the name of the type and the byte that encodes it.
+    pub const fn encoding(self) -> (&'static str, u8) {
+        match self {
+            Self::I32 => ("i32", 0x7F),
+            Self::I64 => ("i64", 0x7E),
+        }
+    }
+}
+
+/// A numeric instruction, which exists for both types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Numeric {
+    Add,
+    Subtract,
+    Multiply,
+    /// Returns whether the value is 0, as an `i32`.
+    EqualZero,
+    LessThanSigned,
+    LessThanUnsigned,
+    GreaterThanUnsigned,
+    GreaterOrEqualUnsigned,
+    ShiftRightSigned,
+}
+
+impl Numeric {
+    /// Returns the name of the instruction and its opcode for operands of `value_type`.
+    const fn encoding(self, value_type: Type) -> (&'static str, u8) {
+        let (name, i32_opcode, i64_opcode) = match self {
+            Self::Add => ("add", 0x6A, 0x7C),
+            Self::Subtract => ("sub", 0x6B, 0x7D),
+            Self::Multiply => ("mul", 0x6C, 0x7E),
+            Self::EqualZero => ("eqz", 0x45, 0x50),
+            Self::LessThanSigned => ("lt_s", 0x48, 0x53),
+            Self::LessThanUnsigned => ("lt_u", 0x49, 0x54),
+            Self::GreaterThanUnsigned => ("gt_u", 0x4B, 0x56),
+            Self::GreaterOrEqualUnsigned => ("ge_u", 0x4F, 0x5A),
+            Self::ShiftRightSigned => ("shr_s", 0x75, 0x87),
+        };
+        match value_type {
+            Type::I32 => (name, i32_opcode),
+            Type::I64 => (name, i64_opcode),
+        }
+    }
+}
+
+/// A function of the module, in the order of their indices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    ReadByte,
+    WriteByte,
+    Run,
+    OutputBytes,
+}
+
+impl Function {
+    /// Returns the name of the function and its index.
+    pub const fn encoding(self) -> (&'static str, u8) {
+        match self {
+            Self::ReadByte => ("$read_byte", 0),
+            Self::WriteByte => ("$write_byte", 1),
+            Self::Run => ("$run", 2),
+            Self::OutputBytes => ("$output_bytes", 3),
+        }
+    }
+}
+
+/// A local of `run`, or a parameter of `output_bytes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Local {
+    /// The address of the current cell.
+    Pointer,
+    /// The address of the cell a command changes.
+    Target,
+    /// The result of `read_byte`.
+    Byte,
+    /// The value of a cell.
+    Value,
+    /// The product of a multiplication.
+    Product,
+    /// The address of the next byte `output_bytes` writes.
+    Address,
+    /// The number of bytes `output_bytes` has left to write.
+    Length,
+}
+
+impl Local {
+    /// Returns the name of the local and its index.
+    pub const fn encoding(self) -> (&'static str, u8) {
+        match self {
+            Self::Pointer => ("$pointer", 0),
+            Self::Target => ("$target", 1),
+            Self::Byte => ("$byte", 2),
+            Self::Value => ("$value", 3),
+            Self::Product => ("$product", 4),
+            Self::Address => ("$address", 0),
+            Self::Length => ("$length", 1),
+        }
+    }
+}
+
+/// A global, which is exported to report failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Global {
+    Pc,
+    Cell,
+}
+
+impl Global {
+    /// Returns the name of the global and its index.
+    pub const fn encoding(self) -> (&'static str, u8) {
+        match self {
+            Self::Pc => ("$pc", 0),
+            Self::Cell => ("$cell", 1),
+        }
+    }
+}
+
+/// A WebAssembly instruction of the few the lowering uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Block,
+    Loop,
+    If,
+    Else,
+    End,
+    /// Branches to the end of a block or the start of a loop, counting outwards from 0.
+    Branch(u32),
+    BranchIf(u32),
+    Return,
+    Call(Function),
+    /// Returns the first of 2 values if the condition on top isn't 0, or the second.
+    Select,
+    LocalGet(Local),
+    LocalSet(Local),
+    LocalTee(Local),
+    GlobalSet(Global),
+    Const(Type, i64),
+    Numeric(Type, Numeric),
+    /// Loads `width` bytes, zero-extended to the type.
+    Load(Type, usize),
+    /// Stores the lowest `width` bytes of the value.
+    Store(Type, usize),
+    /// Zero-extends an `i32` to an `i64`.
+    Extend,
+}
+
+impl Instruction {
+    /// Returns the name and opcode of a load or store of `width` bytes.
+    const fn memory_encoding(self, value_type: Type, width: usize) -> (&'static str, u8) {
+        let load = matches!(self, Self::Load(..));
+        match (load, value_type, width) {
+            (true, Type::I32, 1) => ("load8_u", 0x2D),
+            (true, Type::I32, 2) => ("load16_u", 0x2F),
+            (true, Type::I32, _) => ("load", 0x28),
+            (true, Type::I64, 1) => ("load8_u", 0x31),
+            (true, Type::I64, 2) => ("load16_u", 0x33),
+            (true, Type::I64, 4) => ("load32_u", 0x35),
+            (true, Type::I64, _) => ("load", 0x29),
+            (false, Type::I32, 1) => ("store8", 0x3A),
+            (false, Type::I32, 2) => ("store16", 0x3B),
+            (false, Type::I32, _) => ("store", 0x36),
+            (false, Type::I64, 1) => ("store8", 0x3C),
+            (false, Type::I64, 2) => ("store16", 0x3D),
+            (false, Type::I64, 4) => ("store32", 0x3E),
+            (false, Type::I64, _) => ("store", 0x37),
+        }
+    }
+
+    /// Appends the binary encoding of the instruction.
+    pub fn encode(self, code: &mut Vec<u8>) {
+        match self {
+            Self::Block => code.extend_from_slice(&[0x02, 0x40]),
+            Self::Loop => code.extend_from_slice(&[0x03, 0x40]),
+            Self::If => code.extend_from_slice(&[0x04, 0x40]),
+            Self::Else => code.push(0x05),
+            Self::End => code.push(0x0B),
+            Self::Branch(depth) => {
+                code.push(0x0C);
+                unsigned(code, depth.into());
+            }
+            Self::BranchIf(depth) => {
+                code.push(0x0D);
+                unsigned(code, depth.into());
+            }
+            Self::Return => code.push(0x0F),
+            Self::Call(function) => code.extend_from_slice(&[0x10, function.encoding().1]),
+            Self::Select => code.push(0x1B),
+            Self::LocalGet(local) => code.extend_from_slice(&[0x20, local.encoding().1]),
+            Self::LocalSet(local) => code.extend_from_slice(&[0x21, local.encoding().1]),
+            Self::LocalTee(local) => code.extend_from_slice(&[0x22, local.encoding().1]),
+            Self::GlobalSet(global) => code.extend_from_slice(&[0x24, global.encoding().1]),
+            Self::Const(value_type, value) => {
+                code.push(if value_type == Type::I32 { 0x41 } else { 0x42 });
+                signed(code, value);
+            }
+            Self::Numeric(value_type, numeric) => code.push(numeric.encoding(value_type).1),
+            Self::Load(value_type, width) | Self::Store(value_type, width) => {
+                // The alignment is the width, which every cell on the tape has
+                let alignment = u64::from(width.trailing_zeros());
+                code.push(self.memory_encoding(value_type, width).1);
+                unsigned(code, alignment);
+                unsigned(code, 0);
+            }
+            Self::Extend => code.push(0xAD),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Block => f.write_str("block"),
+            Self::Loop => f.write_str("loop"),
+            Self::If => f.write_str("if"),
+            Self::Else => f.write_str("else"),
+            Self::End => f.write_str("end"),
+            Self::Branch(depth) => write!(f, "br {depth}"),
+            Self::BranchIf(depth) => write!(f, "br_if {depth}"),
+            Self::Return => f.write_str("return"),
+            Self::Call(function) => write!(f, "call {}", function.encoding().0),
+            Self::Select => f.write_str("select"),
+            Self::LocalGet(local) => write!(f, "local.get {}", local.encoding().0),
+            Self::LocalSet(local) => write!(f, "local.set {}", local.encoding().0),
+            Self::LocalTee(local) => write!(f, "local.tee {}", local.encoding().0),
+            Self::GlobalSet(global) => write!(f, "global.set {}", global.encoding().0),
+            Self::Const(value_type, value) => {
+                write!(f, "{}.const {value}", value_type.encoding().0)
+            }
+            Self::Numeric(value_type, numeric) => write!(
+                f,
+                "{}.{}",
+                value_type.encoding().0,
+                numeric.encoding(value_type).0
+            ),
+            Self::Load(value_type, width) | Self::Store(value_type, width) => write!(
+                f,
+                "{}.{}",
+                value_type.encoding().0,
+                self.memory_encoding(value_type, width).0
+            ),
+            Self::Extend => f.write_str("i64.extend_i32_u"),
+        }
+    }
+}
+
+/// Appends `value` as unsigned LEB128.
+pub fn unsigned(code: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = u8::try_from(value & 0x7F).expect("7 bits fit in a byte");
+        value >>= 7;
+        if value == 0 {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
+
+/// Appends `value` as signed LEB128.
+pub fn signed(code: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = u8::try_from(value & 0x7F).expect("7 bits fit in a byte");
+        value >>= 7;
+        // The sign bit of the last byte extends to the rest of the value
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
+
+/// The body of `output_bytes`, which writes the `length` bytes from `address` with `write_byte`.
+pub const OUTPUT_BYTES: [Instruction; 20] = [
+    Instruction::Block,
+    Instruction::Loop,
+    Instruction::LocalGet(Local::Length),
+    Instruction::Numeric(Type::I32, Numeric::EqualZero),
+    Instruction::BranchIf(1),
+    Instruction::LocalGet(Local::Address),
+    Instruction::Load(Type::I32, 1),
+    Instruction::Call(Function::WriteByte),
+    Instruction::LocalGet(Local::Address),
+    Instruction::Const(Type::I32, 1),
+    Instruction::Numeric(Type::I32, Numeric::Add),
+    Instruction::LocalSet(Local::Address),
+    Instruction::LocalGet(Local::Length),
+    Instruction::Const(Type::I32, 1),
+    Instruction::Numeric(Type::I32, Numeric::Subtract),
+    Instruction::LocalSet(Local::Length),
+    Instruction::Branch(0),
+    Instruction::End,
+    Instruction::End,
+    Instruction::End,
+];
+
+/// A program lowered to the body of `run`, with the memory it uses.
+#[derive(Debug)]
+pub struct Module {
+    /// The instructions of `run`, ending with its `end`.
+    pub run: Vec<Instruction>,
+    /// The type of the locals with the values of cells.
+    pub cell_type: Type,
+    /// The bytes of the known output, which follow the tape.
+    pub data: Vec<u8>,
+    /// The address of the data.
+    pub data_address: usize,
+    /// The number of pages of linear memory.
+    pub pages: usize,
+}
+
+/// The amount to change a cell by.
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Constant(u64),
+    Local(Local),
+}
+
+/// Lowers commands to instructions, as configured.
+struct Lowering {
+    instructions: Vec<Instruction>,
+    data: Vec<u8>,
+    width: usize,
+    cell_type: Type,
+    overflow: Overflow,
+    /// Whether the tape is bounded and wraps around.
+    wraps: bool,
+    /// What moving left and right of the tape fails with.
+    edges: [Failure; 2],
+    /// The number of bytes of the tape, which is where the data starts.
+    tape_bytes: usize,
+    /// The address of the cell the pointer starts at.
+    origin: usize,
+}
+
+impl Lowering {
+    fn emit(&mut self, instruction: Instruction) {
+        self.instructions.push(instruction);
+    }
+
+    /// Pushes the address or number of bytes, which are below [`RESERVED_BYTES`].
+    fn address(&mut self, bytes: usize) {
+        let bytes = i64::try_from(bytes).expect("Addresses fit in 32 bits");
+        self.emit(Instruction::Const(Type::I32, bytes));
+    }
+
+    /// Pushes the value as the type of cells.
+    fn constant(&mut self, value: u64) {
+        let value = match self.cell_type {
+            Type::I32 => u32::try_from(value)
+                .expect("Narrow cells fit in 32 bits")
+                .cast_signed()
+                .into(),
+            Type::I64 => value.cast_signed(),
+        };
+        self.emit(Instruction::Const(self.cell_type, value));
+    }
+
+    fn push(&mut self, operand: Operand) {
+        match operand {
+            Operand::Constant(value) => self.constant(value),
+            Operand::Local(local) => self.emit(Instruction::LocalGet(local)),
+        }
+    }
+
+    fn numeric(&mut self, numeric: Numeric) {
+        self.emit(Instruction::Numeric(self.cell_type, numeric));
+    }
+
+    /// Pushes the cell the local points at.
+    fn load(&mut self, local: Local) {
+        self.emit(Instruction::LocalGet(local));
+        self.emit(Instruction::Load(self.cell_type, self.width));
+    }
+
+    /// Pushes whether the cell the local points at is 0.
+    fn is_zero(&mut self, local: Local) {
+        self.load(local);
+        self.numeric(Numeric::EqualZero);
+    }
+
+    /// Returns the failure from `run`, for the command at `pc`.
+    fn fail(&mut self, pc: usize, failure: Failure) {
+        let pc = i64::try_from(pc).expect("Indices fit in 64 bits");
+        self.emit(Instruction::Const(Type::I32, pc));
+        self.emit(Instruction::GlobalSet(Global::Pc));
+        self.emit(Instruction::Const(Type::I32, failure.code()));
+        self.emit(Instruction::Return);
+    }
+
+    /// Returns the failure from `run` if the condition on top isn't 0.
+    fn fail_if(&mut self, pc: usize, failure: Failure) {
+        self.emit(Instruction::If);
+        self.fail(pc, failure);
+        self.emit(Instruction::End);
+    }
+
+    /// Reports that the cell `target` points at overflowed, if the condition on top isn't 0.
+    /// The index of the cell is relative to the cell the pointer started at.
+    fn overflow_if(&mut self, pc: usize, target: Local) {
+        self.emit(Instruction::If);
+        self.emit(Instruction::LocalGet(target));
+        self.address(self.origin);
+        self.emit(Instruction::Numeric(Type::I32, Numeric::Subtract));
+        if self.width > 1 {
+            self.emit(Instruction::Const(
+                Type::I32,
+                self.width.trailing_zeros().into(),
+            ));
+            self.emit(Instruction::Numeric(Type::I32, Numeric::ShiftRightSigned));
+        }
+        self.emit(Instruction::GlobalSet(Global::Cell));
+        self.fail(pc, Failure::Overflow);
+        self.emit(Instruction::End);
+    }
+
+    /// Returns the bytes `cells` cells take, if they're within the tape.
+    const fn bytes(&self, cells: usize) -> Option<usize> {
+        super::tape_bytes(cells, self.width, RESERVED_BYTES)
+    }
+
+    /// Sets `local` to the address `offset` cells right of the current cell, moving there like
+    /// a real move would.
+    fn move_to(&mut self, pc: usize, offset: isize, local: Local) {
+        use Instruction::{End, If, LocalGet, LocalSet, LocalTee};
+
+        let cells = self.tape_bytes.div_euclid(self.width);
+        if self.wraps {
+            let offset = super::wrapped_offset(offset, cells);
+            self.emit(LocalGet(Local::Pointer));
+            self.address(offset * self.width);
+            self.emit(Instruction::Numeric(Type::I32, Numeric::Add));
+            self.emit(LocalTee(local));
+            self.address(self.tape_bytes);
+            self.emit(Instruction::Numeric(
+                Type::I32,
+                Numeric::GreaterOrEqualUnsigned,
+            ));
+            self.emit(If);
+            self.emit(LocalGet(local));
+            self.address(self.tape_bytes);
+            self.emit(Instruction::Numeric(Type::I32, Numeric::Subtract));
+            self.emit(LocalSet(local));
+            self.emit(End);
+            return;
+        }
+
+        let [left, right] = self.edges;
+        let failure = if offset < 0 { left } else { right };
+        let Some(bytes) = self.bytes(offset.unsigned_abs()) else {
+            self.fail(pc, failure);
+            return;
+        };
+        self.emit(LocalGet(Local::Pointer));
+        self.address(bytes);
+        if offset < 0 {
+            self.emit(Instruction::Numeric(Type::I32, Numeric::LessThanUnsigned));
+            self.fail_if(pc, failure);
+            self.emit(LocalGet(Local::Pointer));
+            self.address(bytes);
+            self.emit(Instruction::Numeric(Type::I32, Numeric::Subtract));
+            self.emit(LocalSet(local));
+        } else {
+            self.emit(Instruction::Numeric(Type::I32, Numeric::Add));
+            self.emit(LocalTee(local));
+            self.address(self.tape_bytes);
+            self.emit(Instruction::Numeric(
+                Type::I32,
+                Numeric::GreaterOrEqualUnsigned,
+            ));
+            self.fail_if(pc, failure);
+        }
+    }
+
+    /// Returns the local pointing at the cell `offset` cells right of the current cell.
+    fn cell_at(&mut self, pc: usize, offset: isize) -> Local {
+        if offset == 0 {
+            Local::Pointer
+        } else {
+            self.move_to(pc, offset, Local::Target);
+            Local::Target
+        }
+    }
+
+    /// Adds `operand` to or subtracts it from the cell `target` points at, as configured.
+    fn change(&mut self, pc: usize, target: Local, change: Numeric, operand: Operand) {
+        use Instruction::{LocalGet, LocalTee, Select, Store};
+
+        let max = u64::MAX >> (64 - 8 * self.width);
+        let add = change == Numeric::Add;
+        // The most a cell can be before adding `operand` overflows
+        let push_limit = |lowering: &mut Self| match operand {
+            Operand::Constant(value) => lowering.constant(max - value),
+            Operand::Local(local) => {
+                lowering.constant(max);
+                lowering.emit(LocalGet(local));
+                lowering.numeric(Numeric::Subtract);
+            }
+        };
+        match self.overflow {
+            Overflow::Wrap => {}
+            Overflow::Saturate => {
+                self.emit(LocalGet(target));
+                self.constant(if add { max } else { 0 });
+                self.load(target);
+                self.emit(LocalTee(Local::Value));
+                self.push(operand);
+                self.numeric(change);
+                self.emit(LocalGet(Local::Value));
+                if add {
+                    push_limit(self);
+                    self.numeric(Numeric::GreaterThanUnsigned);
+                } else {
+                    self.push(operand);
+                    self.numeric(Numeric::LessThanUnsigned);
+                }
+                self.emit(Select);
+                self.emit(Store(self.cell_type, self.width));
+                return;
+            }
+            Overflow::Trap => {
+                self.load(target);
+                if add {
+                    push_limit(self);
+                    self.numeric(Numeric::GreaterThanUnsigned);
+                } else {
+                    self.push(operand);
+                    self.numeric(Numeric::LessThanUnsigned);
+                }
+                self.overflow_if(pc, target);
+            }
+        }
+        self.emit(LocalGet(target));
+        self.load(target);
+        self.push(operand);
+        self.numeric(change);
+        self.emit(Store(self.cell_type, self.width));
+    }
+
+    /// Changes or sets the cell `offset` cells right of the current cell to `value`.
+    fn change_at(&mut self, pc: usize, offset: isize, value: u64, change: Option<Numeric>) {
+        let target = self.cell_at(pc, offset);
+        if let Some(change) = change {
+            self.change(pc, target, change, Operand::Constant(value));
+        } else {
+            self.emit(Instruction::LocalGet(target));
+            self.constant(value);
+            self.emit(Instruction::Store(self.cell_type, self.width));
+        }
+    }
+
+    /// Changes the cell `offset` cells right of the current cell by the current cell times
+    /// `factor`, unless the current cell is 0.
+    fn multiply(&mut self, pc: usize, offset: isize, factor: u64, change: Numeric) {
+        self.is_zero(Local::Pointer);
+        self.emit(Instruction::Numeric(Type::I32, Numeric::EqualZero));
+        self.emit(Instruction::If);
+        let target = self.cell_at(pc, offset);
+        let max = u64::MAX >> (64 - 8 * self.width);
+        let limit = max.checked_div(factor);
+        match (self.overflow, limit) {
+            (Overflow::Saturate, Some(limit)) => {
+                self.constant(max);
+                self.load(Local::Pointer);
+                self.constant(factor);
+                self.numeric(Numeric::Multiply);
+                self.load(Local::Pointer);
+                self.constant(limit);
+                self.numeric(Numeric::GreaterThanUnsigned);
+                self.emit(Instruction::Select);
+            }
+            (Overflow::Trap, Some(limit)) => {
+                self.load(Local::Pointer);
+                self.constant(limit);
+                self.numeric(Numeric::GreaterThanUnsigned);
+                self.overflow_if(pc, target);
+                self.load(Local::Pointer);
+                self.constant(factor);
+                self.numeric(Numeric::Multiply);
+            }
+            _ => {
+                self.load(Local::Pointer);
+                self.constant(factor);
+                self.numeric(Numeric::Multiply);
+            }
+        }
+        self.emit(Instruction::LocalSet(Local::Product));
+        self.change(pc, target, change, Operand::Local(Local::Product));
+        self.emit(Instruction::End);
+    }
+
+    /// Moves the pointer in steps of `stride` cells until the current cell is 0.
+    fn scan(&mut self, pc: usize, stride: isize) {
+        self.emit(Instruction::Block);
+        self.emit(Instruction::Loop);
+        self.is_zero(Local::Pointer);
+        self.emit(Instruction::BranchIf(1));
+        self.move_pointer(pc, stride);
+        self.emit(Instruction::Branch(0));
+        self.emit(Instruction::End);
+        self.emit(Instruction::End);
+    }
+
+    /// Moves the pointer `amount` cells, to the left if it's negative.
+    fn move_pointer(&mut self, pc: usize, amount: isize) {
+        let cells = self.tape_bytes.div_euclid(self.width);
+        if !self.wraps || amount.rem_euclid(cells.cast_signed()) != 0 {
+            self.move_to(pc, amount, Local::Pointer);
+        }
+    }
+
+    /// Outputs the lowest byte of the cell `offset` cells right of the current cell.
+    fn output(&mut self, pc: usize, offset: isize) {
+        let source = self.cell_at(pc, offset);
+        self.emit(Instruction::LocalGet(source));
+        self.emit(Instruction::Load(Type::I32, 1));
+        self.emit(Instruction::Call(Function::WriteByte));
+    }
+
+    /// Reads a byte into the current cell, handling the end of the input as configured.
+    fn input(&mut self, pc: usize, end_of_input: EndOfInput) {
+        use Instruction::{Const, LocalGet, Store};
+
+        self.emit(Instruction::Call(Function::ReadByte));
+        self.emit(Instruction::LocalTee(Local::Byte));
+        self.emit(Const(Type::I32, 0));
+        self.emit(Instruction::Numeric(Type::I32, Numeric::LessThanSigned));
+        self.emit(Instruction::If);
+        match end_of_input {
+            EndOfInput::Zero | EndOfInput::MinusOne => {
+                self.emit(LocalGet(Local::Pointer));
+                self.constant(if end_of_input == EndOfInput::Zero {
+                    0
+                } else {
+                    u64::MAX >> (64 - 8 * self.width)
+                });
+                self.emit(Store(self.cell_type, self.width));
+            }
+            EndOfInput::Unchanged => {}
+            EndOfInput::Trap => self.fail(pc, Failure::EndOfInput),
+        }
+        self.emit(Instruction::Else);
+        self.emit(LocalGet(Local::Pointer));
+        self.emit(LocalGet(Local::Byte));
+        if self.cell_type == Type::I64 {
+            self.emit(Instruction::Extend);
+        }
+        self.emit(Store(self.cell_type, self.width));
+        self.emit(Instruction::End);
+    }
+
+    /// Lowers the command at `pc`.
+    fn command<C: Cell>(&mut self, pc: usize, command: &OptimizedCommand<C>, config: Config) {
+        let signed = |amount: usize| amount.cast_signed();
+        match command {
+            OptimizedCommand::AddPointer(amount) => self.move_pointer(pc, signed(*amount)),
+            OptimizedCommand::SubtractPointer(amount) => {
+                self.move_pointer(pc, signed(*amount).wrapping_neg());
+            }
+            OptimizedCommand::AddValue(value) => {
+                self.change_at(pc, 0, value.to_u64(), Some(Numeric::Add));
+            }
+            OptimizedCommand::SubtractValue(value) => {
+                self.change_at(pc, 0, value.to_u64(), Some(Numeric::Subtract));
+            }
+            OptimizedCommand::SetValue(value) => self.change_at(pc, 0, value.to_u64(), None),
+            OptimizedCommand::MultiplyAdd { offset, factor } => {
+                self.multiply(pc, *offset, factor.to_u64(), Numeric::Add);
+            }
+            OptimizedCommand::MultiplySubtract { offset, factor } => {
+                self.multiply(pc, *offset, factor.to_u64(), Numeric::Subtract);
+            }
+            OptimizedCommand::ScanRight(stride) => self.scan(pc, signed(*stride)),
+            OptimizedCommand::ScanLeft(stride) => self.scan(pc, signed(*stride).wrapping_neg()),
+            OptimizedCommand::AddAt { offset, value } => {
+                self.change_at(pc, *offset, value.to_u64(), Some(Numeric::Add));
+            }
+            OptimizedCommand::SubtractAt { offset, value } => {
+                self.change_at(pc, *offset, value.to_u64(), Some(Numeric::Subtract));
+            }
+            OptimizedCommand::SetAt { offset, value } => {
+                self.change_at(pc, *offset, value.to_u64(), None);
+            }
+            OptimizedCommand::OutputAt { offset } => self.output(pc, *offset),
+            OptimizedCommand::Output => self.output(pc, 0),
+            OptimizedCommand::OutputBytes(bytes) => {
+                if !bytes.is_empty() {
+                    self.address(self.tape_bytes + self.data.len());
+                    self.address(bytes.len());
+                    self.emit(Instruction::Call(Function::OutputBytes));
+                    self.data.extend_from_slice(bytes);
+                }
+            }
+            OptimizedCommand::Input => self.input(pc, config.end_of_input),
+            OptimizedCommand::StartOfLoop { .. } => {
+                self.emit(Instruction::Block);
+                self.emit(Instruction::Loop);
+                self.is_zero(Local::Pointer);
+                self.emit(Instruction::BranchIf(1));
+            }
+            OptimizedCommand::EndOfLoop { .. } => {
+                self.emit(Instruction::Branch(0));
+                self.emit(Instruction::End);
+                self.emit(Instruction::End);
+            }
+        }
+    }
+}
+
+impl Module {
+    /// Lowers `program`, which keeps its config.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TapeTooLarge`] if the bounded tape takes more than [`RESERVED_BYTES`].
+    pub fn new<C: Cell>(program: &OptimizedProgram<C>) -> Result<Self, Error> {
+        let config = program.config();
+        let width = mem::size_of::<C>();
+        let reserved = RESERVED_BYTES.div_euclid(width);
+        let (cells, origin, wraps) = match config.tape {
+            TapeMode::Bounded { size, edge } => (size.max(1), 0, edge == Edge::Wrap),
+            TapeMode::RightInfinite => (reserved, 0, false),
+            TapeMode::BiInfinite => (reserved, reserved.div_euclid(2), false),
+        };
+        if cells > reserved {
+            return Err(Error::TapeTooLarge);
+        }
+
+        let cell_type = if width == 8 { Type::I64 } else { Type::I32 };
+        let mut lowering = Lowering {
+            instructions: Vec::new(),
+            data: Vec::new(),
+            width,
+            cell_type,
+            overflow: config.overflow,
+            wraps,
+            edges: match config.tape {
+                TapeMode::Bounded { .. } => [Failure::Left, Failure::Right],
+                TapeMode::RightInfinite => [Failure::Left, Failure::Reserved],
+                TapeMode::BiInfinite => [Failure::Reserved, Failure::Reserved],
+            },
+            tape_bytes: cells * width,
+            origin: origin * width,
+        };
+        lowering.address(lowering.origin);
+        lowering.emit(Instruction::LocalSet(Local::Pointer));
+        for (pc, command) in program.commands().iter().enumerate() {
+            lowering.command(pc, command, config);
+        }
+        lowering.emit(Instruction::Const(Type::I32, 0));
+        lowering.emit(Instruction::End);
+
+        let memory = lowering.tape_bytes + lowering.data.len();
+        Ok(Self {
+            run: lowering.instructions,
+            cell_type,
+            data: lowering.data,
+            data_address: lowering.tape_bytes,
+            pages: memory.div_ceil(PAGE_SIZE).max(1),
+        })
+    }
+}
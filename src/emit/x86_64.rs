@@ -0,0 +1,1463 @@
+//! The x86-64 instructions shared by the [`asm`](super::asm) and [`elf`](super::elf) backends,
+//! which can be written as GNU assembler syntax or encoded as machine code.
+//! The JIT encodes its machine code with them as well.
+//!
+//! The program runs without libc, with raw Linux syscalls for reading, writing and exiting.
+//! While it runs, `rbx` points at the current cell, `r12` and `r13` at the start and end of the
+//! tape, `r14` at the end of the buffered output and `r15` at the start of the output buffer.
+
+use core::{
+    fmt::{self, Display, Formatter},
+    mem,
+};
+
+use crate::{
+    cell::Cell,
+    config::{Edge, EndOfInput, Overflow, TapeMode},
+    error::Error,
+    optimized_command::OptimizedCommand,
+    optimized_program::OptimizedProgram,
+};
+
+/// The number of bytes reserved for tapes that grow, and the most a bounded tape can use.
+pub const RESERVED_BYTES: usize = 1 << 28;
+
+/// The number of bytes of output that are buffered before they're written.
+pub const BUFFER_SIZE: usize = 4096;
+
+/// The names of the registers for 8, 16, 32 and 64-bit operands, by register number.
+const REGISTER_NAMES: [[&str; 4]; 16] = [
+    ["al", "ax", "eax", "rax"],
+    ["cl", "cx", "ecx", "rcx"],
+    ["dl", "dx", "edx", "rdx"],
+    ["bl", "bx", "ebx", "rbx"],
+    ["spl", "sp", "esp", "rsp"],
+    ["bpl", "bp", "ebp", "rbp"],
+    ["sil", "si", "esi", "rsi"],
+    ["dil", "di", "edi", "rdi"],
+    ["r8b", "r8w", "r8d", "r8"],
+    ["r9b", "r9w", "r9d", "r9"],
+    ["r10b", "r10w", "r10d", "r10"],
+    ["r11b", "r11w", "r11d", "r11"],
+    ["r12b", "r12w", "r12d", "r12"],
+    ["r13b", "r13w", "r13d", "r13"],
+    ["r14b", "r14w", "r14d", "r14"],
+    ["r15b", "r15w", "r15d", "r15"],
+];
+
+/// A general purpose register, of which the generated code uses a few.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    /// Returns the number that encodes the register.
+    const fn number(self) -> u8 {
+        match self {
+            Self::Rax => 0,
+            Self::Rcx => 1,
+            Self::Rdx => 2,
+            Self::Rbx => 3,
+            Self::Rsp => 4,
+            Self::Rsi => 6,
+            Self::Rdi => 7,
+            Self::R8 => 8,
+            Self::R9 => 9,
+            Self::R11 => 11,
+            Self::R12 => 12,
+            Self::R13 => 13,
+            Self::R14 => 14,
+            Self::R15 => 15,
+        }
+    }
+
+    /// Returns the name of the register for operands of `width` bytes.
+    fn name(self, width: usize) -> &'static str {
+        let size = match width {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            _ => 3,
+        };
+        REGISTER_NAMES[usize::from(self.number())][size]
+    }
+}
+
+/// The condition of a conditional jump, on the flags of the previous instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// The unsigned result was lower, which is also a carry.
+    Below,
+    /// The unsigned result wasn't lower, so there was no carry.
+    AboveOrEqual,
+    Equal,
+    NotEqual,
+    BelowOrEqual,
+    Above,
+    Sign,
+    NotSign,
+}
+
+impl Condition {
+    /// Returns the number that encodes the condition, and its mnemonic.
+    const fn code(self) -> (u8, &'static str) {
+        match self {
+            Self::Below => (0x2, "b"),
+            Self::AboveOrEqual => (0x3, "ae"),
+            Self::Equal => (0x4, "e"),
+            Self::NotEqual => (0x5, "ne"),
+            Self::BelowOrEqual => (0x6, "be"),
+            Self::Above => (0x7, "a"),
+            Self::Sign => (0x8, "s"),
+            Self::NotSign => (0x9, "ns"),
+        }
+    }
+}
+
+/// A position in the code, which routines give a name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Label {
+    id: usize,
+    name: Option<&'static str>,
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => f.write_str(name),
+            None => write!(f, ".L{}", self.id),
+        }
+    }
+}
+
+/// Data outside of the code, which is addressed relative to the instruction using it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symbol {
+    /// The cells, which are zeroed when the program starts.
+    Tape,
+    /// The output that hasn't been written yet.
+    Buffer,
+    /// The byte that input is read into.
+    InputByte,
+    /// The bytes of a message or known output, by index in [`Assembly::messages`].
+    Message(usize),
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tape => f.write_str("tape"),
+            Self::Buffer => f.write_str("buffer"),
+            Self::InputByte => f.write_str("input_byte"),
+            Self::Message(index) => write!(f, "message_{index}"),
+        }
+    }
+}
+
+/// An operation on a register and an immediate value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImmediateOperation {
+    Add,
+    Subtract,
+    Compare,
+}
+
+impl ImmediateOperation {
+    /// Returns the mnemonic of the operation and its opcode extension.
+    const fn encoding(self) -> (&'static str, u8) {
+        match self {
+            Self::Add => ("add", 0),
+            Self::Subtract => ("sub", 5),
+            Self::Compare => ("cmp", 7),
+        }
+    }
+}
+
+/// An operation with a source and a destination register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterOperation {
+    Move,
+    Subtract,
+    /// Sets the flags like subtracting the source from the destination.
+    Compare,
+    Test,
+    /// Multiplies the destination by the source, keeping the lower 64 bits.
+    MultiplyLow,
+}
+
+impl RegisterOperation {
+    /// Returns the mnemonic of the operation and its opcode.
+    const fn encoding(self) -> (&'static str, &'static [u8]) {
+        match self {
+            Self::Move => ("mov", &[0x89]),
+            Self::Subtract => ("sub", &[0x29]),
+            Self::Compare => ("cmp", &[0x39]),
+            Self::Test => ("test", &[0x85]),
+            Self::MultiplyLow => ("imul", &[0x0F, 0xAF]),
+        }
+    }
+}
+
+/// An operation on a single register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOperation {
+    /// Multiplies `rax` by the register into `rdx:rax`.
+    Multiply,
+    /// Divides `rdx:rax` by the register, with the quotient in `rax` and remainder in `rdx`.
+    Divide,
+    Negate,
+    Increment,
+    Decrement,
+}
+
+impl UnaryOperation {
+    /// Returns the mnemonic of the operation, its opcode and its opcode extension.
+    const fn encoding(self) -> (&'static str, u8, u8) {
+        match self {
+            Self::Multiply => ("mul", 0xF7, 4),
+            Self::Divide => ("div", 0xF7, 6),
+            Self::Negate => ("neg", 0xF7, 3),
+            Self::Increment => ("inc", 0xFF, 0),
+            Self::Decrement => ("dec", 0xFF, 1),
+        }
+    }
+}
+
+/// An operation between a register and memory of the width of a cell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryOperation {
+    Add,
+    Subtract,
+    Store,
+    /// Loads the memory into the register, zeroing its upper bits.
+    Load,
+}
+
+/// An x86-64 instruction, or a label for the instructions after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Label(Label),
+    MoveImmediate(Register, u64),
+    /// Sets the register to its lower 32 bits exclusive-or themselves, which is 0.
+    Clear(Register),
+    Immediate(ImmediateOperation, Register, i32),
+    /// An operation with the destination register first.
+    Registers(RegisterOperation, Register, Register),
+    Unary(UnaryOperation, Register),
+    /// Shifts the register right by the amount, keeping its sign.
+    ShiftRight(Register, u8),
+    Push(Register),
+    Pop(Register),
+    /// Sets the destination to the base plus the displacement.
+    LoadAddress {
+        destination: Register,
+        base: Register,
+        displacement: i32,
+    },
+    /// Sets the destination to the address of the symbol plus the addend.
+    LoadSymbol {
+        destination: Register,
+        symbol: Symbol,
+        addend: i32,
+    },
+    /// An operation between the register and the `width` bytes the base points at.
+    Memory {
+        operation: MemoryOperation,
+        width: usize,
+        register: Register,
+        base: Register,
+    },
+    /// Compares the `width` bytes the base points at with 0.
+    CompareZero {
+        width: usize,
+        base: Register,
+    },
+    Jump(Option<Condition>, Label),
+    Call(Label),
+    /// Calls the function at the address in the register.
+    CallAddress(Register),
+    Return,
+    Syscall,
+}
+
+/// The suffix of instructions on memory of `width` bytes.
+const fn suffix(width: usize) -> char {
+    match width {
+        1 => 'b',
+        2 => 'w',
+        4 => 'l',
+        _ => 'q',
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Label(label) => write!(f, "{label}:"),
+            Self::MoveImmediate(register, value) => {
+                if let Ok(value) = u32::try_from(value) {
+                    write!(f, "    mov ${value}, %{}", register.name(4))
+                } else if let Ok(value) = i32::try_from(value.cast_signed()) {
+                    write!(f, "    mov ${value}, %{}", register.name(8))
+                } else {
+                    write!(f, "    movabs ${value}, %{}", register.name(8))
+                }
+            }
+            Self::Clear(register) => {
+                write!(f, "    xor %{0}, %{0}", register.name(4))
+            }
+            Self::Immediate(operation, register, value) => {
+                let (mnemonic, _) = operation.encoding();
+                write!(f, "    {mnemonic} ${value}, %{}", register.name(8))
+            }
+            Self::Registers(operation, destination, source) => {
+                let (mnemonic, _) = operation.encoding();
+                write!(
+                    f,
+                    "    {mnemonic} %{}, %{}",
+                    source.name(8),
+                    destination.name(8)
+                )
+            }
+            Self::Unary(operation, register) => {
+                let (mnemonic, ..) = operation.encoding();
+                write!(f, "    {mnemonic} %{}", register.name(8))
+            }
+            Self::ShiftRight(register, amount) => {
+                write!(f, "    sar ${amount}, %{}", register.name(8))
+            }
+            Self::Push(register) => write!(f, "    push %{}", register.name(8)),
+            Self::Pop(register) => write!(f, "    pop %{}", register.name(8)),
+            Self::LoadAddress {
+                destination,
+                base,
+                displacement,
+            } => write!(
+                f,
+                "    lea {displacement}(%{}), %{}",
+                base.name(8),
+                destination.name(8)
+            ),
+            Self::LoadSymbol {
+                destination,
+                symbol,
+                addend,
+            } => write!(
+                f,
+                "    lea {symbol}{addend:+}(%rip), %{}",
+                destination.name(8)
+            ),
+            Self::Memory {
+                operation,
+                width,
+                register,
+                base,
+            } => {
+                let base = base.name(8);
+                let mnemonic = match operation {
+                    MemoryOperation::Add => "add",
+                    MemoryOperation::Subtract => "sub",
+                    MemoryOperation::Store => "mov",
+                    MemoryOperation::Load if width < 4 => {
+                        let register = register.name(4);
+                        return write!(f, "    movz{}l (%{base}), %{register}", suffix(width));
+                    }
+                    MemoryOperation::Load => {
+                        return write!(f, "    mov (%{base}), %{}", register.name(width));
+                    }
+                };
+                write!(f, "    {mnemonic} %{}, (%{base})", register.name(width))
+            }
+            Self::CompareZero { width, base } => {
+                write!(f, "    cmp{} $0, (%{})", suffix(width), base.name(8))
+            }
+            Self::Jump(None, label) => write!(f, "    jmp {label}"),
+            Self::Jump(Some(condition), label) => write!(f, "    j{} {label}", condition.code().1),
+            Self::Call(label) => write!(f, "    call {label}"),
+            Self::CallAddress(register) => write!(f, "    call *%{}", register.name(8)),
+            Self::Return => write!(f, "    ret"),
+            Self::Syscall => write!(f, "    syscall"),
+        }
+    }
+}
+
+/// Where the labels and symbols are while encoding.
+struct Addresses<'layout> {
+    /// The address of the first instruction.
+    code: u64,
+    /// The offset of every label from the first instruction.
+    labels: &'layout [usize],
+    symbols: &'layout dyn Fn(Symbol) -> u64,
+}
+
+/// Appends the REX prefix if it's needed, for 64-bit operands if `wide` and for the upper 8
+/// registers in the `ModRM` `reg` and `rm` fields.
+fn rex(code: &mut Vec<u8>, wide: bool, reg: u8, rm: u8) {
+    let prefix = 0x40 | (u8::from(wide) << 3) | ((reg >> 3) << 2) | (rm >> 3);
+    if prefix != 0x40 {
+        code.push(prefix);
+    }
+}
+
+/// Returns the `ModRM` byte with the addressing `mode` and the lower 3 bits of `reg` and `rm`.
+const fn modrm(mode: u8, reg: u8, rm: u8) -> u8 {
+    (mode << 6) | ((reg & 7) << 3) | (rm & 7)
+}
+
+#[expect(
+    clippy::little_endian_bytes,
+    reason = "x86-64 encodes immediates as little endian"
+)]
+impl Instruction {
+    /// Appends the opcode and `ModRM` byte of an instruction on 64-bit registers.
+    fn register_operation(code: &mut Vec<u8>, opcode: &[u8], reg: u8, rm: u8) {
+        rex(code, true, reg, rm);
+        code.extend_from_slice(opcode);
+        code.push(modrm(0b11, reg, rm));
+    }
+
+    /// Appends the offset of `target` from the end of the 4 bytes about to be appended.
+    fn relative(code: &mut Vec<u8>, start: u64, target: u64) {
+        let end = start + u64::try_from(code.len()).expect("The code fits in memory") + 4;
+        let offset = i32::try_from(target.wrapping_sub(end).cast_signed())
+            .expect("The code and data are within 2 GiB");
+        code.extend_from_slice(&offset.to_le_bytes());
+    }
+
+    /// Appends the machine code of the instruction.
+    fn encode(self, code: &mut Vec<u8>, addresses: &Addresses<'_>) {
+        let label_address = |label: Label| {
+            addresses.code + u64::try_from(addresses.labels[label.id]).expect("Offsets fit")
+        };
+        match self {
+            Self::Label(_) => {}
+            Self::MoveImmediate(register, value) => Self::encode_move(code, register, value),
+            Self::Clear(register) => {
+                let number = register.number();
+                rex(code, false, number, number);
+                code.extend_from_slice(&[0x31, modrm(0b11, number, number)]);
+            }
+            Self::Immediate(operation, register, value) => {
+                let (_, extension) = operation.encoding();
+                Self::register_operation(code, &[0x81], extension, register.number());
+                code.extend_from_slice(&value.to_le_bytes());
+            }
+            Self::Registers(operation, destination, source) => {
+                let (_, opcode) = operation.encoding();
+                let (destination, source) = (destination.number(), source.number());
+                // Multiplying is the only operation with the destination in the `reg` field
+                if operation == RegisterOperation::MultiplyLow {
+                    Self::register_operation(code, opcode, destination, source);
+                } else {
+                    Self::register_operation(code, opcode, source, destination);
+                }
+            }
+            Self::Unary(operation, register) => {
+                let (_, opcode, extension) = operation.encoding();
+                Self::register_operation(code, &[opcode], extension, register.number());
+            }
+            Self::ShiftRight(register, amount) => {
+                Self::register_operation(code, &[0xC1], 7, register.number());
+                code.push(amount);
+            }
+            Self::Push(register) | Self::Pop(register) => {
+                let number = register.number();
+                let opcode = if matches!(self, Self::Push(_)) {
+                    0x50
+                } else {
+                    0x58
+                };
+                rex(code, false, 0, number);
+                code.push(opcode + (number & 7));
+            }
+            Self::LoadAddress {
+                destination,
+                base,
+                displacement,
+            } => {
+                let base = base.number();
+                rex(code, true, destination.number(), base);
+                code.extend_from_slice(&[0x8D, modrm(0b10, destination.number(), base)]);
+                // `rsp` and `r12` can only be a base with a SIB byte
+                if base & 7 == 4 {
+                    code.push(0x24);
+                }
+                code.extend_from_slice(&displacement.to_le_bytes());
+            }
+            Self::LoadSymbol {
+                destination,
+                symbol,
+                addend,
+            } => {
+                rex(code, true, destination.number(), 0);
+                code.extend_from_slice(&[0x8D, modrm(0b00, destination.number(), 0b101)]);
+                let target = (addresses.symbols)(symbol).wrapping_add_signed(addend.into());
+                Self::relative(code, addresses.code, target);
+            }
+            Self::Memory {
+                operation,
+                width,
+                register,
+                base,
+            } => Self::encode_memory(code, operation, width, register, base),
+            Self::CompareZero { width, base } => {
+                if width == 2 {
+                    code.push(0x66);
+                }
+                rex(code, width == 8, 0, base.number());
+                let opcode = if width == 1 { 0x80 } else { 0x83 };
+                code.extend_from_slice(&[opcode, modrm(0b00, 7, base.number()), 0]);
+            }
+            Self::Jump(condition, label) => {
+                match condition {
+                    None => code.push(0xE9),
+                    Some(condition) => code.extend_from_slice(&[0x0F, 0x80 + condition.code().0]),
+                }
+                Self::relative(code, addresses.code, label_address(label));
+            }
+            Self::Call(label) => {
+                code.push(0xE8);
+                Self::relative(code, addresses.code, label_address(label));
+            }
+            Self::CallAddress(register) => {
+                let number = register.number();
+                rex(code, false, 0, number);
+                code.extend_from_slice(&[0xFF, modrm(0b11, 2, number)]);
+            }
+            Self::Return => code.push(0xC3),
+            Self::Syscall => code.extend_from_slice(&[0x0F, 0x05]),
+        }
+    }
+
+    /// Appends the machine code of a [`Self::MoveImmediate`] instruction, which is shorter for
+    /// values that fit in 32 bits, zero-extended or sign-extended.
+    fn encode_move(code: &mut Vec<u8>, register: Register, value: u64) {
+        let number = register.number();
+        if let Ok(value) = u32::try_from(value) {
+            rex(code, false, 0, number);
+            code.push(0xB8 + (number & 7));
+            code.extend_from_slice(&value.to_le_bytes());
+        } else if let Ok(value) = i32::try_from(value.cast_signed()) {
+            Self::register_operation(code, &[0xC7], 0, number);
+            code.extend_from_slice(&value.to_le_bytes());
+        } else {
+            rex(code, true, 0, number);
+            code.push(0xB8 + (number & 7));
+            code.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// Appends the machine code of a [`Self::Memory`] instruction.
+    fn encode_memory(
+        code: &mut Vec<u8>,
+        operation: MemoryOperation,
+        width: usize,
+        register: Register,
+        base: Register,
+    ) {
+        let (register, base) = (register.number(), base.number());
+        debug_assert!(
+            base & 7 != 4 && base & 7 != 5,
+            "The base can be addressed without a SIB byte or displacement"
+        );
+        debug_assert!(
+            width != 1 || register < 4,
+            "The lowest byte of the register can be addressed without a REX prefix"
+        );
+        let opcode: &[u8] = match (operation, width) {
+            (MemoryOperation::Add, 1) => &[0x00],
+            (MemoryOperation::Add, _) => &[0x01],
+            (MemoryOperation::Subtract, 1) => &[0x28],
+            (MemoryOperation::Subtract, _) => &[0x29],
+            (MemoryOperation::Store, 1) => &[0x88],
+            (MemoryOperation::Store, _) => &[0x89],
+            (MemoryOperation::Load, 1) => &[0x0F, 0xB6],
+            (MemoryOperation::Load, 2) => &[0x0F, 0xB7],
+            (MemoryOperation::Load, _) => &[0x8B],
+        };
+        // Loads of 1 or 2 bytes zero-extend into the 32-bit register instead
+        let wide = if operation == MemoryOperation::Load && width == 2 {
+            false
+        } else {
+            if width == 2 {
+                code.push(0x66);
+            }
+            width == 8
+        };
+        rex(code, wide, register, base);
+        code.extend_from_slice(opcode);
+        code.push(modrm(0b00, register, base));
+    }
+}
+
+/// The routines that the code for commands calls or jumps to, which are the first labels.
+#[derive(Clone, Copy, Debug)]
+struct Routines {
+    /// Buffers the byte in `al`, flushing the buffer after a newline or when it's full.
+    output: Label,
+    /// Writes the buffered output, keeping every register.
+    flush: Label,
+    /// Outputs the `rdx` bytes `rsi` points at.
+    output_bytes: Label,
+    /// Reads a byte into the current cell.
+    input: Label,
+    /// Reports moving left of the tape at command `r8`.
+    fail_left: Label,
+    /// Reports moving right of the tape at command `r8`.
+    fail_right: Label,
+    /// Reports that the cell `r9` points at overflowed at command `r8`.
+    fail_overflow: Label,
+    /// Writes the `rdx` bytes `rsi` points at to the standard error.
+    write_error: Label,
+    /// Writes the signed number in `rax` to the standard error.
+    write_number: Label,
+}
+
+/// What an error stub reports.
+#[derive(Clone, Copy, Debug)]
+enum Failure {
+    Left,
+    Right,
+    /// An overflow of the cell the register points at.
+    Overflow(Register),
+}
+
+/// The code for an error, which is out of line so the common path doesn't jump.
+#[derive(Clone, Copy, Debug)]
+struct Stub {
+    label: Label,
+    pc: usize,
+    failure: Failure,
+}
+
+/// A program lowered to x86-64 instructions, with the data they use.
+#[derive(Debug, Default)]
+pub struct Assembly {
+    /// The instructions, starting with the entry point.
+    pub instructions: Vec<Instruction>,
+    /// The number of labels, which are numbered from 0.
+    pub labels: usize,
+    /// The bytes of the messages and known output.
+    pub messages: Vec<Vec<u8>>,
+    /// The number of bytes the tape takes.
+    pub tape_bytes: usize,
+}
+
+/// Lowers commands to instructions, as configured.
+struct Lowering {
+    assembly: Assembly,
+    width: usize,
+    overflow: Overflow,
+    /// Whether the tape is bounded and wraps around.
+    wraps: bool,
+    /// The number of cells in the tape.
+    cells: usize,
+    /// The index of the cell the pointer starts at.
+    origin: usize,
+    routines: Routines,
+    stubs: Vec<Stub>,
+    /// The labels at the start and end of the loops being lowered.
+    open_loops: Vec<(Label, Label)>,
+}
+
+impl Lowering {
+    fn emit(&mut self, instruction: Instruction) {
+        self.assembly.instructions.push(instruction);
+    }
+
+    fn move_register(&mut self, destination: Register, source: Register) {
+        self.emit(Instruction::Registers(
+            RegisterOperation::Move,
+            destination,
+            source,
+        ));
+    }
+
+    /// Returns a new label without a name.
+    const fn label(&mut self) -> Label {
+        self.assembly.label()
+    }
+
+    /// Lowers the start of the program, which points the registers at the tape and the buffer.
+    fn start(&mut self) {
+        let tape_end = self.bytes(self.cells).expect("The tape fits");
+        let origin = self
+            .bytes(self.origin)
+            .expect("The origin is within the tape");
+        for (destination, symbol, addend) in [
+            (Register::R12, Symbol::Tape, 0),
+            (Register::R13, Symbol::Tape, tape_end),
+            (Register::Rbx, Symbol::Tape, origin),
+            (Register::R15, Symbol::Buffer, 0),
+        ] {
+            self.emit(Instruction::LoadSymbol {
+                destination,
+                symbol,
+                addend,
+            });
+        }
+        self.move_register(Register::R14, Register::R15);
+    }
+
+    /// Returns the symbol for the message, which is only stored once.
+    fn message(&mut self, bytes: &[u8]) -> Symbol {
+        let messages = &mut self.assembly.messages;
+        let index = messages
+            .iter()
+            .position(|message| message == bytes)
+            .unwrap_or_else(|| {
+                messages.push(bytes.to_vec());
+                messages.len() - 1
+            });
+        Symbol::Message(index)
+    }
+
+    /// Sets `rsi` and `rdx` to the address and length of the message.
+    fn load_message(&mut self, bytes: &[u8]) {
+        let symbol = self.message(bytes);
+        self.emit(Instruction::LoadSymbol {
+            destination: Register::Rsi,
+            symbol,
+            addend: 0,
+        });
+        self.emit(Instruction::MoveImmediate(
+            Register::Rdx,
+            u64::try_from(bytes.len()).expect("Lengths fit in 64 bits"),
+        ));
+    }
+
+    /// Jumps to a new stub reporting `failure` at command `pc`, if `condition` holds.
+    fn fail(&mut self, condition: Option<Condition>, pc: usize, failure: Failure) {
+        let label = self.label();
+        self.emit(Instruction::Jump(condition, label));
+        self.stubs.push(Stub { label, pc, failure });
+    }
+
+    /// Returns the bytes `cells` cells take, if they're within the tape.
+    fn bytes(&self, cells: usize) -> Option<i32> {
+        i32::try_from(super::tape_bytes(cells, self.width, RESERVED_BYTES)?).ok()
+    }
+
+    /// Sets `register` to the address `offset` cells right of the current cell, moving there
+    /// like a real move would.
+    fn address(&mut self, pc: usize, offset: isize, register: Register) {
+        if self.wraps {
+            let tape_bytes = self.bytes(self.cells).expect("The tape fits");
+            let cells = super::wrapped_offset(offset, self.cells);
+            let displacement = self.bytes(cells).expect("The offset is within the tape");
+            self.emit(Instruction::LoadAddress {
+                destination: register,
+                base: Register::Rbx,
+                displacement,
+            });
+            let inside = self.label();
+            self.emit(Instruction::Registers(
+                RegisterOperation::Compare,
+                register,
+                Register::R13,
+            ));
+            self.emit(Instruction::Jump(Some(Condition::Below), inside));
+            self.emit(Instruction::Immediate(
+                ImmediateOperation::Subtract,
+                register,
+                tape_bytes,
+            ));
+            self.emit(Instruction::Label(inside));
+            return;
+        }
+
+        let failure = if offset < 0 {
+            Failure::Left
+        } else {
+            Failure::Right
+        };
+        let Some(bytes) = self.bytes(offset.unsigned_abs()) else {
+            self.fail(None, pc, failure);
+            return;
+        };
+        let displacement = if offset < 0 { -bytes } else { bytes };
+        self.emit(Instruction::LoadAddress {
+            destination: register,
+            base: Register::Rbx,
+            displacement,
+        });
+        if offset < 0 {
+            self.emit(Instruction::Registers(
+                RegisterOperation::Compare,
+                register,
+                Register::R12,
+            ));
+            self.fail(Some(Condition::Below), pc, failure);
+        } else {
+            self.emit(Instruction::Registers(
+                RegisterOperation::Compare,
+                register,
+                Register::R13,
+            ));
+            self.fail(Some(Condition::AboveOrEqual), pc, failure);
+        }
+    }
+
+    /// Returns the register pointing at the cell `offset` cells right of the current cell.
+    fn cell_at(&mut self, pc: usize, offset: isize) -> Register {
+        if offset == 0 {
+            Register::Rbx
+        } else {
+            self.address(pc, offset, Register::Rcx);
+            Register::Rcx
+        }
+    }
+
+    /// Adds `rax` to or subtracts it from the cell `target` points at, as configured.
+    fn change(&mut self, pc: usize, operation: MemoryOperation, target: Register) {
+        self.emit(Instruction::Memory {
+            operation,
+            width: self.width,
+            register: Register::Rax,
+            base: target,
+        });
+        match self.overflow {
+            Overflow::Wrap => {}
+            Overflow::Saturate => {
+                let done = self.label();
+                self.emit(Instruction::Jump(Some(Condition::AboveOrEqual), done));
+                self.emit(if operation == MemoryOperation::Add {
+                    Instruction::MoveImmediate(Register::Rax, u64::MAX)
+                } else {
+                    Instruction::Clear(Register::Rax)
+                });
+                self.emit(Instruction::Memory {
+                    operation: MemoryOperation::Store,
+                    width: self.width,
+                    register: Register::Rax,
+                    base: target,
+                });
+                self.emit(Instruction::Label(done));
+            }
+            Overflow::Trap => self.fail(Some(Condition::Below), pc, Failure::Overflow(target)),
+        }
+    }
+
+    /// Changes the cell `offset` cells right of the current cell by `value`.
+    fn change_at(&mut self, pc: usize, offset: isize, value: u64, operation: MemoryOperation) {
+        let target = self.cell_at(pc, offset);
+        self.emit(Instruction::MoveImmediate(Register::Rax, value));
+        if operation == MemoryOperation::Store {
+            self.emit(Instruction::Memory {
+                operation,
+                width: self.width,
+                register: Register::Rax,
+                base: target,
+            });
+        } else {
+            self.change(pc, operation, target);
+        }
+    }
+
+    /// Changes the cell `offset` cells right of the current cell by the current cell times
+    /// `factor`, unless the current cell is 0.
+    fn multiply(&mut self, pc: usize, offset: isize, factor: u64, operation: MemoryOperation) {
+        let done = self.label();
+        self.emit(Instruction::CompareZero {
+            width: self.width,
+            base: Register::Rbx,
+        });
+        self.emit(Instruction::Jump(Some(Condition::Equal), done));
+        let target = self.cell_at(pc, offset);
+        self.emit(Instruction::Memory {
+            operation: MemoryOperation::Load,
+            width: self.width,
+            register: Register::Rax,
+            base: Register::Rbx,
+        });
+        self.emit(Instruction::MoveImmediate(Register::Rdx, factor));
+        if self.overflow == Overflow::Wrap {
+            self.emit(Instruction::Registers(
+                RegisterOperation::MultiplyLow,
+                Register::Rax,
+                Register::Rdx,
+            ));
+        } else {
+            // The product of 64-bit cells carries, narrower products are just larger than a cell
+            self.emit(Instruction::Unary(UnaryOperation::Multiply, Register::Rdx));
+            let (overflowed, fits) = if self.width == 8 {
+                (Condition::Below, Condition::AboveOrEqual)
+            } else {
+                let max = u64::MAX >> (64 - 8 * self.width);
+                self.emit(Instruction::MoveImmediate(Register::Rdx, max));
+                self.emit(Instruction::Registers(
+                    RegisterOperation::Compare,
+                    Register::Rax,
+                    Register::Rdx,
+                ));
+                (Condition::Above, Condition::BelowOrEqual)
+            };
+            if self.overflow == Overflow::Trap {
+                self.fail(Some(overflowed), pc, Failure::Overflow(target));
+            } else {
+                let saturated = self.label();
+                self.emit(Instruction::Jump(Some(fits), saturated));
+                self.emit(Instruction::MoveImmediate(Register::Rax, u64::MAX));
+                self.emit(Instruction::Label(saturated));
+            }
+        }
+        self.change(pc, operation, target);
+        self.emit(Instruction::Label(done));
+    }
+
+    /// Moves the pointer `amount` cells, to the left if it's negative.
+    fn move_pointer(&mut self, pc: usize, amount: isize) {
+        if self.wraps && amount.rem_euclid(self.cells.cast_signed()) == 0 {
+            return;
+        }
+        self.address(pc, amount, Register::Rbx);
+    }
+
+    /// Moves the pointer in steps of `stride` cells until the current cell is 0.
+    fn scan(&mut self, pc: usize, stride: isize) {
+        let (start, end) = (self.label(), self.label());
+        self.emit(Instruction::Label(start));
+        self.emit(Instruction::CompareZero {
+            width: self.width,
+            base: Register::Rbx,
+        });
+        self.emit(Instruction::Jump(Some(Condition::Equal), end));
+        self.move_pointer(pc, stride);
+        self.emit(Instruction::Jump(None, start));
+        self.emit(Instruction::Label(end));
+    }
+
+    /// Outputs the lowest byte of the cell `offset` cells right of the current cell.
+    fn output(&mut self, pc: usize, offset: isize) {
+        let source = self.cell_at(pc, offset);
+        self.emit(Instruction::Memory {
+            operation: MemoryOperation::Load,
+            width: 1,
+            register: Register::Rax,
+            base: source,
+        });
+        self.emit(Instruction::Call(self.routines.output));
+    }
+
+    /// Lowers the command at `pc`.
+    fn command<C: Cell>(&mut self, pc: usize, command: &OptimizedCommand<C>) {
+        let signed = |amount: usize| amount.cast_signed();
+        match command {
+            OptimizedCommand::AddPointer(amount) => self.move_pointer(pc, signed(*amount)),
+            OptimizedCommand::SubtractPointer(amount) => {
+                self.move_pointer(pc, signed(*amount).wrapping_neg());
+            }
+            OptimizedCommand::AddValue(value) => {
+                self.change_at(pc, 0, value.to_u64(), MemoryOperation::Add);
+            }
+            OptimizedCommand::SubtractValue(value) => {
+                self.change_at(pc, 0, value.to_u64(), MemoryOperation::Subtract);
+            }
+            OptimizedCommand::SetValue(value) => {
+                self.change_at(pc, 0, value.to_u64(), MemoryOperation::Store);
+            }
+            OptimizedCommand::MultiplyAdd { offset, factor } => {
+                self.multiply(pc, *offset, factor.to_u64(), MemoryOperation::Add);
+            }
+            OptimizedCommand::MultiplySubtract { offset, factor } => {
+                self.multiply(pc, *offset, factor.to_u64(), MemoryOperation::Subtract);
+            }
+            OptimizedCommand::ScanRight(stride) => self.scan(pc, signed(*stride)),
+            OptimizedCommand::ScanLeft(stride) => self.scan(pc, signed(*stride).wrapping_neg()),
+            OptimizedCommand::AddAt { offset, value } => {
+                self.change_at(pc, *offset, value.to_u64(), MemoryOperation::Add);
+            }
+            OptimizedCommand::SubtractAt { offset, value } => {
+                self.change_at(pc, *offset, value.to_u64(), MemoryOperation::Subtract);
+            }
+            OptimizedCommand::SetAt { offset, value } => {
+                self.change_at(pc, *offset, value.to_u64(), MemoryOperation::Store);
+            }
+            OptimizedCommand::OutputAt { offset } => self.output(pc, *offset),
+            OptimizedCommand::Output => self.output(pc, 0),
+            OptimizedCommand::OutputBytes(bytes) => {
+                if !bytes.is_empty() {
+                    self.load_message(bytes);
+                    self.emit(Instruction::Call(self.routines.output_bytes));
+                }
+            }
+            OptimizedCommand::Input => self.emit(Instruction::Call(self.routines.input)),
+            OptimizedCommand::StartOfLoop { .. } => {
+                let (start, end) = (self.label(), self.label());
+                self.emit(Instruction::CompareZero {
+                    width: self.width,
+                    base: Register::Rbx,
+                });
+                self.emit(Instruction::Jump(Some(Condition::Equal), end));
+                self.emit(Instruction::Label(start));
+                self.open_loops.push((start, end));
+            }
+            OptimizedCommand::EndOfLoop { .. } => {
+                let (start, end) = self.open_loops.pop().expect("Loops are balanced");
+                self.emit(Instruction::CompareZero {
+                    width: self.width,
+                    base: Register::Rbx,
+                });
+                self.emit(Instruction::Jump(Some(Condition::NotEqual), start));
+                self.emit(Instruction::Label(end));
+            }
+        }
+    }
+
+    /// Lowers the routines that write output, which keep every register but `rax` and `rcx`.
+    fn output_routines(&mut self) {
+        use Instruction::{Call, Jump, Label, Memory, Pop, Push, Registers, Return, Syscall};
+
+        let routines = self.routines;
+        self.emit(Label(routines.output));
+        self.emit(Memory {
+            operation: MemoryOperation::Store,
+            width: 1,
+            register: Register::Rax,
+            base: Register::R14,
+        });
+        self.emit(Instruction::Unary(UnaryOperation::Increment, Register::R14));
+        self.emit(Instruction::Immediate(
+            ImmediateOperation::Compare,
+            Register::Rax,
+            i32::from(b'\n'),
+        ));
+        self.emit(Jump(Some(Condition::Equal), routines.flush));
+        self.emit(Instruction::LoadAddress {
+            destination: Register::Rcx,
+            base: Register::R15,
+            displacement: i32::try_from(BUFFER_SIZE).expect("The buffer is small"),
+        });
+        self.emit(Registers(
+            RegisterOperation::Compare,
+            Register::R14,
+            Register::Rcx,
+        ));
+        self.emit(Jump(Some(Condition::Equal), routines.flush));
+        self.emit(Return);
+
+        let flushed = self.label();
+        let saved = [
+            Register::Rax,
+            Register::Rcx,
+            Register::Rdx,
+            Register::Rsi,
+            Register::Rdi,
+            Register::R11,
+        ];
+        self.emit(Label(routines.flush));
+        self.emit(Registers(
+            RegisterOperation::Compare,
+            Register::R14,
+            Register::R15,
+        ));
+        self.emit(Jump(Some(Condition::Equal), flushed));
+        for register in saved {
+            self.emit(Push(register));
+        }
+        self.emit(Instruction::MoveImmediate(Register::Rax, 1));
+        self.emit(Instruction::MoveImmediate(Register::Rdi, 1));
+        self.move_register(Register::Rsi, Register::R15);
+        self.move_register(Register::Rdx, Register::R14);
+        self.emit(Registers(
+            RegisterOperation::Subtract,
+            Register::Rdx,
+            Register::R15,
+        ));
+        self.emit(Syscall);
+        for register in saved.into_iter().rev() {
+            self.emit(Pop(register));
+        }
+        self.move_register(Register::R14, Register::R15);
+        self.emit(Label(flushed));
+        self.emit(Return);
+
+        self.emit(Label(routines.output_bytes));
+        self.emit(Memory {
+            operation: MemoryOperation::Load,
+            width: 1,
+            register: Register::Rax,
+            base: Register::Rsi,
+        });
+        self.emit(Call(routines.output));
+        self.emit(Instruction::Unary(UnaryOperation::Increment, Register::Rsi));
+        self.emit(Instruction::Unary(UnaryOperation::Decrement, Register::Rdx));
+        self.emit(Jump(Some(Condition::NotEqual), routines.output_bytes));
+        self.emit(Return);
+    }
+
+    /// Lowers the routine that reads a byte into the current cell.
+    fn input_routine(&mut self, end_of_input: EndOfInput, fail_end: Label) {
+        use Instruction::{Clear, Jump, Label, Memory, MoveImmediate, Return};
+
+        let (end, failed) = (self.label(), self.label());
+        self.emit(Label(self.routines.input));
+        self.emit(Clear(Register::Rax));
+        self.emit(Clear(Register::Rdi));
+        self.emit(Instruction::LoadSymbol {
+            destination: Register::Rsi,
+            symbol: Symbol::InputByte,
+            addend: 0,
+        });
+        self.emit(MoveImmediate(Register::Rdx, 1));
+        self.emit(Instruction::Syscall);
+        self.emit(Instruction::Registers(
+            RegisterOperation::Test,
+            Register::Rax,
+            Register::Rax,
+        ));
+        self.emit(Jump(Some(Condition::Sign), failed));
+        self.emit(Jump(Some(Condition::Equal), end));
+        self.emit(Memory {
+            operation: MemoryOperation::Load,
+            width: 1,
+            register: Register::Rax,
+            base: Register::Rsi,
+        });
+        let store = Memory {
+            operation: MemoryOperation::Store,
+            width: self.width,
+            register: Register::Rax,
+            base: Register::Rbx,
+        };
+        self.emit(store);
+        self.emit(Return);
+
+        self.emit(Label(end));
+        match end_of_input {
+            EndOfInput::Zero => {
+                self.emit(Clear(Register::Rax));
+                self.emit(store);
+                self.emit(Return);
+            }
+            EndOfInput::MinusOne => {
+                self.emit(MoveImmediate(Register::Rax, u64::MAX));
+                self.emit(store);
+                self.emit(Return);
+            }
+            EndOfInput::Unchanged => self.emit(Return),
+            EndOfInput::Trap => {
+                self.emit(Instruction::Call(self.routines.flush));
+                self.report(b"error: Unexpected end of input");
+                self.emit(Jump(None, fail_end));
+            }
+        }
+
+        self.emit(Label(failed));
+        self.emit(Instruction::Call(self.routines.flush));
+        self.report(b"error: I/O error");
+        self.emit(Jump(None, fail_end));
+    }
+
+    /// Writes the message to the standard error.
+    fn report(&mut self, message: &[u8]) {
+        self.load_message(message);
+        self.emit(Instruction::Call(self.routines.write_error));
+    }
+
+    /// Lowers the routines that report errors like
+    /// [`Error`](crate::error::Error) and exit with a failure.
+    fn error_routines(&mut self, messages: [String; 2], fail_end: Label) {
+        use Instruction::{Call, Jump, Label, MoveImmediate, Registers};
+
+        let routines = self.routines;
+        let fail_at = self.label();
+        for (label, message) in [routines.fail_left, routines.fail_right]
+            .into_iter()
+            .zip(messages)
+        {
+            self.emit(Label(label));
+            self.load_message(message.as_bytes());
+            self.emit(Jump(None, fail_at));
+        }
+
+        // The message is kept in `r12` and `r13` while writing the start of the line
+        self.emit(Label(fail_at));
+        self.emit(Call(routines.flush));
+        self.move_register(Register::R12, Register::Rsi);
+        self.move_register(Register::R13, Register::Rdx);
+        self.report(b"error: ");
+        self.move_register(Register::Rsi, Register::R12);
+        self.move_register(Register::Rdx, Register::R13);
+        self.emit(Call(routines.write_error));
+        self.report(b" at command ");
+        self.move_register(Register::Rax, Register::R8);
+        self.emit(Call(routines.write_number));
+        self.emit(Jump(None, fail_end));
+
+        // The index of the cell is relative to the cell the pointer started at
+        self.emit(Label(routines.fail_overflow));
+        self.emit(Call(routines.flush));
+        self.move_register(Register::Rax, Register::R9);
+        self.emit(Registers(
+            RegisterOperation::Subtract,
+            Register::Rax,
+            Register::R12,
+        ));
+        if self.width > 1 {
+            let shift = u8::try_from(self.width.trailing_zeros()).expect("Shifts are small");
+            self.emit(Instruction::ShiftRight(Register::Rax, shift));
+        }
+        if self.origin > 0 {
+            self.emit(Instruction::Immediate(
+                ImmediateOperation::Subtract,
+                Register::Rax,
+                i32::try_from(self.origin).expect("The origin is within the tape"),
+            ));
+        }
+        self.move_register(Register::R13, Register::Rax);
+        self.report(b"error: Cell ");
+        self.move_register(Register::Rax, Register::R13);
+        self.emit(Call(routines.write_number));
+        self.report(b" overflowed at command ");
+        self.move_register(Register::Rax, Register::R8);
+        self.emit(Call(routines.write_number));
+
+        self.emit(Label(fail_end));
+        self.report(b"\n");
+        self.emit(MoveImmediate(Register::Rdi, 1));
+        self.emit(MoveImmediate(Register::Rax, 60));
+        self.emit(Instruction::Syscall);
+    }
+
+    /// Lowers the routines that write messages and numbers to the standard error.
+    fn write_routines(&mut self) {
+        use Instruction::{Call, Jump, Label, MoveImmediate, Registers, Unary};
+
+        let routines = self.routines;
+        self.emit(Label(routines.write_error));
+        self.emit(MoveImmediate(Register::Rax, 1));
+        self.emit(MoveImmediate(Register::Rdi, 2));
+        self.emit(Instruction::Syscall);
+        self.emit(Instruction::Return);
+
+        // The digits are written backwards into 32 bytes of the stack, followed by the sign
+        let (digit, positive, write) = (self.label(), self.label(), self.label());
+        let end_of_digits = Instruction::LoadAddress {
+            destination: Register::Rsi,
+            base: Register::Rsp,
+            displacement: 32,
+        };
+        self.emit(Label(routines.write_number));
+        self.emit(Instruction::Immediate(
+            ImmediateOperation::Subtract,
+            Register::Rsp,
+            32,
+        ));
+        self.emit(end_of_digits);
+        self.move_register(Register::Rdi, Register::Rax);
+        self.emit(Registers(
+            RegisterOperation::Test,
+            Register::Rax,
+            Register::Rax,
+        ));
+        self.emit(Jump(Some(Condition::NotSign), positive));
+        self.emit(Unary(UnaryOperation::Negate, Register::Rax));
+        self.emit(Label(positive));
+        self.emit(MoveImmediate(Register::Rcx, 10));
+        self.emit(Label(digit));
+        self.emit(Instruction::Clear(Register::Rdx));
+        self.emit(Unary(UnaryOperation::Divide, Register::Rcx));
+        self.emit(Instruction::Immediate(
+            ImmediateOperation::Add,
+            Register::Rdx,
+            i32::from(b'0'),
+        ));
+        self.emit(Unary(UnaryOperation::Decrement, Register::Rsi));
+        let store = |register| Instruction::Memory {
+            operation: MemoryOperation::Store,
+            width: 1,
+            register,
+            base: Register::Rsi,
+        };
+        self.emit(store(Register::Rdx));
+        self.emit(Registers(
+            RegisterOperation::Test,
+            Register::Rax,
+            Register::Rax,
+        ));
+        self.emit(Jump(Some(Condition::NotEqual), digit));
+        self.emit(Registers(
+            RegisterOperation::Test,
+            Register::Rdi,
+            Register::Rdi,
+        ));
+        self.emit(Jump(Some(Condition::NotSign), write));
+        self.emit(Unary(UnaryOperation::Decrement, Register::Rsi));
+        self.emit(MoveImmediate(Register::Rax, u64::from(b'-')));
+        self.emit(store(Register::Rax));
+        self.emit(Label(write));
+        self.emit(Instruction::LoadAddress {
+            destination: Register::Rdx,
+            base: Register::Rsp,
+            displacement: 32,
+        });
+        self.emit(Registers(
+            RegisterOperation::Subtract,
+            Register::Rdx,
+            Register::Rsi,
+        ));
+        self.emit(Call(routines.write_error));
+        self.emit(Instruction::Immediate(
+            ImmediateOperation::Add,
+            Register::Rsp,
+            32,
+        ));
+        self.emit(Instruction::Return);
+    }
+
+    /// Lowers the stubs that load the command and offset for the error routines.
+    fn stubs(&mut self) {
+        for stub in mem::take(&mut self.stubs) {
+            self.emit(Instruction::Label(stub.label));
+            self.emit(Instruction::MoveImmediate(
+                Register::R8,
+                u64::try_from(stub.pc).expect("Indices fit in 64 bits"),
+            ));
+            let routine = match stub.failure {
+                Failure::Left => self.routines.fail_left,
+                Failure::Right => self.routines.fail_right,
+                Failure::Overflow(target) => {
+                    self.move_register(Register::R9, target);
+                    self.routines.fail_overflow
+                }
+            };
+            self.emit(Instruction::Jump(None, routine));
+        }
+    }
+}
+
+impl Assembly {
+    /// Returns a new label without a name.
+    pub const fn label(&mut self) -> Label {
+        let label = Label {
+            id: self.labels,
+            name: None,
+        };
+        self.labels += 1;
+        label
+    }
+
+    /// Lowers `program`, which keeps its config.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::TapeTooLarge`] if the bounded tape takes more than [`RESERVED_BYTES`].
+    pub fn new<C: Cell>(program: &OptimizedProgram<C>) -> Result<Self, Error> {
+        let config = program.config();
+        let width = mem::size_of::<C>();
+        let reserved = RESERVED_BYTES.div_euclid(width);
+        let (cells, origin, wraps) = match config.tape {
+            TapeMode::Bounded { size, edge } => (size.max(1), 0, edge == Edge::Wrap),
+            TapeMode::RightInfinite => (reserved, 0, false),
+            TapeMode::BiInfinite => (reserved, reserved.div_euclid(2), false),
+        };
+        if cells > reserved {
+            return Err(Error::TapeTooLarge);
+        }
+
+        let mut labels = 0;
+        let mut routine = |name| {
+            labels += 1;
+            Label {
+                id: labels - 1,
+                name: Some(name),
+            }
+        };
+        let routines = Routines {
+            output: routine("output"),
+            flush: routine("flush"),
+            output_bytes: routine("output_bytes"),
+            input: routine("input"),
+            fail_left: routine("fail_left"),
+            fail_right: routine("fail_right"),
+            fail_overflow: routine("fail_overflow"),
+            write_error: routine("write_error"),
+            write_number: routine("write_number"),
+        };
+        let mut lowering = Lowering {
+            assembly: Self {
+                instructions: Vec::new(),
+                labels,
+                messages: Vec::new(),
+                tape_bytes: cells * width,
+            },
+            width,
+            overflow: config.overflow,
+            wraps,
+            cells,
+            origin,
+            routines,
+            stubs: Vec::new(),
+            open_loops: Vec::new(),
+        };
+        lowering.start();
+        for (pc, command) in program.commands().iter().enumerate() {
+            lowering.command(pc, command);
+        }
+
+        // Exit successfully after writing the rest of the output
+        lowering.emit(Instruction::Call(routines.flush));
+        lowering.emit(Instruction::Clear(Register::Rdi));
+        lowering.emit(Instruction::MoveImmediate(Register::Rax, 60));
+        lowering.emit(Instruction::Syscall);
+        lowering.stubs();
+
+        let reserved_message = format!("Moved past the {reserved} cells reserved for the tape");
+        let messages = match config.tape {
+            TapeMode::Bounded { .. } => [
+                "Moved left of the start of the tape".to_owned(),
+                "Moved right of the end of the tape".to_owned(),
+            ],
+            TapeMode::RightInfinite => [
+                "Moved left of the start of the tape".to_owned(),
+                reserved_message,
+            ],
+            TapeMode::BiInfinite => [reserved_message.clone(), reserved_message],
+        };
+        let fail_end = lowering.label();
+        lowering.output_routines();
+        lowering.input_routine(config.end_of_input, fail_end);
+        lowering.error_routines(messages, fail_end);
+        lowering.write_routines();
+        Ok(lowering.assembly)
+    }
+
+    /// Encodes the instructions as machine code starting at `address`, with the data at the
+    /// addresses `symbols` returns.
+    pub fn encode(&self, address: u64, symbols: &dyn Fn(Symbol) -> u64) -> Vec<u8> {
+        // Instructions are as long wherever their targets are, so the first pass finds the labels
+        let mut labels = vec![0; self.labels];
+        let mut code = Vec::new();
+        let placeholder = |_| address;
+        for pass in 0..2 {
+            code.clear();
+            for instruction in &self.instructions {
+                if let Instruction::Label(label) = instruction {
+                    labels[label.id] = code.len();
+                }
+                let addresses = Addresses {
+                    code: address,
+                    labels: &labels,
+                    symbols: if pass == 0 { &placeholder } else { symbols },
+                };
+                instruction.encode(&mut code, &addresses);
+            }
+        }
+        code
+    }
+}
@@ -0,0 +1,224 @@
+use core::fmt::{self, Display, Formatter};
+use std::{error, io};
+
+use crate::position::Position;
+
+/// An error that occurred while compiling or executing a brainfuck program.
+#[expect(
+    clippy::error_impl_error,
+    reason = "Callers refer to it as `error::Error`"
+)]
+#[derive(Debug)]
+pub enum Error {
+    /// A `]` was found without a matching `[`, at the given position if known.
+    UnmatchedEndOfLoop(Option<Position>),
+    /// A `[` was never closed by a matching `]`, at the given position if known.
+    UnmatchedStartOfLoop(Option<Position>),
+    /// The `,` command was executed after the input was exhausted.
+    EndOfInput,
+    /// A cell overflowed while overflowing is configured to trap.
+    Overflow {
+        /// The index of the command that overflowed.
+        pc: usize,
+        /// The index of the cell that overflowed, relative to the cell the pointer started at.
+        cell: isize,
+    },
+    /// The pointer moved left of the first cell of the tape.
+    TapeUnderflow {
+        /// The index of the command that moved the pointer.
+        pc: usize,
+    },
+    /// The pointer moved right of the last cell of a bounded tape.
+    TapeOverflow {
+        /// The index of the command that moved the pointer.
+        pc: usize,
+    },
+    /// The program ran for more steps than its step limit allows.
+    StepLimit {
+        /// The index of the command that would have run next.
+        pc: usize,
+    },
+    /// The program is too large for the 32-bit operands of the bytecode.
+    ProgramTooLarge,
+    /// The bounded tape is too large to reserve up front in a compiled program.
+    TapeTooLarge,
+    /// Reading input or writing output failed.
+    Io(io::Error),
+}
+
+impl Error {
+    /// Returns the position in the source code that caused the error, if known.
+    #[must_use]
+    pub const fn position(&self) -> Option<Position> {
+        match self {
+            Self::UnmatchedEndOfLoop(position) | Self::UnmatchedStartOfLoop(position) => *position,
+            Self::EndOfInput
+            | Self::Overflow { .. }
+            | Self::TapeUnderflow { .. }
+            | Self::TapeOverflow { .. }
+            | Self::StepLimit { .. }
+            | Self::ProgramTooLarge
+            | Self::TapeTooLarge
+            | Self::Io(_) => None,
+        }
+    }
+
+    /// Creates a rustc-style report of the error, showing the offending line of `source`.
+    #[must_use]
+    pub const fn diagnostic<'error>(
+        &'error self,
+        file_name: &'error str,
+        source: &'error str,
+    ) -> Diagnostic<'error> {
+        Diagnostic {
+            error: self,
+            file_name,
+            source,
+        }
+    }
+
+    /// Describes the character at the position of the error.
+    const fn label(&self) -> &'static str {
+        match self {
+            Self::UnmatchedEndOfLoop(_) => "this `]` has no matching `[`",
+            Self::UnmatchedStartOfLoop(_) => "this `[` is never closed",
+            Self::EndOfInput
+            | Self::Overflow { .. }
+            | Self::TapeUnderflow { .. }
+            | Self::TapeOverflow { .. }
+            | Self::StepLimit { .. }
+            | Self::ProgramTooLarge
+            | Self::TapeTooLarge
+            | Self::Io(_) => "",
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnmatchedEndOfLoop(None) => write!(f, "Unexpected end of loop"),
+            Self::UnmatchedEndOfLoop(Some(position)) => {
+                write!(f, "Unexpected end of loop at {position}")
+            }
+            Self::UnmatchedStartOfLoop(None) => write!(f, "Missing end of loop"),
+            Self::UnmatchedStartOfLoop(Some(position)) => {
+                write!(f, "Missing end of loop for the loop at {position}")
+            }
+            Self::EndOfInput => write!(f, "Unexpected end of input"),
+            Self::Overflow { pc, cell } => {
+                write!(f, "Cell {cell} overflowed at command {pc}")
+            }
+            Self::TapeUnderflow { pc } => {
+                write!(f, "Moved left of the start of the tape at command {pc}")
+            }
+            Self::TapeOverflow { pc } => {
+                write!(f, "Moved right of the end of the tape at command {pc}")
+            }
+            Self::StepLimit { pc } => write!(f, "Reached the step limit at command {pc}"),
+            Self::ProgramTooLarge => write!(f, "The program is too large to compile to bytecode"),
+            Self::TapeTooLarge => write!(
+                f,
+                "The tape is too large to reserve in the compiled program"
+            ),
+            Self::Io(error) => write!(f, "I/O error: {error}"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// An error with a snippet of the source code around it.
+#[derive(Debug)]
+pub struct Diagnostic<'error> {
+    error: &'error Error,
+    file_name: &'error str,
+    source: &'error str,
+}
+
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let Some(position) = self.error.position() else {
+            return write!(f, "error: {}", self.error);
+        };
+
+        // Find the line containing the error, the gutter is as wide as the line number
+        let line = self
+            .source
+            .lines()
+            .nth(position.line - 1)
+            .unwrap_or_default();
+        let gutter = position.line.to_string().len();
+        let message = match self.error {
+            Error::UnmatchedEndOfLoop(_) => "Unexpected end of loop",
+            Error::UnmatchedStartOfLoop(_) => "Missing end of loop",
+            Error::EndOfInput
+            | Error::Overflow { .. }
+            | Error::TapeUnderflow { .. }
+            | Error::TapeOverflow { .. }
+            | Error::StepLimit { .. }
+            | Error::ProgramTooLarge
+            | Error::TapeTooLarge
+            | Error::Io(_) => "",
+        };
+
+        writeln!(f, "error: {message}")?;
+        writeln!(f, "{:gutter$}--> {}:{position}", "", self.file_name)?;
+        writeln!(f, "{:gutter$} |", "")?;
+        writeln!(f, "{} | {line}", position.line)?;
+        write!(
+            f,
+            "{:gutter$} | {:column$}^ {}",
+            "",
+            "",
+            self.error.label(),
+            column = position.column - 1
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chars::{Chars, Decoding},
+        command::Token,
+        parse, parse_with,
+    };
+
+    #[test]
+    fn points_at_unmatched_bracket() {
+        let source = "+[\n>]]-";
+        let error = Token::check_loops(&parse(source.bytes()).collect::<Vec<_>>())
+            .expect_err("The second `]` is unmatched");
+        assert_eq!(
+            error.diagnostic("test.b", source).to_string(),
+            "error: Unexpected end of loop\n --> test.b:2:3\n  |\n2 | >]]-\n  |   ^ this `]` has no matching `[`"
+        );
+    }
+
+    #[test]
+    fn points_at_raw_bytes() {
+        // Every byte is a column when decoding bytes, so the snippet is decoded the same way
+        let source = "\u{e9}\n+\u{e9}]";
+        let tokens = parse_with(source.bytes(), Decoding::Bytes).collect::<Vec<_>>();
+        let error = Token::check_loops(&tokens).expect_err("The `]` is unmatched");
+        let decoded = Chars::new(source.bytes(), Decoding::Bytes).collect::<String>();
+        assert_eq!(
+            error.diagnostic("test.b", &decoded).to_string(),
+            "error: Unexpected end of loop\n --> test.b:2:4\n  |\n2 | +\u{c3}\u{a9}]\n  |    ^ this `]` has no matching `[`"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{cell::Cell, command::Command, error::Error};
+
+/// A node of the intermediate representation the optimization passes work on.
+///
+/// Loops are nested nodes that own their body, so passes can match and replace a whole loop
+/// without having to find its end or fix up jumps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node<C: Cell = u8> {
+    AddPointer(usize),
+    SubtractPointer(usize),
+    AddValue(C),
+    SubtractValue(C),
+    SetValue(C),
+    /// Adds the current cell times `factor` to the cell `offset` cells to the right,
+    /// unless the current cell is 0.
+    MultiplyAdd {
+        offset: isize,
+        factor: C,
+    },
+    /// Subtracts the current cell times `factor` from the cell `offset` cells to the right,
+    /// unless the current cell is 0.
+    MultiplySubtract {
+        offset: isize,
+        factor: C,
+    },
+    /// Moves the pointer right in steps of the stride until the current cell is 0.
+    ScanRight(usize),
+    /// Moves the pointer left in steps of the stride until the current cell is 0.
+    ScanLeft(usize),
+    /// Adds the value to the cell `offset` cells to the right, without moving the pointer.
+    AddAt {
+        offset: isize,
+        value: C,
+    },
+    /// Subtracts the value from the cell `offset` cells to the right, without moving the pointer.
+    SubtractAt {
+        offset: isize,
+        value: C,
+    },
+    /// Sets the cell `offset` cells to the right to the value, without moving the pointer.
+    SetAt {
+        offset: isize,
+        value: C,
+    },
+    /// Outputs the cell `offset` cells to the right, without moving the pointer.
+    OutputAt {
+        offset: isize,
+    },
+    Output,
+    /// Outputs the bytes, which were already known when compiling the program.
+    OutputBytes(Vec<u8>),
+    Input,
+    /// Runs the body while the current cell isn't 0.
+    Loop(Vec<Self>),
+}
+
+impl<C: Cell> Node<C> {
+    /// Builds the IR from commands, every command becomes a node of its own.
+    ///
+    /// # Errors
+    /// Returns an error if the loops aren't balanced.
+    pub fn build<Commands: IntoIterator<Item = Command>>(
+        commands: Commands,
+    ) -> Result<Vec<Self>, Error> {
+        // The bodies of the loops that are still open, the last one is the innermost
+        let mut open_loops = Vec::new();
+        let mut nodes = Vec::new();
+        for command in commands {
+            let node = match command {
+                Command::IncrementPointer => Self::AddPointer(1),
+                Command::DecrementPointer => Self::SubtractPointer(1),
+                Command::IncrementValue => Self::AddValue(C::ONE),
+                Command::DecrementValue => Self::SubtractValue(C::ONE),
+                Command::Output => Self::Output,
+                Command::Input => Self::Input,
+                Command::StartOfLoop => {
+                    open_loops.push(nodes);
+                    nodes = Vec::new();
+                    continue;
+                }
+                Command::EndOfLoop => {
+                    let body = nodes;
+                    nodes = open_loops.pop().ok_or(Error::UnmatchedEndOfLoop(None))?;
+                    Self::Loop(body)
+                }
+            };
+            nodes.push(node);
+        }
+
+        if open_loops.is_empty() {
+            Ok(nodes)
+        } else {
+            Err(Error::UnmatchedStartOfLoop(None))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{command::Command, error::Error};
+
+    use super::Node;
+
+    #[test]
+    fn nested_loops() {
+        let commands = crate::parse(*b"+[>[-]<.]").map(|token| token.command);
+        assert_eq!(
+            Node::<u8>::build(commands).expect("The loops are balanced"),
+            [
+                Node::AddValue(1),
+                Node::Loop(vec![
+                    Node::AddPointer(1),
+                    Node::Loop(vec![Node::SubtractValue(1)]),
+                    Node::SubtractPointer(1),
+                    Node::Output,
+                ]),
+            ]
+        );
+        assert!(matches!(
+            Node::<u8>::build([Command::StartOfLoop]),
+            Err(Error::UnmatchedStartOfLoop(None))
+        ));
+        assert!(matches!(
+            Node::<u8>::build([Command::EndOfLoop]),
+            Err(Error::UnmatchedEndOfLoop(None))
+        ));
+    }
+}
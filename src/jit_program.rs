@@ -0,0 +1,620 @@
+use core::{
+    ffi::c_void,
+    mem::{self, size_of},
+    ptr::{self, NonNull},
+    slice,
+};
+use std::io::{self, Read, Write};
+
+use crate::{
+    cell::Cell,
+    config::Overflow,
+    emit::x86_64::{
+        Assembly, Condition, Instruction, Label, MemoryOperation, Register, RegisterOperation,
+    },
+    error::Error,
+    optimized_command::OptimizedCommand,
+    optimized_program::OptimizedProgram,
+    tape::Tape,
+};
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(
+        address: *mut c_void,
+        length: usize,
+        protection: i32,
+        flags: i32,
+        file: i32,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, protection: i32) -> i32;
+    fn munmap(address: *mut c_void, length: usize) -> i32;
+}
+
+/// Memory mapped pages holding machine code, which can be executed but no longer written.
+#[derive(Debug)]
+struct ExecutableMemory {
+    address: NonNull<c_void>,
+    length: usize,
+}
+
+impl ExecutableMemory {
+    /// Copies `code` into newly mapped pages and makes them executable.
+    fn new(code: &[u8]) -> io::Result<Self> {
+        // SAFETY: Mapping new anonymous pages doesn't touch any existing memory
+        let address = unsafe {
+            mmap(
+                ptr::null_mut(),
+                code.len(),
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        // mmap returns -1 instead of a null pointer when it fails
+        let address = NonNull::new(address)
+            .filter(|address| address.as_ptr().addr() != usize::MAX)
+            .ok_or_else(io::Error::last_os_error)?;
+        let memory = Self {
+            address,
+            length: code.len(),
+        };
+
+        // SAFETY: The pages were just mapped writable and are at least as long as the code
+        unsafe { slice::from_raw_parts_mut(address.as_ptr().cast::<u8>(), code.len()) }
+            .copy_from_slice(code);
+        // SAFETY: The pages were mapped above and nothing else refers to them
+        if unsafe { mprotect(address.as_ptr(), code.len(), PROT_READ | PROT_EXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(memory)
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: The pages were mapped by `new` and the code can't run anymore
+        let unmapped = unsafe { munmap(self.address.as_ptr(), self.length) };
+        debug_assert_eq!(unmapped, 0, "Unmapping the machine code failed");
+    }
+}
+
+/// The state shared between the machine code and the commands it calls back into.
+///
+/// The machine code reads and writes the first 3 fields, so their order can't change.
+#[repr(C)]
+struct Context<'run, C: Cell> {
+    /// The first allocated cell.
+    start: *mut C,
+    /// The end of the allocated cells.
+    end: *mut C,
+    /// The current cell.
+    current: *mut C,
+    program: &'run mut OptimizedProgram<C>,
+    input: &'run mut dyn Read,
+    output: &'run mut dyn Write,
+    error: Option<Error>,
+}
+
+impl<C: Cell> Context<'_, C> {
+    /// Moves the pointer of the tape to `current`, after the machine code moved it.
+    fn sync(&mut self, current: *mut C) {
+        let pointer = (current.addr() - self.start.addr()).div_euclid(size_of::<C>());
+        self.program.tape_mut().set_pointer(pointer);
+    }
+
+    /// Points the machine code at the cells again, after the tape may have grown.
+    fn refresh(&mut self) {
+        let tape = self.program.tape_mut();
+        let pointer = tape.pointer();
+        let cells = tape.contiguous().as_mut_ptr_range();
+        self.start = cells.start;
+        self.end = cells.end;
+        self.current = cells.start.wrapping_add(pointer);
+    }
+}
+
+/// Runs the command at `pc` for the machine code, which returns on anything but 0.
+extern "C" fn execute_command<C: Cell>(
+    context_pointer: *mut Context<'_, C>,
+    current: *mut C,
+    pc: usize,
+) -> u64 {
+    // SAFETY: The machine code passes on the context it was called with, which outlives it
+    let context = unsafe { &mut *context_pointer };
+    context.sync(current);
+    match context
+        .program
+        .execute_command(pc, context.input, context.output)
+    {
+        Ok(()) => {
+            context.refresh();
+            0
+        }
+        Err(error) => {
+            context.error = Some(error);
+            1
+        }
+    }
+}
+
+/// The signature of the compiled program.
+type Function<C> = for<'run> unsafe extern "C" fn(*mut Context<'run, C>);
+
+/// The signature of [`execute_command`].
+type Callback<C> = for<'run> extern "C" fn(*mut Context<'run, C>, *mut C, usize) -> u64;
+
+// The offsets of the fields of `Context` the machine code uses
+const START: i32 = 0;
+const END: i32 = 8;
+const CURRENT: i32 = 16;
+
+/// Returns the operation that undoes `operation` on a cell.
+const fn undo(operation: MemoryOperation) -> MemoryOperation {
+    match operation {
+        MemoryOperation::Add => MemoryOperation::Subtract,
+        MemoryOperation::Subtract => MemoryOperation::Add,
+        other => other,
+    }
+}
+
+/// Returns the displacement of the cell `amount` cells away, if it fits in an instruction.
+fn displacement<C: Cell>(amount: isize) -> Option<i32> {
+    let displacement = amount.checked_mul(size_of::<C>().cast_signed())?;
+    i32::try_from(displacement).ok()
+}
+
+/// Compiles optimized commands into x86-64 machine code.
+///
+/// While the program runs, `rbx` points to the current cell, `r13` and `r14` to the start and end
+/// of the allocated cells and `r15` to the [`Context`].
+///
+/// Commands try a fast path first, and jump to the slow path that runs the command with the
+/// callback if they can't take it, like when the pointer leaves the allocated cells.
+#[derive(Debug)]
+struct Compiler {
+    assembly: Assembly,
+    /// The number of bytes of a cell.
+    width: usize,
+    /// Whether cells wrap, so changes don't have to be checked for overflow.
+    wraps: bool,
+    /// The address of [`execute_command`].
+    callback: u64,
+    /// The slow path of the current command.
+    slow: Label,
+    /// The end of the current command.
+    done: Label,
+    /// The starts of the bodies and the ends of the loops that are still open.
+    open_loops: Vec<(Label, Label)>,
+    /// The end of the program, which commands jump to when they fail.
+    exit: Label,
+}
+
+impl Compiler {
+    fn emit(&mut self, instruction: Instruction) {
+        self.assembly.instructions.push(instruction);
+    }
+
+    fn registers(&mut self, operation: RegisterOperation, destination: Register, source: Register) {
+        self.emit(Instruction::Registers(operation, destination, source));
+    }
+
+    /// Emits pointing `register` at the cell `displacement` bytes away from the current cell.
+    fn address(&mut self, register: Register, displacement: i32) {
+        self.emit(Instruction::LoadAddress {
+            destination: register,
+            base: Register::Rbx,
+            displacement,
+        });
+    }
+
+    /// Emits jumps to the slow path for when `register` isn't an allocated cell.
+    fn check_bounds(&mut self, register: Register) {
+        self.registers(RegisterOperation::Compare, register, Register::R13);
+        self.emit(Instruction::Jump(Some(Condition::Below), self.slow));
+        self.registers(RegisterOperation::Compare, register, Register::R14);
+        self.emit(Instruction::Jump(Some(Condition::AboveOrEqual), self.slow));
+    }
+
+    /// Emits loading the current cell into `rax`, zero extended, and testing it for 0.
+    fn test_current(&mut self) {
+        self.operate(MemoryOperation::Load, Register::Rbx);
+        self.registers(RegisterOperation::Test, Register::Rax, Register::Rax);
+    }
+
+    /// Emits `operation` on the cell `base` points to with the low bits of `rax`.
+    fn operate(&mut self, operation: MemoryOperation, base: Register) {
+        self.emit(Instruction::Memory {
+            operation,
+            width: self.width,
+            register: Register::Rax,
+            base,
+        });
+    }
+
+    /// Emits `operation` on the cell `base` points to, unless it overflows.
+    /// An overflow is undone and falls through to the slow path, as it depends on the config.
+    fn change(&mut self, operation: MemoryOperation, base: Register) {
+        self.operate(operation, base);
+        if self.wraps || operation == MemoryOperation::Store {
+            self.emit(Instruction::Jump(None, self.done));
+            return;
+        }
+        self.emit(Instruction::Jump(Some(Condition::AboveOrEqual), self.done));
+        self.operate(undo(operation), base);
+    }
+
+    /// Emits `operation` between `register` and the field of the context at `offset`.
+    /// The field is addressed through `rax`, as memory operations take no displacement.
+    fn field(&mut self, operation: MemoryOperation, register: Register, offset: i32) {
+        self.emit(Instruction::LoadAddress {
+            destination: Register::Rax,
+            base: Register::R15,
+            displacement: offset,
+        });
+        self.emit(Instruction::Memory {
+            operation,
+            width: 8,
+            register,
+            base: Register::Rax,
+        });
+    }
+
+    /// Emits loading the cells from the context.
+    fn load_cells(&mut self) {
+        self.field(MemoryOperation::Load, Register::R13, START);
+        self.field(MemoryOperation::Load, Register::R14, END);
+        self.field(MemoryOperation::Load, Register::Rbx, CURRENT);
+    }
+
+    /// Emits calling the callback to run the command at `pc`, leaving the program if it fails.
+    fn call(&mut self, pc: usize) {
+        self.registers(RegisterOperation::Move, Register::Rdi, Register::R15);
+        self.registers(RegisterOperation::Move, Register::Rsi, Register::Rbx);
+        self.emit(Instruction::MoveImmediate(
+            Register::Rdx,
+            u64::try_from(pc).unwrap_or(u64::MAX),
+        ));
+        self.emit(Instruction::MoveImmediate(Register::Rax, self.callback));
+        self.emit(Instruction::CallAddress(Register::Rax));
+        self.registers(RegisterOperation::Test, Register::Rax, Register::Rax);
+        self.emit(Instruction::Jump(Some(Condition::NotEqual), self.exit));
+        self.load_cells();
+    }
+
+    /// Emits moving the pointer `amount` cells to the right, if it stays in the allocated cells.
+    fn move_pointer<C: Cell>(&mut self, amount: Option<isize>) {
+        if let Some(displacement) = amount.and_then(displacement::<C>) {
+            self.address(Register::Rax, displacement);
+            self.check_bounds(Register::Rax);
+            self.registers(RegisterOperation::Move, Register::Rbx, Register::Rax);
+            self.emit(Instruction::Jump(None, self.done));
+        }
+    }
+
+    /// Emits `operation` on the cell `offset` cells away, if it's allocated.
+    fn change_at<C: Cell>(&mut self, operation: MemoryOperation, offset: isize, value: C) {
+        if let Some(displacement) = displacement::<C>(offset) {
+            self.address(Register::Rcx, displacement);
+            self.check_bounds(Register::Rcx);
+            self.emit(Instruction::MoveImmediate(Register::Rax, value.to_u64()));
+            self.change(operation, Register::Rcx);
+        }
+    }
+
+    /// Emits `operation` with the current cell times `factor` on the cell `offset` cells away.
+    fn multiply<C: Cell>(&mut self, operation: MemoryOperation, offset: isize, factor: C) {
+        // Only products that wrap can be computed without checking for overflow
+        if let Some(displacement) = displacement::<C>(offset).filter(|_| self.wraps) {
+            // Nothing happens if the current cell is 0, not even a check of the offset
+            self.test_current();
+            self.emit(Instruction::Jump(Some(Condition::Equal), self.done));
+            self.address(Register::Rcx, displacement);
+            self.check_bounds(Register::Rcx);
+            self.emit(Instruction::MoveImmediate(Register::Rdx, factor.to_u64()));
+            self.registers(RegisterOperation::MultiplyLow, Register::Rax, Register::Rdx);
+            self.change(operation, Register::Rcx);
+        }
+    }
+
+    /// Emits the fast path of `command`, returns false if it doesn't need a slow path.
+    fn fast_path<C: Cell>(&mut self, command: &OptimizedCommand<C>) -> bool {
+        match command {
+            OptimizedCommand::AddPointer(amount) => {
+                self.move_pointer::<C>(isize::try_from(*amount).ok());
+            }
+            OptimizedCommand::SubtractPointer(amount) => {
+                self.move_pointer::<C>(isize::try_from(*amount).ok().map(isize::wrapping_neg));
+            }
+            OptimizedCommand::AddValue(value) | OptimizedCommand::SubtractValue(value) => {
+                let operation = if matches!(command, OptimizedCommand::AddValue(_)) {
+                    MemoryOperation::Add
+                } else {
+                    MemoryOperation::Subtract
+                };
+                self.emit(Instruction::MoveImmediate(Register::Rax, value.to_u64()));
+                if self.wraps {
+                    self.operate(operation, Register::Rbx);
+                    return false;
+                }
+                self.change(operation, Register::Rbx);
+            }
+            OptimizedCommand::SetValue(value) => {
+                self.emit(Instruction::MoveImmediate(Register::Rax, value.to_u64()));
+                self.operate(MemoryOperation::Store, Register::Rbx);
+                return false;
+            }
+            OptimizedCommand::AddAt { offset, value } => {
+                self.change_at(MemoryOperation::Add, *offset, *value);
+            }
+            OptimizedCommand::SubtractAt { offset, value } => {
+                self.change_at(MemoryOperation::Subtract, *offset, *value);
+            }
+            OptimizedCommand::SetAt { offset, value } => {
+                self.change_at(MemoryOperation::Store, *offset, *value);
+            }
+            OptimizedCommand::MultiplyAdd { offset, factor } => {
+                self.multiply(MemoryOperation::Add, *offset, *factor);
+            }
+            OptimizedCommand::MultiplySubtract { offset, factor } => {
+                self.multiply(MemoryOperation::Subtract, *offset, *factor);
+            }
+            OptimizedCommand::StartOfLoop { .. } => {
+                let (body, end) = (self.assembly.label(), self.assembly.label());
+                self.test_current();
+                self.emit(Instruction::Jump(Some(Condition::Equal), end));
+                self.emit(Instruction::Label(body));
+                self.open_loops.push((body, end));
+                return false;
+            }
+            OptimizedCommand::EndOfLoop { .. } => {
+                let (body, end) = self.open_loops.pop().expect("The loops are balanced");
+                self.test_current();
+                self.emit(Instruction::Jump(Some(Condition::NotEqual), body));
+                self.emit(Instruction::Label(end));
+                return false;
+            }
+            // Input, output and scans always run through the callback
+            OptimizedCommand::ScanRight(_)
+            | OptimizedCommand::ScanLeft(_)
+            | OptimizedCommand::OutputAt { .. }
+            | OptimizedCommand::Output
+            | OptimizedCommand::OutputBytes(_)
+            | OptimizedCommand::Input => {}
+        }
+        true
+    }
+
+    /// Compiles the commands into the machine code of a [`Function`].
+    fn compile<C: Cell>(program: &OptimizedProgram<C>) -> Vec<u8> {
+        #[expect(
+            clippy::as_conversions,
+            clippy::fn_to_numeric_cast_any,
+            reason = "The machine code needs the address of the callback"
+        )]
+        let callback = (execute_command::<C> as Callback<C>) as usize;
+        let mut assembly = Assembly::default();
+        let exit = assembly.label();
+        let mut compiler = Self {
+            assembly,
+            width: size_of::<C>(),
+            wraps: program.config().overflow == Overflow::Wrap,
+            callback: u64::try_from(callback).unwrap_or(u64::MAX),
+            // Every command gets its own labels
+            slow: exit,
+            done: exit,
+            open_loops: Vec::new(),
+            exit,
+        };
+
+        // Pushing 5 registers also aligns the stack for calls
+        for register in [
+            Register::Rbx,
+            Register::R12,
+            Register::R13,
+            Register::R14,
+            Register::R15,
+        ] {
+            compiler.emit(Instruction::Push(register));
+        }
+        compiler.registers(RegisterOperation::Move, Register::R15, Register::Rdi);
+        compiler.load_cells();
+
+        for (pc, command) in program.commands().iter().enumerate() {
+            compiler.slow = compiler.assembly.label();
+            compiler.done = compiler.assembly.label();
+            if compiler.fast_path(command) {
+                compiler.emit(Instruction::Label(compiler.slow));
+                compiler.call(pc);
+            }
+            compiler.emit(Instruction::Label(compiler.done));
+        }
+
+        // Store the current cell and pop the registers in reverse order
+        compiler.emit(Instruction::Label(exit));
+        compiler.field(MemoryOperation::Store, Register::Rbx, CURRENT);
+        for register in [
+            Register::R15,
+            Register::R14,
+            Register::R13,
+            Register::R12,
+            Register::Rbx,
+        ] {
+            compiler.emit(Instruction::Pop(register));
+        }
+        compiler.emit(Instruction::Return);
+
+        // The code only jumps within itself, so it runs at any address
+        compiler.assembly.encode(0, &|_| 0)
+    }
+}
+
+/// A brainfuck program compiled to x86-64 machine code.
+///
+/// The machine code handles pointer moves, value changes and loops itself, and calls back into
+/// the [`OptimizedProgram`] for input, output, scans and anything that leaves the allocated cells
+/// or overflows, so the program behaves exactly like the optimized program.
+#[derive(Debug)]
+pub struct JitProgram<C: Cell = u8> {
+    program: OptimizedProgram<C>,
+    code: ExecutableMemory,
+}
+
+impl<C: Cell> TryFrom<OptimizedProgram<C>> for JitProgram<C> {
+    type Error = Error;
+
+    fn try_from(program: OptimizedProgram<C>) -> Result<Self, Self::Error> {
+        let code = ExecutableMemory::new(&Compiler::compile(&program))?;
+        Ok(Self { program, code })
+    }
+}
+
+impl<C: Cell> JitProgram<C> {
+    /// Returns the tape as it was left by the last execution.
+    #[must_use]
+    pub const fn tape(&self) -> &Tape<C> {
+        self.program.tape()
+    }
+
+    /// Runs the machine code in `code` with `context`.
+    fn call(code: &ExecutableMemory, context: &mut Context<'_, C>) {
+        // SAFETY: The memory holds the machine code of a `Function`, compiled for this program
+        let function = unsafe { mem::transmute::<*mut c_void, Function<C>>(code.address.as_ptr()) };
+        // SAFETY: The context points to the cells of the program, like the machine code expects
+        unsafe { function(context) }
+    }
+
+    /// Runs the program, reading bytes from `input` and writing bytes to `output`.
+    ///
+    /// # Errors
+    /// Returns an error if the input runs out or reading input or writing output fails.
+    pub fn execute<Input: Read, Output: Write>(
+        &mut self,
+        mut input: Input,
+        mut output: Output,
+    ) -> Result<(), Error> {
+        *self.program.tape_mut() = Tape::new(self.program.config().tape);
+        let mut context = Context {
+            start: ptr::null_mut(),
+            end: ptr::null_mut(),
+            current: ptr::null_mut(),
+            program: &mut self.program,
+            input: &mut input,
+            output: &mut output,
+            error: None,
+        };
+        context.refresh();
+
+        Self::call(&self.code, &mut context);
+        let result = match context.error.take() {
+            None => {
+                context.sync(context.current);
+                Ok(())
+            }
+            Some(error) => Err(error),
+        };
+
+        // Flush the output
+        output.flush()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cell::Cell,
+        compile_optimized_with,
+        config::{Config, Edge, EndOfInput, Overflow, TapeMode},
+        parse,
+        passes::{Pass, PassManager},
+    };
+
+    use super::JitProgram;
+
+    /// Runs `source` with the JIT and the optimized interpreter and checks they behave the same.
+    /// The start of the program isn't run at compile time, so the JIT runs every command.
+    /// Returns the output and the error message, if any.
+    fn compare<C: Cell>(source: &[u8], config: Config) -> (Vec<u8>, Option<String>) {
+        let passes = PassManager::default().without_pass(Pass::Constants);
+        let compile = || {
+            compile_optimized_with::<C, _>(parse(source.iter().copied()), config, &passes)
+                .expect("The loops are balanced")
+        };
+        let mut expected = compile();
+        let mut jit = JitProgram::try_from(compile()).expect("The machine code can be mapped");
+
+        let input = b"Hello, JIT!";
+        let (mut expected_output, mut output) = (Vec::new(), Vec::new());
+        let expected_result = expected.execute(&input[..], &mut expected_output);
+        let result = jit.execute(&input[..], &mut output);
+        assert_eq!(output, expected_output);
+        assert_eq!(jit.tape().cells(), expected.tape().cells());
+        assert_eq!(jit.tape().position(), expected.tape().position());
+        let error = result.err().as_ref().map(ToString::to_string);
+        assert_eq!(
+            error,
+            expected_result.err().as_ref().map(ToString::to_string)
+        );
+        (output, error)
+    }
+
+    #[test]
+    fn same_as_optimized() {
+        let config = Config {
+            end_of_input: EndOfInput::Zero,
+            ..Config::default()
+        };
+        let hello = compare::<u8>(
+            b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.",
+            config,
+        );
+        assert_eq!(hello, (b"Hello".to_vec(), None));
+        let echo = compare::<u8>(b",[.,]", config);
+        assert_eq!(echo, (b"Hello, JIT!".to_vec(), None));
+        compare::<u16>(b",[>+++[->++<]<-]>>[<<+>>-]<<[>]", config);
+        compare::<u64>(b"+++++++[>+++++++<-]>.<<,[>>+<<-]>>[<<+>>-]<[-]-", config);
+    }
+
+    #[test]
+    fn tapes_and_overflow() {
+        let sources: [&[u8]; 4] = [
+            b"<+<+<+[>]",
+            b"-[>+<-]>[>>>>>>>>>+<<<<<<<<<-]",
+            b",[>,]<<<<<<<<<<<<<<[-]",
+            b",[->++++<]>[-<+>]<[>]",
+        ];
+        let tapes = [
+            TapeMode::BiInfinite,
+            TapeMode::RightInfinite,
+            TapeMode::Bounded {
+                size: 8,
+                edge: Edge::Trap,
+            },
+            TapeMode::Bounded {
+                size: 8,
+                edge: Edge::Wrap,
+            },
+        ];
+        for source in sources {
+            for tape in tapes {
+                for overflow in [Overflow::Wrap, Overflow::Saturate, Overflow::Trap] {
+                    let config = Config {
+                        overflow,
+                        end_of_input: EndOfInput::Zero,
+                        tape,
+                    };
+                    // Some combinations fail, which only has to happen like in the interpreter
+                    compare::<u8>(source, config);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,367 @@
+#![warn(clippy::pedantic, clippy::nursery, clippy::restriction)]
+#![allow(
+    clippy::missing_docs_in_private_items,
+    clippy::blanket_clippy_restriction_lints,
+    clippy::expect_used,
+    clippy::implicit_return,
+    clippy::missing_trait_methods,
+    clippy::arithmetic_side_effects,
+    clippy::default_numeric_fallback,
+    clippy::shadow_reuse,
+    clippy::indexing_slicing,
+    clippy::pattern_type_mismatch,
+    clippy::separated_literal_suffix,
+    clippy::single_call_fn,
+    clippy::wildcard_enum_match_arm,
+    clippy::use_debug,
+    clippy::min_ident_chars,
+    clippy::panic,
+    clippy::arbitrary_source_item_ordering,
+    clippy::missing_inline_in_public_items,
+    clippy::exhaustive_enums,
+    clippy::exhaustive_structs,
+    clippy::question_mark_used,
+    clippy::integer_division_remainder_used,
+    clippy::self_named_module_files,
+    reason = ""
+)]
+
+//! A brainfuck interpreter that can be embedded in other Rust programs.
+//!
+//! Source code is parsed into [`Token`]s, which can either be compiled into a [`Program`]
+//! or optimized into [`OptimizedCommand`]s and compiled into an [`OptimizedProgram`].
+//! Both programs can then be executed.
+//!
+//! An optimized program can be compiled further into a [`BytecodeProgram`], which packs the
+//! commands into a compact bytecode.
+//! On x86-64 Linux, it can also be compiled into native machine code as a
+//! [`jit_program::JitProgram`].
+//! The [`emit`] backends translate an optimized program into other languages instead.
+//!
+//! Optimizing builds a tree of [`ir::Node`]s, runs the [`passes`] over it and lowers the result
+//! into commands.
+
+pub mod bytecode_program;
+pub mod cell;
+pub mod chars;
+pub mod command;
+pub mod config;
+pub mod emit;
+pub mod error;
+pub mod ir;
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub mod jit_program;
+pub mod optimized_command;
+pub mod optimized_program;
+pub mod passes;
+pub mod position;
+pub mod program;
+pub mod tape;
+
+use bytecode_program::BytecodeProgram;
+use cell::Cell;
+use chars::{Chars, Decoding};
+use command::{Command, Token};
+use config::Config;
+use error::Error;
+use optimized_command::OptimizedCommand;
+use optimized_program::OptimizedProgram;
+use passes::PassManager;
+use position::Positions;
+use program::Program;
+
+/// Parses UTF-8 brainfuck source code into commands with their positions, ignoring every other
+/// character.
+pub fn parse<Bytes: IntoIterator<Item = u8>>(source: Bytes) -> impl Iterator<Item = Token> {
+    parse_with(source, Decoding::default())
+}
+
+/// Parses brainfuck source code decoded as specified by `decoding` into commands with their
+/// positions, ignoring every other character.
+pub fn parse_with<Bytes: IntoIterator<Item = u8>>(
+    source: Bytes,
+    decoding: Decoding,
+) -> impl Iterator<Item = Token> {
+    Positions::from(Chars::new(source.into_iter(), decoding)).filter_map(|(position, character)| {
+        Command::try_from(character)
+            .ok()
+            .map(|command| Token { command, position })
+    })
+}
+
+/// Collects the tokens and strips their positions after making sure all loops are balanced.
+fn check<Code: IntoIterator<Item = Token>>(tokens: Code) -> Result<Vec<Command>, Error> {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+    Token::check_loops(&tokens)?;
+    Ok(tokens.into_iter().map(|token| token.command).collect())
+}
+
+/// Compiles the program without optimizations, running with the given `config`.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced.
+pub fn compile<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+) -> Result<Program<C>, Error> {
+    // Collect the commands into a buffer and store it as a program, if valid
+    Ok(Program::try_from(check(tokens)?)?.with_config(config))
+}
+
+/// Compiles the program with every optimization pass, an optimized program may not always work as
+/// expected.
+/// The optimizations respect the given `config`, which the program will run with.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced.
+pub fn compile_optimized<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+) -> Result<OptimizedProgram<C>, Error> {
+    compile_optimized_with(tokens, config, &PassManager::default())
+}
+
+/// Compiles the program, only running the optimization passes enabled in `passes`.
+/// The optimizations respect the given `config`, which the program will run with.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced.
+pub fn compile_optimized_with<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+    passes: &PassManager,
+) -> Result<OptimizedProgram<C>, Error> {
+    // Turn the commands into optimized commands and store it in a program, if valid
+    Ok(
+        OptimizedProgram::try_from(OptimizedCommand::optimize_commands_with(
+            check(tokens)?.into_iter(),
+            config,
+            passes,
+        )?)?
+        .with_config(config),
+    )
+}
+
+/// Compiles the program with every optimization pass into bytecode.
+/// The optimizations respect the given `config`, which the program will run with.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced, or if
+/// the program is too large for the bytecode.
+pub fn compile_bytecode<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+) -> Result<BytecodeProgram<C>, Error> {
+    compile_bytecode_with(tokens, config, &PassManager::default())
+}
+
+/// Compiles the program into bytecode, only running the optimization passes enabled in `passes`.
+/// The optimizations respect the given `config`, which the program will run with.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced, or if
+/// the program is too large for the bytecode.
+pub fn compile_bytecode_with<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+    passes: &PassManager,
+) -> Result<BytecodeProgram<C>, Error> {
+    BytecodeProgram::try_from(&compile_optimized_with(tokens, config, passes)?)
+}
+
+/// Compiles the program with every optimization pass into native machine code.
+/// The optimizations respect the given `config`, which the program will run with.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced, or if
+/// the memory for the machine code can't be mapped.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub fn compile_jit<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+) -> Result<jit_program::JitProgram<C>, Error> {
+    compile_jit_with(tokens, config, &PassManager::default())
+}
+
+/// Compiles the program into native machine code, only running the optimization passes enabled in
+/// `passes`.
+/// The optimizations respect the given `config`, which the program will run with.
+///
+/// # Errors
+/// Returns an error pointing at the first unmatched bracket if the loops aren't balanced, or if
+/// the memory for the machine code can't be mapped.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+pub fn compile_jit_with<C: Cell, Code: IntoIterator<Item = Token>>(
+    tokens: Code,
+    config: Config,
+    passes: &PassManager,
+) -> Result<jit_program::JitProgram<C>, Error> {
+    jit_program::JitProgram::try_from(compile_optimized_with(tokens, config, passes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        bytecode_program::BytecodeProgram,
+        compile, compile_optimized, compile_optimized_with,
+        config::{Config, Edge, Overflow, TapeMode},
+        error::Error,
+        parse,
+        passes::{Pass, PassManager},
+    };
+
+    /// Every tape mode, with bounded tapes small enough for programs to reach their edges.
+    const TAPES: [TapeMode; 4] = [
+        TapeMode::BiInfinite,
+        TapeMode::RightInfinite,
+        TapeMode::Bounded {
+            size: 2,
+            edge: Edge::Trap,
+        },
+        TapeMode::Bounded {
+            size: 2,
+            edge: Edge::Wrap,
+        },
+    ];
+
+    /// Programs that move past the edges of the tape, nest loops, multiply, clear, scan and read
+    /// input.
+    const PROGRAMS: [&[u8]; 10] = [
+        b"++[>+++[>+<-]<-]>>.",
+        b"+++++[-]>.<.",
+        b",[>,]<[.<]",
+        b"+>>+<<[>]<.",
+        b"<+[<]>.",
+        b"-[>+<-]>.",
+        b"+[->++<]>[-<+>]<.",
+        b",+[-.]",
+        b"+++[>+<-]>>>><<<<[-]+.",
+        b">>>+<<<<.",
+    ];
+
+    /// The number of steps a program runs unoptimized before it's assumed to never halt.
+    const STEP_LIMIT: u64 = 1 << 20;
+
+    /// Every config that changes how cells and the pointer move.
+    fn configs() -> impl Iterator<Item = Config> {
+        TAPES.into_iter().flat_map(|tape| {
+            [Overflow::Wrap, Overflow::Saturate, Overflow::Trap]
+                .into_iter()
+                .map(move |overflow| Config {
+                    overflow,
+                    tape,
+                    ..Config::default()
+                })
+        })
+    }
+
+    /// Returns the message of `error` without the command it was reported at.
+    fn without_pc(error: &Error) -> String {
+        match error {
+            Error::Overflow { cell, .. } => Error::Overflow { pc: 0, cell: *cell },
+            Error::TapeUnderflow { .. } => Error::TapeUnderflow { pc: 0 },
+            Error::TapeOverflow { .. } => Error::TapeOverflow { pc: 0 },
+            Error::StepLimit { .. } => Error::StepLimit { pc: 0 },
+            other => return other.to_string(),
+        }
+        .to_string()
+    }
+
+    /// Checks that `source` writes the same output and fails the same way unoptimized and
+    /// optimized with `passes`, although errors can be reported at other commands.
+    /// Programs that don't halt within [`STEP_LIMIT`] steps unoptimized are skipped.
+    fn compare(source: &[u8], config: Config, passes: &PassManager) {
+        let mut program = compile::<u8, _>(parse(source.iter().copied()), config)
+            .expect("The loops are balanced")
+            .with_step_limit(STEP_LIMIT);
+        let mut optimized =
+            compile_optimized_with::<u8, _>(parse(source.iter().copied()), config, passes)
+                .expect("The loops are balanced")
+                .with_step_limit(STEP_LIMIT);
+        let (mut expected_output, mut output) = (Vec::new(), Vec::new());
+        let expected = program.execute(&b"ab"[..], &mut expected_output);
+        if matches!(expected, Err(Error::StepLimit { .. })) {
+            return;
+        }
+        let actual = optimized.execute(&b"ab"[..], &mut output);
+        assert_eq!(
+            (output, actual.as_ref().err().map(without_pc)),
+            (expected_output, expected.as_ref().err().map(without_pc)),
+            "{} with {config:?} and {passes:?}: {actual:?} instead of {expected:?}",
+            String::from_utf8_lossy(source)
+        );
+    }
+
+    #[test]
+    fn turning_at_edges() {
+        let combine = PassManager::new().with_pass(Pass::Combine);
+        for source in [&b"+<>."[..], b">>><<<+.", b"><<>", b"<>--."] {
+            for tape in TAPES {
+                compare(
+                    source,
+                    Config {
+                        tape,
+                        ..Config::default()
+                    },
+                    &combine,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn optimizing_on_every_tape() {
+        let passes = PassManager::default();
+        for source in PROGRAMS {
+            for config in configs() {
+                compare(source, config, &passes);
+            }
+        }
+    }
+
+    #[test]
+    fn every_pass_on_every_tape() {
+        for pass in Pass::ALL {
+            let passes = PassManager::new().with_pass(pass);
+            for source in PROGRAMS {
+                for config in configs() {
+                    compare(source, config, &passes);
+                }
+            }
+        }
+    }
+    #[test]
+    fn endless_scans() {
+        let config = Config {
+            tape: TapeMode::Bounded {
+                size: 3,
+                edge: Edge::Wrap,
+            },
+            ..Config::default()
+        };
+        for source in [&b"+>+>+[>]"[..], b"+>+>+[<<]", b"+>+>+[>>>]"] {
+            let mut program = compile::<u8, _>(parse(source.iter().copied()), config)
+                .expect("The loops are balanced")
+                .with_step_limit(STEP_LIMIT);
+            let mut optimized = compile_optimized::<u8, _>(parse(source.iter().copied()), config)
+                .expect("The loops are balanced")
+                .with_step_limit(STEP_LIMIT);
+            let mut bytecode = BytecodeProgram::try_from(&optimized)
+                .expect("The program is small enough")
+                .with_step_limit(STEP_LIMIT);
+            for result in [
+                program.execute(io::empty(), io::sink()),
+                optimized.execute(io::empty(), io::sink()),
+                bytecode.execute(io::empty(), io::sink()),
+            ] {
+                assert!(
+                    matches!(result, Err(Error::StepLimit { .. })),
+                    "{} gave {result:?}",
+                    String::from_utf8_lossy(source)
+                );
+            }
+        }
+    }
+}
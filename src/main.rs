@@ -12,81 +12,344 @@
     clippy::indexing_slicing,
     clippy::pattern_type_mismatch,
     clippy::separated_literal_suffix,
-    clippy::match_on_vec_items,
     clippy::single_call_fn,
     clippy::wildcard_enum_match_arm,
     clippy::use_debug,
     clippy::min_ident_chars,
     clippy::panic,
+    clippy::question_mark_used,
     reason = ""
 )]
 
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt as _;
 use std::{
     env::args,
-    fs::File,
-    io::{BufReader, Read},
+    fs,
+    io::{self, Write},
+    process::ExitCode,
     time::Instant,
 };
 
-use chars::Chars;
-use command::Command;
-use optimized_command::OptimizedCommand;
-use optimized_program::OptimizedProgram;
-use program::Program;
-
-mod chars;
-mod command;
-mod optimized_command;
-mod optimized_program;
-mod program;
-
-/// Compiles the program without optimizations.
-fn unoptimized_compiler<Code: Iterator<Item = Command>>(commands: Code) -> Program {
-    // Collect the commands into a buffer and store it as a program, if valid
-    Program::from(commands.collect::<Vec<_>>())
+use brainfuck::{
+    cell::Cell,
+    chars::{Chars, Decoding, InvalidUtf8},
+    command::Token,
+    config::{Config, Edge, EndOfInput, Overflow, TapeMode},
+    emit,
+    error::Error,
+    optimized_program::OptimizedProgram,
+    passes::{Pass, PassManager},
+};
+
+/// A language the program can be translated into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Asm,
+    C,
+    /// A static ELF64 executable for x86-64 Linux.
+    Elf,
+    Rust,
+    /// A binary WebAssembly module.
+    Wasm,
+    Wat,
+}
+
+/// What runs the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    Bytecode,
+    /// Writes the program in another language to the output or a file instead of running it.
+    Emit(Target),
+    Interpreter,
+    Jit,
+}
+
+/// Writes the program translated into the `target` language to the file at `path`, or `output` if
+/// there is none.
+fn emit<C: Cell>(
+    program: &OptimizedProgram<C>,
+    target: Target,
+    path: Option<&str>,
+    mut output: impl Write,
+) -> Result<(), Error> {
+    let translation = match target {
+        Target::Asm => emit::asm::Source::new(program)?.to_string().into_bytes(),
+        Target::C => emit::c::Source::new(program).to_string().into_bytes(),
+        Target::Elf => emit::elf::executable(program)?,
+        Target::Rust => emit::rust::Source::new(program).to_string().into_bytes(),
+        Target::Wasm => emit::wasm::module(program)?,
+        Target::Wat => emit::wat::Source::new(program)?.to_string().into_bytes(),
+    };
+    let Some(path) = path else {
+        output.write_all(&translation)?;
+        return Ok(());
+    };
+    fs::write(path, translation)?;
+
+    // Executables can run right away
+    #[cfg(unix)]
+    if target == Target::Elf {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Compiles and runs the program with the `engine`, only optimizing it if any passes are enabled or
+/// it doesn't run in the interpreter.
+/// The interpreters stop after `step_limit` steps, and emitted programs are written to the file at
+/// `path` if there is one.
+fn run<C: Cell, Code: Iterator<Item = Token>>(
+    commands: Code,
+    passes: &PassManager,
+    engine: Engine,
+    config: Config,
+    step_limit: u64,
+    path: Option<&str>,
+) -> Result<(), Error> {
+    let (input, output) = (io::stdin().lock(), io::stdout().lock());
+    match engine {
+        Engine::Interpreter if passes.passes().is_empty() => {
+            brainfuck::compile::<C, _>(commands, config)?
+                .with_step_limit(step_limit)
+                .execute(input, output)
+        }
+        Engine::Interpreter => brainfuck::compile_optimized_with::<C, _>(commands, config, passes)?
+            .with_step_limit(step_limit)
+            .execute(input, output),
+        Engine::Bytecode => brainfuck::compile_bytecode_with::<C, _>(commands, config, passes)?
+            .with_step_limit(step_limit)
+            .execute(input, output),
+        Engine::Emit(target) => emit(
+            &brainfuck::compile_optimized_with::<C, _>(commands, config, passes)?,
+            target,
+            path,
+            output,
+        ),
+        #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+        Engine::Jit => {
+            brainfuck::compile_jit_with::<C, _>(commands, config, passes)?.execute(input, output)
+        }
+        #[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+        Engine::Jit => panic!("The JIT only runs on x86-64 Linux"),
+    }
+}
+
+/// Checks that the options for some engines are only given with those engines.
+fn check_engine_options(engine: Engine, step_limit: Option<u64>, path: Option<&str>) {
+    assert!(
+        step_limit.is_none() || matches!(engine, Engine::Interpreter | Engine::Bytecode),
+        "The step limit only applies to the interpreters"
+    );
+    assert!(
+        path.is_none() || matches!(engine, Engine::Emit(_)),
+        "An output file only applies to emitted programs"
+    );
+}
+
+/// Parses an overflow behavior: `wrap`, `saturate` or `trap`.
+fn parse_overflow(argument: Option<&str>) -> Overflow {
+    match argument {
+        Some("wrap") => Overflow::Wrap,
+        Some("saturate") => Overflow::Saturate,
+        Some("trap") => Overflow::Trap,
+        _ => panic!("The overflow behavior should be wrap, saturate or trap"),
+    }
 }
 
-/// Compiles the program, an optimized program may not always work as expected
-fn optimized_compiler<Code: Iterator<Item = Command>>(commands: Code) -> OptimizedProgram {
-    // Turn the commands into optimized commands and store it in a program, if valid
-    OptimizedProgram::from(OptimizedCommand::optimize_commands(commands))
+/// Parses an end of input behavior: `0`, `-1`, `unchanged` or `error`.
+fn parse_end_of_input(argument: Option<&str>) -> EndOfInput {
+    match argument {
+        Some("0") => EndOfInput::Zero,
+        Some("-1") => EndOfInput::MinusOne,
+        Some("unchanged") => EndOfInput::Unchanged,
+        Some("error") => EndOfInput::Trap,
+        _ => panic!("The end of input behavior should be 0, -1, unchanged or error"),
+    }
+}
+
+/// Parses how the source is decoded: `replace`, `skip` or `bytes`.
+fn parse_decoding(argument: Option<&str>) -> Decoding {
+    match argument {
+        Some("replace") => Decoding::Utf8(InvalidUtf8::Replace),
+        Some("skip") => Decoding::Utf8(InvalidUtf8::Skip),
+        Some("bytes") => Decoding::Bytes,
+        _ => panic!("The decoding should be replace, skip or bytes"),
+    }
+}
+
+/// Parses a tape mode: `bi-infinite`, `right-infinite`, `bounded[=size]` or `wrapping[=size]`.
+fn parse_tape(argument: Option<String>) -> TapeMode {
+    let argument = argument.expect("Missing tape mode");
+    let (mode, size) = argument
+        .split_once('=')
+        .map_or((argument.as_str(), None), |(mode, size)| {
+            (mode, Some(size.parse().expect("Invalid tape size")))
+        });
+    let size = size.unwrap_or(TapeMode::CLASSIC_SIZE);
+    assert!(size > 0, "The tape should have at least 1 cell");
+    match mode {
+        "bi-infinite" => TapeMode::BiInfinite,
+        "right-infinite" => TapeMode::RightInfinite,
+        "bounded" => TapeMode::Bounded {
+            size,
+            edge: Edge::Trap,
+        },
+        "wrapping" => TapeMode::Bounded {
+            size,
+            edge: Edge::Wrap,
+        },
+        _ => panic!("The tape mode should be bi-infinite, right-infinite, bounded or wrapping"),
+    }
+}
+
+/// Parses the language to emit the program in: `asm`, `c`, `elf`, `rust`, `wasm` or `wat`.
+fn parse_target(argument: Option<&str>) -> Target {
+    match argument {
+        Some("asm") => Target::Asm,
+        Some("c") => Target::C,
+        Some("elf") => Target::Elf,
+        Some("rust") => Target::Rust,
+        Some("wasm") => Target::Wasm,
+        Some("wat") => Target::Wat,
+        _ => panic!("The language to emit should be asm, c, elf, rust, wasm or wat"),
+    }
 }
 
-fn main() {
-    // Read the name of the brainfuck file and check whether the code should be optimized
+/// Parses the name of an optimization pass.
+fn parse_pass(argument: Option<&str>) -> Pass {
+    match argument {
+        Some("combine") => Pass::Combine,
+        Some("multiply-loops") => Pass::MultiplyLoops,
+        Some("clear-loops") => Pass::ClearLoops,
+        Some("scan-loops") => Pass::ScanLoops,
+        Some("dead-code") => Pass::DeadCode,
+        Some("constants") => Pass::Constants,
+        Some("offsets") => Pass::Offsets,
+        _ => panic!(
+            "The pass should be combine, multiply-loops, clear-loops, scan-loops, dead-code, \
+             constants or offsets"
+        ),
+    }
+}
+
+/// Starts from the passes of the optimization `level` and toggles passes in the given order.
+fn select_passes(level: u8, toggled_passes: Vec<(Pass, bool)>) -> PassManager {
+    toggled_passes
+        .into_iter()
+        .fold(PassManager::level(level), |passes, (pass, enabled)| {
+            if enabled {
+                passes.with_pass(pass)
+            } else {
+                passes.without_pass(pass)
+            }
+        })
+}
+
+fn main() -> ExitCode {
+    // Read the name of the brainfuck file and check which optimization passes should run
     let mut file_name = None;
-    let mut optimization = false;
+    let mut level = None;
+    let mut toggled_passes = Vec::new();
     let mut performance = false;
-    for argument in args().skip(1).take(3) {
+    let mut step_limit = None;
+    let mut engine = Engine::Interpreter;
+    let mut cell_bits = None;
+    let mut overflow = None;
+    let mut end_of_input = None;
+    let mut tape = None;
+    let mut decoding = None;
+    let mut path = None;
+    let mut arguments = args().skip(1);
+    while let Some(argument) = arguments.next() {
         match argument.as_str() {
-            "-O" if !optimization => optimization = true,
+            "-O" if level.is_none() => level = Some(PassManager::MAX_LEVEL),
+            "-O0" | "-O1" | "-O2" | "-O3" if level.is_none() => {
+                level = argument
+                    .strip_prefix("-O")
+                    .and_then(|digit| digit.parse().ok());
+            }
+            "--enable-pass" => {
+                toggled_passes.push((parse_pass(arguments.next().as_deref()), true));
+            }
+            "--disable-pass" => {
+                toggled_passes.push((parse_pass(arguments.next().as_deref()), false));
+            }
             "-p" if !performance => performance = true,
+            "--bytecode" if engine == Engine::Interpreter => engine = Engine::Bytecode,
+            "--jit" if engine == Engine::Interpreter => engine = Engine::Jit,
+            "--emit" if engine == Engine::Interpreter => {
+                engine = Engine::Emit(parse_target(arguments.next().as_deref()));
+            }
+            "-o" if path.is_none() => {
+                path = arguments.next();
+                assert!(path.is_some(), "Missing output file");
+            }
+            "--cell-bits" if cell_bits.is_none() => {
+                cell_bits = arguments.next().map(|bits| bits.parse::<u32>());
+                assert!(cell_bits.is_some(), "Missing number of bits per cell");
+            }
+            "--overflow" if overflow.is_none() => {
+                overflow = Some(parse_overflow(arguments.next().as_deref()));
+            }
+            "--eof" if end_of_input.is_none() => {
+                end_of_input = Some(parse_end_of_input(arguments.next().as_deref()));
+            }
+            "--step-limit" if step_limit.is_none() => {
+                step_limit = Some(
+                    arguments
+                        .next()
+                        .and_then(|limit| limit.parse().ok())
+                        .expect("The step limit should be a number"),
+                );
+            }
+            "--tape" if tape.is_none() => tape = Some(parse_tape(arguments.next())),
+            "--decoding" if decoding.is_none() => {
+                decoding = Some(parse_decoding(arguments.next().as_deref()));
+            }
             _ if file_name.is_none() => file_name = Some(argument),
             _ => panic!("Unexpected argument: {argument}"),
         }
     }
     let file_name = file_name.expect("No filename found");
+    let path = path.as_deref();
+    check_engine_options(engine, step_limit, path);
+    let step_limit = step_limit.unwrap_or(u64::MAX);
+    let config = Config {
+        overflow: overflow.unwrap_or_default(),
+        end_of_input: end_of_input.unwrap_or_default(),
+        tape: tape.unwrap_or_default(),
+    };
+
+    let passes = select_passes(level.unwrap_or(0), toggled_passes);
 
     // Read the brainfuck file
-    let file = File::open(file_name).expect("Failed to open file");
-    let reader = BufReader::new(file);
+    let source = fs::read(&file_name).expect("Failed to read file");
 
     // Create an iterator to read the commands
-    let commands = Chars::from(reader.bytes().map_while(Result::ok))
-        .map(Command::try_from)
-        .filter_map(Result::ok);
+    let decoding = decoding.unwrap_or_default();
+    let commands = brainfuck::parse_with(source.iter().copied(), decoding);
 
     let start = performance.then(Instant::now);
 
-    // Compile and run the program, only optimize if requested
-    if optimization {
-        optimized_compiler(commands).execute();
-    } else {
-        unoptimized_compiler(commands).execute();
-    }
+    // Compile and run the program with the requested cell type, only optimize if requested
+    let result = match cell_bits.unwrap_or(Ok(u8::BITS)) {
+        Ok(u8::BITS) => run::<u8, _>(commands, &passes, engine, config, step_limit, path),
+        Ok(u16::BITS) => run::<u16, _>(commands, &passes, engine, config, step_limit, path),
+        Ok(u32::BITS) => run::<u32, _>(commands, &passes, engine, config, step_limit, path),
+        Ok(u64::BITS) => run::<u64, _>(commands, &passes, engine, config, step_limit, path),
+        _ => panic!("The number of bits per cell should be 8, 16, 32 or 64"),
+    };
 
     eprintln!();
+    if let Err(error) = result {
+        // Decode the snippet like the parser did, so the caret lines up with the columns it counted
+        let source = Chars::new(source.iter().copied(), decoding).collect::<String>();
+        eprintln!("{}", error.diagnostic(&file_name, &source));
+        return ExitCode::FAILURE;
+    }
     if let Some(start) = start {
         eprintln!("{:?}", start.elapsed());
     }
+    ExitCode::SUCCESS
 }
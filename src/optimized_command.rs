@@ -1,212 +1,178 @@
-use crate::command::Command;
+use crate::{
+    cell::Cell, command::Command, config::Config, error::Error, ir::Node, passes::PassManager,
+};
 
 /// A command type that can represent multiple brainfuck commands with 1 command.
-/// It also stores the start and end of each loop as indices to make jumps faster.
-/// This can save calculations on execution, but doesn't work correctly for all programs yet.
-#[derive(Debug, PartialEq, Eq)]
-pub enum OptimizedCommand {
+///
+/// The commands are lowered from the optimized IR, see [`Node`] for what they do.
+/// Loops are flattened and store the start and end of each loop as indices to make jumps faster.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptimizedCommand<C: Cell = u8> {
     AddPointer(usize),
     SubtractPointer(usize),
-    AddValue(u8),
-    SubtractValue(u8),
+    AddValue(C),
+    SubtractValue(C),
+    SetValue(C),
+    /// Adds the current cell times `factor` to the cell `offset` cells to the right,
+    /// unless the current cell is 0.
+    MultiplyAdd {
+        offset: isize,
+        factor: C,
+    },
+    /// Subtracts the current cell times `factor` from the cell `offset` cells to the right,
+    /// unless the current cell is 0.
+    MultiplySubtract {
+        offset: isize,
+        factor: C,
+    },
+    /// Moves the pointer right in steps of the stride until the current cell is 0.
+    ScanRight(usize),
+    /// Moves the pointer left in steps of the stride until the current cell is 0.
+    ScanLeft(usize),
+    /// Adds the value to the cell `offset` cells to the right, without moving the pointer.
+    AddAt {
+        offset: isize,
+        value: C,
+    },
+    /// Subtracts the value from the cell `offset` cells to the right, without moving the pointer.
+    SubtractAt {
+        offset: isize,
+        value: C,
+    },
+    /// Sets the cell `offset` cells to the right to the value, without moving the pointer.
+    SetAt {
+        offset: isize,
+        value: C,
+    },
+    /// Outputs the cell `offset` cells to the right, without moving the pointer.
+    OutputAt {
+        offset: isize,
+    },
     Output,
+    /// Outputs the bytes, which were already known when compiling the program.
+    OutputBytes(Vec<u8>),
     Input,
-    StartOfLoop { end: usize },
-    EndOfLoop { start: usize },
+    StartOfLoop {
+        end: usize,
+    },
+    EndOfLoop {
+        start: usize,
+    },
 }
 
-impl OptimizedCommand {
-    fn optimize_increment_pointer(
-        current_command: Option<Self>,
-        optimized_program: &mut Vec<Self>,
-    ) -> Option<Self> {
-        match current_command {
-            None => Some(Self::AddPointer(1)),
-            Some(Self::AddPointer(value)) => {
-                (value < usize::MAX).then(|| Self::AddPointer(value + 1))
-            }
-            Some(Self::SubtractPointer(value)) => {
-                (value > 1).then(|| Self::SubtractPointer(value - 1))
-            }
-            Some(optimized_command) => {
-                optimized_program.push(optimized_command);
-                Some(Self::AddPointer(1))
-            }
-        }
-    }
-
-    fn optimize_decrement_pointer(
-        current_command: Option<Self>,
-        optimized_program: &mut Vec<Self>,
-    ) -> Option<Self> {
-        match current_command {
-            None => Some(Self::SubtractPointer(1)),
-            Some(Self::AddPointer(value)) => (value > 1).then(|| Self::AddPointer(value - 1)),
-            Some(Self::SubtractPointer(value)) => {
-                (value < usize::MAX).then(|| Self::SubtractPointer(value + 1))
-            }
-            Some(optimized_command) => {
-                optimized_program.push(optimized_command);
-                Some(Self::SubtractPointer(1))
-            }
-        }
-    }
-
-    fn optimize_increment_value(
-        current_command: Option<Self>,
-        optimized_program: &mut Vec<Self>,
-    ) -> Option<Self> {
-        match current_command {
-            None => Some(Self::AddValue(1)),
-            Some(Self::AddValue(value)) => (value < u8::MAX).then(|| Self::AddValue(value + 1)),
-            Some(Self::SubtractValue(value)) => (value > 1).then(|| Self::SubtractValue(value - 1)),
-            Some(optimized_command) => {
-                optimized_program.push(optimized_command);
-                Some(Self::AddValue(1))
-            }
-        }
-    }
-
-    fn optimize_decrement_value(
-        current_command: Option<Self>,
-        optimized_program: &mut Vec<Self>,
-    ) -> Option<Self> {
-        match current_command {
-            None => Some(Self::SubtractValue(1)),
-            Some(Self::AddValue(value)) => (value > 1).then(|| Self::AddValue(value - 1)),
-            Some(Self::SubtractValue(value)) => {
-                (value < u8::MAX).then(|| Self::SubtractValue(value + 1))
-            }
-            Some(optimized_command) => {
-                optimized_program.push(optimized_command);
-                Some(Self::SubtractValue(1))
-            }
-        }
-    }
-
-    fn optimize_output(current_command: Option<Self>, optimized_program: &mut Vec<Self>) -> Self {
-        if let Some(optimized_command) = current_command {
-            optimized_program.push(optimized_command);
+impl<C: Cell> OptimizedCommand<C> {
+    /// Appends the commands for `nodes` to `program`, linking every loop start to its end.
+    fn lower_into(nodes: &[Node<C>], program: &mut Vec<Self>) {
+        for node in nodes {
+            let command = match node {
+                Node::AddPointer(value) => Self::AddPointer(*value),
+                Node::SubtractPointer(value) => Self::SubtractPointer(*value),
+                Node::AddValue(value) => Self::AddValue(*value),
+                Node::SubtractValue(value) => Self::SubtractValue(*value),
+                Node::SetValue(value) => Self::SetValue(*value),
+                Node::MultiplyAdd { offset, factor } => Self::MultiplyAdd {
+                    offset: *offset,
+                    factor: *factor,
+                },
+                Node::MultiplySubtract { offset, factor } => Self::MultiplySubtract {
+                    offset: *offset,
+                    factor: *factor,
+                },
+                Node::ScanRight(stride) => Self::ScanRight(*stride),
+                Node::ScanLeft(stride) => Self::ScanLeft(*stride),
+                Node::AddAt { offset, value } => Self::AddAt {
+                    offset: *offset,
+                    value: *value,
+                },
+                Node::SubtractAt { offset, value } => Self::SubtractAt {
+                    offset: *offset,
+                    value: *value,
+                },
+                Node::SetAt { offset, value } => Self::SetAt {
+                    offset: *offset,
+                    value: *value,
+                },
+                Node::OutputAt { offset } => Self::OutputAt { offset: *offset },
+                Node::Output => Self::Output,
+                Node::OutputBytes(bytes) => Self::OutputBytes(bytes.clone()),
+                Node::Input => Self::Input,
+                Node::Loop(body) => {
+                    // The end of the loop is only known once the body is lowered
+                    let start = program.len();
+                    program.push(Self::StartOfLoop { end: 0 });
+                    Self::lower_into(body, program);
+                    let end = program.len();
+                    program[start] = Self::StartOfLoop { end };
+                    Self::EndOfLoop { start }
+                }
+            };
+            program.push(command);
         }
-        Self::Output
     }
 
-    fn optimize_input(current_command: Option<Self>, optimized_program: &mut Vec<Self>) -> Self {
-        if let Some(optimized_command) = current_command {
-            optimized_program.push(optimized_command);
-        }
-        Self::Input
+    /// Turns the IR into commands, with every loop start linked to its end.
+    #[must_use]
+    pub fn lower(nodes: &[Node<C>]) -> Vec<Self> {
+        let mut program = Vec::with_capacity(nodes.len());
+        Self::lower_into(nodes, &mut program);
+        program
     }
 
-    fn optimize_start_of_loop(
-        current_command: Option<Self>,
-        optimized_program: &mut Vec<Self>,
-    ) -> Self {
-        if let Some(optimized_command) = current_command {
-            optimized_program.push(optimized_command);
-        }
-        Self::StartOfLoop { end: 0 }
+    /// Optimizes the commands with every pass, see [`Self::optimize_commands_with`].
+    ///
+    /// # Errors
+    /// Returns an error if the loops aren't balanced.
+    pub fn optimize_commands<Commands: Iterator<Item = Command>>(
+        commands: Commands,
+        config: Config,
+    ) -> Result<Vec<Self>, Error> {
+        Self::optimize_commands_with(commands, config, &PassManager::default())
     }
 
-    fn optimize_end_of_loop(
-        current_command: Option<Self>,
-        optimized_program: &mut Vec<Self>,
-    ) -> Self {
-        if let Some(optimized_command) = current_command {
-            optimized_program.push(optimized_command);
-        }
-
-        // Store the current program length
-        let current_program_length = optimized_program.len();
-
-        // Find the beginning of the loop
-        let mut closed_loops = 1;
-        let (index, start_of_loop) = optimized_program
-            .iter_mut()
-            .enumerate()
-            .rev()
-            .find(|(_, optimized_command)| {
-                match optimized_command {
-                    Self::EndOfLoop { .. } => closed_loops += 1,
-                    Self::StartOfLoop { .. } => closed_loops -= 1,
-                    _ => {}
-                }
-                closed_loops == 0
-            })
-            .expect("Unmatched end of loop");
-
-        // Make sure the found loop start is still unmatched
-        assert_eq!(
-            start_of_loop,
-            &mut Self::StartOfLoop { end: 0 },
-            "Found a wrong start of loop while looking for a match for end of loop"
-        );
-
-        // Store the current program length as the end of the loop,
-        // as that will be the index of the end of the loop
-        *start_of_loop = Self::StartOfLoop {
-            end: current_program_length,
-        };
-        Self::EndOfLoop { start: index }
-    }
-
-    pub fn optimize_commands<Commands: Iterator<Item = Command>>(commands: Commands) -> Vec<Self> {
-        // Create a variable to store the current optimized command and a buffer for the full program
-        let mut current_command = None;
-        let mut optimized_program = Vec::with_capacity(commands.size_hint().0);
-
-        // Iterate through the commands
-        for command in commands {
-            // Store the new command, pushing the command on change of command type.
-            // Operations on pointers are the same type.
-            // Operations on values are the same type.
-            // Everything else is their own type
-            current_command = match command {
-                Command::IncrementPointer => {
-                    Self::optimize_increment_pointer(current_command, &mut optimized_program)
-                }
-                Command::DecrementPointer => {
-                    Self::optimize_decrement_pointer(current_command, &mut optimized_program)
-                }
-                Command::IncrementValue => {
-                    Self::optimize_increment_value(current_command, &mut optimized_program)
-                }
-                Command::DecrementValue => {
-                    Self::optimize_decrement_value(current_command, &mut optimized_program)
-                }
-                Command::Output => Some(Self::optimize_output(
-                    current_command,
-                    &mut optimized_program,
-                )),
-                Command::Input => Some(Self::optimize_input(
-                    current_command,
-                    &mut optimized_program,
-                )),
-                Command::StartOfLoop => Some(Self::optimize_start_of_loop(
-                    current_command,
-                    &mut optimized_program,
-                )),
-                Command::EndOfLoop => Some(Self::optimize_end_of_loop(
-                    current_command,
-                    &mut optimized_program,
-                )),
-            };
-        }
-
-        // Make sure all commands are stored in the program
-        if let Some(command) = current_command {
-            optimized_program.push(command);
-        }
-        optimized_program
+    /// Builds the IR from the commands, runs `passes` over it and turns the result into commands.
+    /// The passes respect the `config` the program will run with.
+    ///
+    /// # Errors
+    /// Returns an error if the loops aren't balanced.
+    pub fn optimize_commands_with<Commands: Iterator<Item = Command>>(
+        commands: Commands,
+        config: Config,
+        passes: &PassManager,
+    ) -> Result<Vec<Self>, Error> {
+        let nodes = passes.run(Node::build(commands)?, config);
+        Ok(Self::lower(&nodes))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::command::Command;
+    use core::iter;
+
+    use crate::{
+        cell::Cell,
+        command::Command,
+        config::{Config, Overflow},
+        error::Error,
+        passes::{Pass, PassManager},
+    };
 
     use super::OptimizedCommand;
 
+    /// Optimizes the commands without removing dead code or running them at compile time, which
+    /// would remove loops at the start.
+    fn optimize<C: Cell, Commands: Iterator<Item = Command>>(
+        commands: Commands,
+        config: Config,
+    ) -> Result<Vec<OptimizedCommand<C>>, Error> {
+        OptimizedCommand::optimize_commands_with(
+            commands,
+            config,
+            &PassManager::default()
+                .without_pass(Pass::DeadCode)
+                .without_pass(Pass::Constants),
+        )
+    }
+
     #[test]
     fn no_duplicates() {
         let program = [
@@ -215,17 +181,21 @@ mod tests {
             Command::IncrementValue,
             Command::DecrementPointer,
             Command::DecrementValue,
+            Command::Output,
             Command::EndOfLoop,
         ];
-        let optimized_program = OptimizedCommand::optimize_commands(program.into_iter());
+        let optimized_program = optimize::<u8, _>(program.into_iter(), Config::default())
+            .expect("The loops are balanced");
         assert_eq!(
             optimized_program,
             [
-                OptimizedCommand::StartOfLoop { end: 5 },
-                OptimizedCommand::AddPointer(1),
-                OptimizedCommand::AddValue(1),
-                OptimizedCommand::SubtractPointer(1),
+                OptimizedCommand::StartOfLoop { end: 4 },
+                OptimizedCommand::AddAt {
+                    offset: 1,
+                    value: 1
+                },
                 OptimizedCommand::SubtractValue(1),
+                OptimizedCommand::Output,
                 OptimizedCommand::EndOfLoop { start: 0 }
             ]
         );
@@ -241,7 +211,8 @@ mod tests {
             Command::DecrementValue,
             Command::EndOfLoop,
         ];
-        let optimized_program = OptimizedCommand::optimize_commands(program.into_iter());
+        let optimized_program = optimize::<u8, _>(program.into_iter(), Config::default())
+            .expect("The loops are balanced");
         assert_eq!(
             optimized_program,
             [
@@ -265,16 +236,184 @@ mod tests {
             Command::DecrementValue,
             Command::EndOfLoop,
         ];
-        let optimized_program = OptimizedCommand::optimize_commands(program.into_iter());
+        let optimized_program = optimize::<u8, _>(program.into_iter(), Config::default())
+            .expect("The loops are balanced");
         assert_eq!(
             optimized_program,
             [
+                OptimizedCommand::StartOfLoop { end: 3 },
+                OptimizedCommand::AddAt {
+                    offset: 2,
+                    value: 2
+                },
+                OptimizedCommand::SubtractValue(2),
+                OptimizedCommand::EndOfLoop { start: 0 }
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_end_of_loop() {
+        let program = [Command::IncrementValue, Command::EndOfLoop];
+        assert!(matches!(
+            optimize::<u8, _>(program.into_iter(), Config::default()),
+            Err(Error::UnmatchedEndOfLoop(None))
+        ));
+    }
+
+    #[test]
+    fn wide_cells() {
+        let program = iter::repeat_n(Command::IncrementValue, 256);
+        let optimized_program =
+            optimize::<u16, _>(program, Config::default()).expect("There are no loops");
+        assert_eq!(optimized_program, [OptimizedCommand::AddValue(256)]);
+    }
+
+    #[test]
+    fn trapping_cells() {
+        let program = [
+            Command::IncrementValue,
+            Command::DecrementValue,
+            Command::DecrementValue,
+        ];
+        let optimized_program = optimize::<u8, _>(
+            program.into_iter(),
+            Config {
+                overflow: Overflow::Trap,
+                ..Config::default()
+            },
+        )
+        .expect("There are no loops");
+        assert_eq!(
+            optimized_program,
+            [
+                OptimizedCommand::AddValue(1),
+                OptimizedCommand::SubtractValue(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn clear_loops() {
+        let program = crate::parse(*b"+[-]+++>[+]<[>[-]<-]")
+            .map(|token| token.command)
+            .collect::<Vec<_>>();
+        let optimized_program = optimize::<u8, _>(program.iter().copied(), Config::default())
+            .expect("The loops are balanced");
+        assert_eq!(
+            optimized_program,
+            [
+                OptimizedCommand::SetValue(3),
+                OptimizedCommand::SetAt {
+                    offset: 1,
+                    value: 0
+                },
                 OptimizedCommand::StartOfLoop { end: 5 },
+                OptimizedCommand::SetAt {
+                    offset: 1,
+                    value: 0
+                },
+                OptimizedCommand::SubtractValue(1),
+                OptimizedCommand::EndOfLoop { start: 2 }
+            ]
+        );
+
+        // Incrementing saturating cells never reaches 0
+        let saturating_program = optimize::<u8, _>(
+            program.into_iter(),
+            Config {
+                overflow: Overflow::Saturate,
+                ..Config::default()
+            },
+        )
+        .expect("The loops are balanced");
+        assert_eq!(
+            saturating_program[2],
+            OptimizedCommand::StartOfLoop { end: 4 }
+        );
+    }
+
+    #[test]
+    fn multiply_loops() {
+        let program = crate::parse(*b"[->+>++<<]>[<<->-->-]>[->>+-<<]").map(|token| token.command);
+        let optimized_program =
+            optimize::<u8, _>(program, Config::default()).expect("The loops are balanced");
+        assert_eq!(
+            optimized_program,
+            [
+                OptimizedCommand::MultiplyAdd {
+                    offset: 1,
+                    factor: 1
+                },
+                OptimizedCommand::MultiplyAdd {
+                    offset: 2,
+                    factor: 2
+                },
+                OptimizedCommand::SetValue(0),
+                OptimizedCommand::AddPointer(1),
+                OptimizedCommand::MultiplySubtract {
+                    offset: -2,
+                    factor: 1
+                },
+                OptimizedCommand::MultiplySubtract {
+                    offset: -1,
+                    factor: 2
+                },
+                OptimizedCommand::SetValue(0),
+                OptimizedCommand::AddPointer(1),
+                // The loop visits a cell it doesn't change, so it could still move past the tape edge
+                OptimizedCommand::StartOfLoop { end: 12 },
+                OptimizedCommand::SubtractValue(1),
                 OptimizedCommand::AddPointer(2),
-                OptimizedCommand::AddValue(2),
                 OptimizedCommand::SubtractPointer(2),
-                OptimizedCommand::SubtractValue(2),
-                OptimizedCommand::EndOfLoop { start: 0 }
+                OptimizedCommand::EndOfLoop { start: 8 },
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_loops() {
+        let program = crate::parse(*b"[>]<[<<<<]+[>+<-<]").map(|token| token.command);
+        let optimized_program =
+            optimize::<u8, _>(program, Config::default()).expect("The loops are balanced");
+        assert_eq!(
+            optimized_program[..4],
+            [
+                OptimizedCommand::ScanRight(1),
+                OptimizedCommand::SubtractPointer(1),
+                OptimizedCommand::ScanLeft(4),
+                OptimizedCommand::AddValue(1),
+            ]
+        );
+        assert_eq!(
+            optimized_program[4],
+            OptimizedCommand::StartOfLoop { end: 8 }
+        );
+    }
+
+    #[test]
+    fn offsets() {
+        let program = crate::parse(*b">+>++<<-.>>>+-<<<<.,").map(|token| token.command);
+        let optimized_program =
+            optimize::<u8, _>(program, Config::default()).expect("There are no loops");
+        assert_eq!(
+            optimized_program,
+            [
+                OptimizedCommand::AddAt {
+                    offset: 1,
+                    value: 1
+                },
+                OptimizedCommand::AddAt {
+                    offset: 2,
+                    value: 2
+                },
+                OptimizedCommand::SubtractValue(1),
+                OptimizedCommand::Output,
+                // Nothing happens at the turning point, so the pointer has to move there
+                OptimizedCommand::AddPointer(3),
+                OptimizedCommand::OutputAt { offset: -4 },
+                OptimizedCommand::SubtractPointer(4),
+                OptimizedCommand::Input,
             ]
         );
     }
@@ -1,119 +1,213 @@
-extern crate alloc;
+use std::io::{Read, Write};
 
-use alloc::collections::VecDeque;
-use std::io::{self, Read, Write};
-
-use crate::optimized_command::OptimizedCommand;
+use crate::{
+    cell::Cell,
+    config::{Config, Overflow},
+    error::Error,
+    optimized_command::OptimizedCommand,
+    tape::Tape,
+};
 
+/// A brainfuck program made of optimized commands.
 #[derive(Debug)]
-pub struct OptimizedProgram {
-    program: Vec<OptimizedCommand>,
-    data: VecDeque<u8>,
+pub struct OptimizedProgram<C: Cell = u8> {
+    program: Vec<OptimizedCommand<C>>,
+    tape: Tape<C>,
+    config: Config,
+    /// The number of commands an execution can run, which is unlimited by default.
+    step_limit: u64,
 }
 
-impl From<Vec<OptimizedCommand>> for OptimizedProgram {
-    fn from(value: Vec<OptimizedCommand>) -> Self {
+impl<C: Cell> TryFrom<Vec<OptimizedCommand<C>>> for OptimizedProgram<C> {
+    type Error = Error;
+
+    fn try_from(value: Vec<OptimizedCommand<C>>) -> Result<Self, Self::Error> {
         // Create an optimized program
         let result = Self {
             program: value,
-            data: VecDeque::with_capacity(1),
+            tape: Tape::new(Config::default().tape),
+            config: Config::default(),
+            step_limit: u64::MAX,
         };
 
         // Make sure all loops are opened AND closed
-        result.check();
-        result
+        result.check()?;
+        Ok(result)
     }
 }
 
-impl OptimizedProgram {
-    fn check(&self) {
+impl<C: Cell> OptimizedProgram<C> {
+    /// Sets the runtime behavior of the program.
+    #[must_use]
+    pub const fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Stops the program with [`Error::StepLimit`] once it has run `limit` commands, counting
+    /// every start and end of a loop it passes too.
+    /// A scan that would never end, because no cell in its path on a wrapping tape is 0, stops
+    /// the program at once, while without a limit it never returns.
+    #[must_use]
+    pub const fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = limit;
+        self
+    }
+
+    /// Returns the tape, to run the program in other ways than [`Self::execute`].
+    pub(crate) const fn tape_mut(&mut self) -> &mut Tape<C> {
+        &mut self.tape
+    }
+
+    /// Returns the commands of the program.
+    #[must_use]
+    pub fn commands(&self) -> &[OptimizedCommand<C>] {
+        &self.program
+    }
+
+    /// Returns the config the program runs with.
+    #[must_use]
+    pub const fn config(&self) -> Config {
+        self.config
+    }
+
+    /// Returns the tape as it was left by the last execution.
+    #[must_use]
+    pub const fn tape(&self) -> &Tape<C> {
+        &self.tape
+    }
+
+    fn check(&self) -> Result<(), Error> {
         let mut active_loops = 0_usize;
 
         // iterate though the commands
         for command in &self.program {
             // Increment the number of active loops on the start of loops.
             // Decrement the number of active loops on the end of loops.
-            // Panic when the end of a loop is found while the number of active loops is 0.
+            // Fail when the end of a loop is found while the number of active loops is 0.
             match command {
                 OptimizedCommand::StartOfLoop { .. } => active_loops += 1,
                 OptimizedCommand::EndOfLoop { .. } => {
-                    assert_ne!(active_loops, 0, "Unexpected end of loop");
+                    if active_loops == 0 {
+                        return Err(Error::UnmatchedEndOfLoop(None));
+                    }
                     active_loops -= 1;
                 }
                 _ => {}
             }
         }
 
-        // Panic if there are loops that haven't been closed
-        assert_eq!(active_loops, 0, "Missing end of loop");
-    }
-
-    fn subtract_pointer(&mut self, pointer: &mut usize, value: usize) {
-        if *pointer >= value {
-            *pointer -= value;
+        // Fail if there are loops that haven't been closed
+        if active_loops == 0 {
+            Ok(())
         } else {
-            if self.data.capacity() - self.data.len() < value - *pointer {
-                self.data
-                    .reserve(*pointer - value - (self.data.capacity() - self.data.len()));
-            }
-            for _ in *pointer..=value {
-                self.data.push_front(0);
-            }
+            Err(Error::UnmatchedStartOfLoop(None))
         }
     }
 
-    fn add_pointer(&mut self, pointer: &mut usize, value: usize) {
-        *pointer += value;
-        if self.data.capacity() < *pointer {
-            self.data.reserve(*pointer - self.data.capacity());
-        }
-        for _ in self.data.len()..=*pointer {
-            self.data.push_back(0);
-        }
+    fn output<Output: Write + ?Sized>(&self, output: &mut Output) -> Result<(), Error> {
+        output.write_all(&[self.tape.get().to_byte()])?;
+        Ok(())
     }
 
-    fn input(&mut self, pointer: usize) {
-        self.data[pointer] = io::stdin()
-            .bytes()
-            .next()
-            .expect("Failed to read input")
-            .expect("Failed to read input");
+    fn output_at<Output: Write + ?Sized>(
+        &mut self,
+        pc: usize,
+        offset: isize,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        output.write_all(&[self.tape.checked_cell_at(pc, offset)?.to_byte()])?;
+        Ok(())
     }
 
-    fn output(&self, pointer: usize) {
-        while io::stdout()
-            .write(&[self.data[pointer]])
-            .expect("Failed to print data")
-            == 0
-        {}
+    /// Runs the command at `pc`, which mustn't be the start or end of a loop.
+    pub(crate) fn execute_command<Input: Read + ?Sized, Output: Write + ?Sized>(
+        &mut self,
+        pc: usize,
+        input: &mut Input,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        let overflow = self.config.overflow;
+        match &self.program[pc] {
+            OptimizedCommand::SubtractPointer(amount) => {
+                self.tape.subtract_pointer(pc, *amount)?;
+            }
+            OptimizedCommand::AddPointer(amount) => self.tape.add_pointer(pc, *amount)?,
+            OptimizedCommand::SubtractValue(value) => {
+                self.tape.change(pc, *value, overflow, Overflow::subtract)?;
+            }
+            OptimizedCommand::AddValue(value) => {
+                self.tape.change(pc, *value, overflow, Overflow::add)?;
+            }
+            OptimizedCommand::SetValue(value) => self.tape.set(*value),
+            OptimizedCommand::MultiplyAdd { offset, factor } => {
+                self.tape
+                    .multiply(pc, *offset, *factor, overflow, Overflow::add)?;
+            }
+            OptimizedCommand::MultiplySubtract { offset, factor } => {
+                self.tape
+                    .multiply(pc, *offset, *factor, overflow, Overflow::subtract)?;
+            }
+            OptimizedCommand::ScanLeft(stride) => {
+                self.tape.checked_scan_left(pc, *stride, self.step_limit)?;
+            }
+            OptimizedCommand::ScanRight(stride) => {
+                self.tape.checked_scan_right(pc, *stride, self.step_limit)?;
+            }
+            OptimizedCommand::AddAt { offset, value } => {
+                self.tape
+                    .change_at(pc, *offset, *value, overflow, Overflow::add)?;
+            }
+            OptimizedCommand::SubtractAt { offset, value } => {
+                self.tape
+                    .change_at(pc, *offset, *value, overflow, Overflow::subtract)?;
+            }
+            OptimizedCommand::SetAt { offset, value } => {
+                *self.tape.checked_cell_at(pc, *offset)? = *value;
+            }
+            OptimizedCommand::OutputAt { offset } => {
+                self.output_at(pc, *offset, output)?;
+            }
+            OptimizedCommand::Input => self.tape.input(input, self.config.end_of_input)?,
+            OptimizedCommand::Output => self.output(output)?,
+            OptimizedCommand::OutputBytes(bytes) => output.write_all(bytes)?,
+            OptimizedCommand::StartOfLoop { .. } | OptimizedCommand::EndOfLoop { .. } => {}
+        }
+        Ok(())
     }
 
-    pub fn execute(&mut self) {
-        // Create a program counter, pointer, and data buffer
+    /// Runs the program, reading bytes from `input` and writing bytes to `output`.
+    ///
+    /// # Errors
+    /// Returns an error if the input runs out, the program runs past its step limit or reading
+    /// input or writing output fails.
+    pub fn execute<Input: Read, Output: Write>(
+        &mut self,
+        mut input: Input,
+        mut output: Output,
+    ) -> Result<(), Error> {
+        // Create a program counter and an empty tape
         let mut pc = 0;
-        let mut pointer = 0;
-        self.data.clear();
-        self.data.push_back(0);
+        let mut steps = 0;
+        self.tape = Tape::new(self.config.tape);
 
         // Iterate through the commands
         while let Some(command) = self.program.get(pc) {
+            if steps == self.step_limit {
+                return Err(Error::StepLimit { pc });
+            }
+            steps += 1;
+
             // Execute the current command
             match command {
-                OptimizedCommand::SubtractPointer(value) => {
-                    self.subtract_pointer(&mut pointer, *value);
-                }
-                OptimizedCommand::AddPointer(value) => self.add_pointer(&mut pointer, *value),
-                OptimizedCommand::SubtractValue(value) => self.data[pointer] -= value,
-                OptimizedCommand::AddValue(value) => self.data[pointer] += value,
-                OptimizedCommand::Input => self.input(pointer),
-                OptimizedCommand::Output => self.output(pointer),
-                OptimizedCommand::StartOfLoop { end } if self.data[pointer] == 0 => {
+                OptimizedCommand::StartOfLoop { end } if self.tape.get() == C::ZERO => {
                     pc = *end;
                 }
-                OptimizedCommand::EndOfLoop { start } if self.data[pointer] != 0 => {
+                OptimizedCommand::EndOfLoop { start } if self.tape.get() != C::ZERO => {
                     pc = *start;
                 }
                 OptimizedCommand::StartOfLoop { .. } | OptimizedCommand::EndOfLoop { .. } => {}
+                _ => self.execute_command(pc, &mut input, &mut output)?,
             }
 
             // Continue to the next command or end of the program
@@ -121,27 +215,52 @@ impl OptimizedProgram {
         }
 
         // Flush the output
-        #[expect(clippy::unwrap_used, reason = "Flushing stdout shouldn't go wrong.")]
-        io::stdout().flush().unwrap();
+        output.flush()?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::optimized_command::OptimizedCommand;
+    use std::io;
+
+    use crate::{
+        compile, compile_optimized, compile_optimized_with,
+        config::{Config, Edge, Overflow, TapeMode},
+        error::Error,
+        optimized_command::OptimizedCommand,
+        parse,
+        passes::{Pass, PassManager},
+    };
 
     use super::OptimizedProgram;
 
+    /// Compiles the source without running its start at compile time, so the program runs every
+    /// command itself.
+    fn compile_without_constants(
+        source: &[u8],
+        config: Config,
+    ) -> Result<OptimizedProgram<u8>, Error> {
+        compile_optimized_with(
+            parse(source.iter().copied()),
+            config,
+            &PassManager::default().without_pass(Pass::Constants),
+        )
+    }
+
     #[test]
     fn adding_two() {
-        let mut program = OptimizedProgram::from(vec![OptimizedCommand::AddValue(2)]);
-        program.execute();
-        assert_eq!(program.data, [2]);
+        let mut program = OptimizedProgram::<u8>::try_from(vec![OptimizedCommand::AddValue(2)])
+            .expect("The loops are balanced");
+        program
+            .execute(io::empty(), io::sink())
+            .expect("The program doesn't use input or output");
+        assert_eq!(program.tape.cells(), &[2]);
     }
 
     #[test]
     fn moving_data() {
-        let mut program = OptimizedProgram::from(vec![
+        let mut program = OptimizedProgram::<u8>::try_from(vec![
             OptimizedCommand::AddValue(2),
             OptimizedCommand::StartOfLoop { end: 6 },
             OptimizedCommand::AddPointer(1),
@@ -149,8 +268,198 @@ mod tests {
             OptimizedCommand::SubtractPointer(1),
             OptimizedCommand::SubtractValue(1),
             OptimizedCommand::EndOfLoop { start: 1 },
-        ]);
-        program.execute();
-        assert_eq!(program.data, [0, 2]);
+        ])
+        .expect("The loops are balanced");
+        program
+            .execute(io::empty(), io::sink())
+            .expect("The program doesn't use input or output");
+        assert_eq!(program.tape.cells(), &[0, 2]);
+    }
+
+    #[test]
+    fn echo() {
+        let mut program = OptimizedProgram::<u8>::try_from(vec![
+            OptimizedCommand::Input,
+            OptimizedCommand::AddValue(1),
+            OptimizedCommand::Output,
+            OptimizedCommand::Input,
+            OptimizedCommand::Output,
+        ])
+        .expect("The program has no loops");
+        let mut output = Vec::new();
+        program
+            .execute(&b"ab"[..], &mut output)
+            .expect("The input is long enough");
+        assert_eq!(output, b"bb");
+    }
+
+    #[test]
+    fn trapping_overflow() {
+        let mut program = OptimizedProgram::<u8>::try_from(vec![
+            OptimizedCommand::AddPointer(1),
+            OptimizedCommand::SubtractValue(1),
+        ])
+        .expect("The program has no loops")
+        .with_config(Config {
+            overflow: Overflow::Trap,
+            ..Config::default()
+        });
+        assert!(matches!(
+            program.execute(io::empty(), io::sink()),
+            Err(Error::Overflow { pc: 1, cell: 1 })
+        ));
+    }
+
+    #[test]
+    fn wrapping_tape() {
+        let mut program = OptimizedProgram::<u8>::try_from(vec![
+            OptimizedCommand::SubtractPointer(4),
+            OptimizedCommand::AddValue(1),
+            OptimizedCommand::AddPointer(4),
+            OptimizedCommand::AddValue(2),
+        ])
+        .expect("The program has no loops")
+        .with_config(Config {
+            tape: TapeMode::Bounded {
+                size: 3,
+                edge: Edge::Wrap,
+            },
+            ..Config::default()
+        });
+        program
+            .execute(io::empty(), io::sink())
+            .expect("The tape wraps");
+        assert_eq!(program.tape.cells(), &[2, 0, 1]);
+    }
+
+    #[test]
+    fn growing_left() {
+        let source = b"<<<+>>>++";
+        let mut program =
+            compile::<u8, _>(parse(*source), Config::default()).expect("The program has no loops");
+        let mut optimized_program = compile_optimized::<u8, _>(parse(*source), Config::default())
+            .expect("The program has no loops");
+        program
+            .execute(io::empty(), io::sink())
+            .expect("The program doesn't use input or output");
+        optimized_program
+            .execute(io::empty(), io::sink())
+            .expect("The program doesn't use input or output");
+        assert_eq!(optimized_program.tape.cells(), &[1, 0, 0, 2]);
+        assert_eq!(optimized_program.tape.cells(), program.tape().cells());
+    }
+
+    #[test]
+    fn multiply_loops() {
+        let run = |source: &[u8], config| {
+            let mut program =
+                compile_without_constants(source, config).expect("The loops are balanced");
+            program
+                .execute(io::empty(), io::sink())
+                .map(|()| program.tape.cells().clone())
+        };
+        let source = format!("+++[->++>{}<<]", "+".repeat(86));
+        assert_eq!(
+            run(source.as_bytes(), Config::default()).expect("Cells wrap"),
+            [0, 6, 2]
+        );
+        let saturating = Config {
+            overflow: Overflow::Saturate,
+            ..Config::default()
+        };
+        assert_eq!(
+            run(b">++<+++[->--<]", saturating).expect("Cells saturate"),
+            [0, 0]
+        );
+        let trapping = Config {
+            overflow: Overflow::Trap,
+            ..Config::default()
+        };
+        assert!(matches!(
+            run(b">++<+++[->--<]", trapping),
+            Err(Error::Overflow { pc: 2, cell: 1 })
+        ));
+        let bounded = Config {
+            tape: TapeMode::Bounded {
+                size: 2,
+                edge: Edge::Trap,
+            },
+            ..Config::default()
+        };
+        assert_eq!(
+            run(b"[->>+<<]", bounded).expect("The loop doesn't run"),
+            [0, 0]
+        );
+        assert!(matches!(
+            run(b"+[->>+<<]", bounded),
+            Err(Error::TapeOverflow { pc: 1 })
+        ));
+    }
+
+    #[test]
+    fn scan_loops() {
+        let run = |source: &[u8], tape| {
+            let config = Config {
+                tape,
+                ..Config::default()
+            };
+            let mut program =
+                compile_without_constants(source, config).expect("The loops are balanced");
+            program
+                .execute(io::empty(), io::sink())
+                .map(|()| program.tape.position())
+        };
+        let source = b"+>+>+>>+<<<<[>]>[>>]<<<<<[<]";
+        assert_eq!(
+            run(source, TapeMode::BiInfinite).expect("The tape grows"),
+            -1
+        );
+        assert!(matches!(
+            run(source, TapeMode::RightInfinite),
+            Err(Error::TapeUnderflow { pc: 8 })
+        ));
+    }
+
+    #[test]
+    fn offsets() {
+        let source = b"++>+++[<.>>+<-]>>-<<.";
+        let mut output = Vec::new();
+        let mut program =
+            compile_without_constants(source, Config::default()).expect("The loops are balanced");
+        program
+            .execute(io::empty(), &mut output)
+            .expect("The program doesn't use input");
+        assert_eq!(output, [2, 2, 2, 0]);
+        assert_eq!(program.tape.cells(), &[2, 0, 3, 255]);
+        assert_eq!(program.tape.position(), 1);
+
+        let mut right_infinite = compile_without_constants(
+            b"+>+<<+",
+            Config {
+                tape: TapeMode::RightInfinite,
+                ..Config::default()
+            },
+        )
+        .expect("There are no loops");
+        assert!(matches!(
+            right_infinite.execute(io::empty(), io::sink()),
+            Err(Error::TapeUnderflow { pc: 2 })
+        ));
+    }
+
+    #[test]
+    fn constant_output() {
+        let source = b"++++++++[>+++++++++<-]>.<+++[>+++++++++++<-]>.,.";
+        let mut program = compile_optimized::<u8, _>(parse(*source), Config::default())
+            .expect("The loops are balanced");
+        assert_eq!(
+            program.program[0],
+            OptimizedCommand::OutputBytes(b"Hi".to_vec())
+        );
+        let mut output = Vec::new();
+        program
+            .execute(&b"!"[..], &mut output)
+            .expect("There is enough input");
+        assert_eq!(output, b"Hi!");
     }
 }
@@ -0,0 +1,201 @@
+mod clear_loops;
+mod combine;
+mod constants;
+mod dead_code;
+mod multiply_loops;
+mod offsets;
+mod scan_loops;
+
+use crate::{cell::Cell, config::Config, ir::Node};
+
+/// An optimization that rewrites the IR.
+///
+/// Every pass keeps the behavior of the program the same for the config it's run with, although
+/// errors can be reported at other commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pass {
+    /// Merges consecutive pointer and value changes.
+    /// Value changes only cancel each other out if cells wrap on overflow.
+    Combine,
+    /// Replaces loops that move the current cell to other cells, like `[->+>++<<]`, with multiply
+    /// nodes.
+    MultiplyLoops,
+    /// Replaces loops that clear the current cell, like `[-]`, with setting it to 0 and folds
+    /// value changes into set values.
+    ClearLoops,
+    /// Replaces loops that only move the pointer, like `[>]`, with scans.
+    ScanLoops,
+    /// Removes loops that can never run because the current cell is known to be 0, like comment
+    /// loops, and other nodes without any effect.
+    DeadCode,
+    /// Runs the start of the program up to the first input at compile time, and replaces it with
+    /// its output and the cells it leaves behind.
+    Constants,
+    /// Replaces pointer moves between loops with offsets and a single move at the end.
+    Offsets,
+}
+
+impl Pass {
+    /// Every pass, in the order they run in.
+    pub const ALL: [Self; 7] = [
+        Self::Combine,
+        Self::MultiplyLoops,
+        Self::ClearLoops,
+        Self::ScanLoops,
+        Self::DeadCode,
+        Self::Constants,
+        Self::Offsets,
+    ];
+
+    /// Returns the lowest optimization level that runs the pass.
+    #[must_use]
+    pub const fn level(self) -> u8 {
+        match self {
+            Self::Combine => 1,
+            Self::ClearLoops | Self::ScanLoops | Self::DeadCode => 2,
+            Self::MultiplyLoops | Self::Constants | Self::Offsets => 3,
+        }
+    }
+
+    /// Runs the pass over `nodes`, for a program that will run with `config`.
+    #[must_use]
+    pub fn run<C: Cell>(self, nodes: Vec<Node<C>>, config: Config) -> Vec<Node<C>> {
+        match self {
+            Self::Combine => combine::run(nodes, config),
+            Self::MultiplyLoops => multiply_loops::run(nodes, config),
+            Self::ClearLoops => clear_loops::run(nodes, config),
+            Self::ScanLoops => scan_loops::run(nodes),
+            Self::DeadCode => dead_code::run(nodes),
+            Self::Constants => constants::run(nodes, config),
+            Self::Offsets => offsets::run(nodes),
+        }
+    }
+}
+
+/// The passes to run over the IR.
+///
+/// The passes always run in the order of [`Pass::ALL`], as later passes look for the patterns
+/// earlier passes leave behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+}
+
+impl Default for PassManager {
+    /// Creates a pass manager that runs every pass.
+    fn default() -> Self {
+        Self {
+            passes: Pass::ALL.to_vec(),
+        }
+    }
+}
+
+impl PassManager {
+    /// The highest optimization level, which runs every pass.
+    pub const MAX_LEVEL: u8 = 3;
+
+    /// Creates a pass manager that doesn't run any passes.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    /// Creates a pass manager that runs the passes of an optimization level.
+    ///
+    /// Level 0 runs no passes, level 1 only combines changes, level 2 also replaces clear and scan
+    /// loops and removes dead code and level 3 runs every pass.
+    #[must_use]
+    pub fn level(level: u8) -> Self {
+        Self {
+            passes: Pass::ALL
+                .into_iter()
+                .filter(|pass| pass.level() <= level)
+                .collect(),
+        }
+    }
+
+    /// Enables `pass`.
+    #[must_use]
+    pub fn with_pass(mut self, pass: Pass) -> Self {
+        if !self.passes.contains(&pass) {
+            self.passes.push(pass);
+            self.passes.sort_unstable();
+        }
+        self
+    }
+
+    /// Disables `pass`.
+    #[must_use]
+    pub fn without_pass(mut self, pass: Pass) -> Self {
+        self.passes.retain(|enabled| *enabled != pass);
+        self
+    }
+
+    /// Returns the enabled passes, in the order they run in.
+    #[must_use]
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    /// Runs the enabled passes over `nodes`, for a program that will run with `config`.
+    #[must_use]
+    pub fn run<C: Cell>(&self, nodes: Vec<Node<C>>, config: Config) -> Vec<Node<C>> {
+        self.passes
+            .iter()
+            .fold(nodes, |nodes, pass| pass.run(nodes, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::Config, ir::Node};
+
+    use super::{Pass, PassManager};
+
+    #[test]
+    fn toggling_passes() {
+        let passes = PassManager::new()
+            .with_pass(Pass::ClearLoops)
+            .with_pass(Pass::Combine)
+            .with_pass(Pass::ClearLoops);
+        assert_eq!(passes.passes(), [Pass::Combine, Pass::ClearLoops]);
+        assert_eq!(PassManager::level(0), PassManager::new());
+        assert_eq!(
+            PassManager::level(2).passes(),
+            [
+                Pass::Combine,
+                Pass::ClearLoops,
+                Pass::ScanLoops,
+                Pass::DeadCode
+            ]
+        );
+        assert_eq!(
+            PassManager::level(PassManager::MAX_LEVEL),
+            PassManager::default()
+        );
+        assert_eq!(
+            PassManager::default()
+                .without_pass(Pass::MultiplyLoops)
+                .passes(),
+            [
+                Pass::Combine,
+                Pass::ClearLoops,
+                Pass::ScanLoops,
+                Pass::DeadCode,
+                Pass::Constants,
+                Pass::Offsets
+            ]
+        );
+
+        let nodes = Node::<u8>::build(crate::parse(*b"++[-]").map(|token| token.command))
+            .expect("The loops are balanced");
+        assert_eq!(
+            passes.run(nodes.clone(), Config::default()),
+            [Node::SetValue(0)]
+        );
+        assert_eq!(
+            PassManager::new().run(nodes.clone(), Config::default()),
+            nodes
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{
+    cell::Cell,
+    config::{Config, Overflow},
+    ir::Node,
+};
+
+/// Checks whether a loop that only contains `node` always ends with the cell at 0.
+fn is_clear_loop<C: Cell>(node: &Node<C>, overflow: Overflow) -> bool {
+    // Wrapping cells reach 0 for any odd step, otherwise only decrementing by 1 is safe
+    match node {
+        Node::SubtractValue(value) if *value == C::ONE => true,
+        Node::AddValue(value) | Node::SubtractValue(value) => {
+            overflow == Overflow::Wrap && value.to_byte() & 1 == 1
+        }
+        _ => false,
+    }
+}
+
+/// Replaces loops that clear the current cell, like `[-]`, with setting the cell to 0.
+/// Value changes right after setting a value are folded into the set value.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>, config: Config) -> Vec<Node<C>> {
+    let overflow = config.overflow;
+    let mut optimized: Vec<Node<C>> = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        // Replace clear loops, fold value changes into a previously set value
+        let node = match (node, optimized.last()) {
+            (Node::Loop(body), _) => match body.as_slice() {
+                [change] if is_clear_loop(change, overflow) => Node::SetValue(C::ZERO),
+                _ => Node::Loop(run(body, config)),
+            },
+            (Node::AddValue(value), Some(Node::SetValue(set))) => overflow
+                .add(*set, value)
+                .map_or(Node::AddValue(value), Node::SetValue),
+            (Node::SubtractValue(value), Some(Node::SetValue(set))) => overflow
+                .subtract(*set, value)
+                .map_or(Node::SubtractValue(value), Node::SetValue),
+            (node, _) => node,
+        };
+
+        // A set value replaces the previous value change, as it's overwritten anyway.
+        // Only trapping cells have to keep the change, as it could still overflow.
+        if matches!(node, Node::SetValue(_))
+            && match optimized.last() {
+                Some(Node::SetValue(_)) => true,
+                Some(Node::AddValue(_) | Node::SubtractValue(_)) => overflow != Overflow::Trap,
+                _ => false,
+            }
+        {
+            optimized.pop();
+        }
+        optimized.push(node);
+    }
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{Config, Overflow},
+        ir::Node,
+    };
+
+    use super::run;
+
+    #[test]
+    fn trapping_cells() {
+        let nodes = vec![
+            Node::<u8>::AddValue(1),
+            Node::Loop(vec![Node::SubtractValue(1)]),
+            Node::Loop(vec![Node::AddValue(1)]),
+        ];
+        assert_eq!(run(nodes.clone(), Config::default()), [Node::SetValue(0)]);
+
+        // The first change could overflow and incrementing never reaches 0 without wrapping
+        let trapping = Config {
+            overflow: Overflow::Trap,
+            ..Config::default()
+        };
+        assert_eq!(
+            run(nodes, trapping),
+            [
+                Node::AddValue(1),
+                Node::SetValue(0),
+                Node::Loop(vec![Node::AddValue(1)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use core::{cmp::Ordering, ops::Sub};
+
+use crate::{
+    cell::Cell,
+    config::{Config, Edge, Overflow, TapeMode},
+    ir::Node,
+};
+
+/// The result of combining two nodes.
+enum Combined<C: Cell> {
+    /// Both nodes are replaced by a single node.
+    Node(Node<C>),
+    /// The nodes cancel each other out.
+    Nothing,
+    /// The nodes have to stay separate.
+    Separate,
+}
+
+/// Combines opposite changes of `previous` and `next` into a single change, if any.
+fn cancel<C: Cell, Amount: Ord + Sub<Output = Amount>>(
+    previous: Amount,
+    next: Amount,
+    same: fn(Amount) -> Node<C>,
+    opposite: fn(Amount) -> Node<C>,
+) -> Combined<C> {
+    match previous.cmp(&next) {
+        Ordering::Greater => Combined::Node(same(previous - next)),
+        Ordering::Less => Combined::Node(opposite(next - previous)),
+        Ordering::Equal => Combined::Nothing,
+    }
+}
+
+/// Turns the wrapped sum of two changes into a single change, if it isn't 0.
+fn wrapped<C: Cell>(sum: C, change: fn(C) -> Node<C>) -> Combined<C> {
+    if sum == C::ZERO {
+        Combined::Nothing
+    } else {
+        Combined::Node(change(sum))
+    }
+}
+
+/// Combines `previous` and the `next` node into a single node if possible.
+fn combine<C: Cell>(previous: &Node<C>, next: &Node<C>, config: Config) -> Combined<C> {
+    // Only wrapping cells can cancel out opposite changes or changes of the cell size
+    let wrap = config.overflow == Overflow::Wrap;
+    // Opposite moves can turn around past an edge that traps, so they only cancel out on tapes
+    // without one
+    let turn = matches!(
+        config.tape,
+        TapeMode::BiInfinite
+            | TapeMode::Bounded {
+                edge: Edge::Wrap,
+                ..
+            }
+    );
+    match (previous, next) {
+        (Node::AddPointer(previous), Node::AddPointer(next)) => previous
+            .checked_add(*next)
+            .map_or(Combined::Separate, |sum| {
+                Combined::Node(Node::AddPointer(sum))
+            }),
+        (Node::SubtractPointer(previous), Node::SubtractPointer(next)) => previous
+            .checked_add(*next)
+            .map_or(Combined::Separate, |sum| {
+                Combined::Node(Node::SubtractPointer(sum))
+            }),
+        (Node::AddPointer(previous), Node::SubtractPointer(next)) if turn => {
+            cancel(*previous, *next, Node::AddPointer, Node::SubtractPointer)
+        }
+        (Node::SubtractPointer(previous), Node::AddPointer(next)) if turn => {
+            cancel(*previous, *next, Node::SubtractPointer, Node::AddPointer)
+        }
+        (Node::AddValue(previous), Node::AddValue(next)) => match previous.checked_add(*next) {
+            Some(sum) => Combined::Node(Node::AddValue(sum)),
+            None if wrap => wrapped(previous.wrapping_add(*next), Node::AddValue),
+            None => Combined::Separate,
+        },
+        (Node::SubtractValue(previous), Node::SubtractValue(next)) => {
+            match previous.checked_add(*next) {
+                Some(sum) => Combined::Node(Node::SubtractValue(sum)),
+                None if wrap => wrapped(previous.wrapping_add(*next), Node::SubtractValue),
+                None => Combined::Separate,
+            }
+        }
+        (Node::AddValue(previous), Node::SubtractValue(next)) if wrap => {
+            cancel(*previous, *next, Node::AddValue, Node::SubtractValue)
+        }
+        (Node::SubtractValue(previous), Node::AddValue(next)) if wrap => {
+            cancel(*previous, *next, Node::SubtractValue, Node::AddValue)
+        }
+        _ => Combined::Separate,
+    }
+}
+
+/// Merges consecutive pointer and value changes, also inside loops.
+///
+/// After changes cancel each other out, the next change isn't merged with the change before them,
+/// so the pointer still visits the cell they were made at.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>, config: Config) -> Vec<Node<C>> {
+    let mut combined = Vec::with_capacity(nodes.len());
+    let mut current = None;
+    for node in nodes {
+        let node = match node {
+            Node::Loop(body) => Node::Loop(run(body, config)),
+            other => other,
+        };
+        current = match current {
+            None => Some(node),
+            Some(previous) => match combine(&previous, &node, config) {
+                Combined::Node(node) => Some(node),
+                Combined::Nothing => None,
+                Combined::Separate => {
+                    combined.push(previous);
+                    Some(node)
+                }
+            },
+        };
+    }
+    combined.extend(current);
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{Config, Overflow},
+        ir::Node,
+    };
+
+    use super::run;
+
+    #[test]
+    fn combining_changes() {
+        let nodes = Node::<u8>::build(crate::parse(*b">>><+++--[>+-<]").map(|token| token.command))
+            .expect("The loops are balanced");
+        assert_eq!(
+            run(nodes.clone(), Config::default()),
+            [
+                Node::AddPointer(2),
+                Node::AddValue(1),
+                Node::Loop(vec![Node::AddPointer(1), Node::SubtractPointer(1)]),
+            ]
+        );
+        let saturating = Config {
+            overflow: Overflow::Saturate,
+            ..Config::default()
+        };
+        assert_eq!(
+            run(nodes, saturating)[1..3],
+            [Node::AddValue(3), Node::SubtractValue(2)]
+        );
+    }
+}
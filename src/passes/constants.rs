@@ -0,0 +1,225 @@
+use core::{cmp::Ordering, slice};
+
+use crate::{
+    cell::Cell,
+    config::{Config, Overflow},
+    ir::Node,
+    tape::Tape,
+};
+
+/// The number of nodes that are run at most while evaluating the start of a program.
+/// Programs that run longer than this before reading input might never stop.
+const MAX_STEPS: usize = 1 << 20;
+
+/// Runs nodes at compile time, as long as they don't need input.
+struct Evaluator<C: Cell> {
+    tape: Tape<C>,
+    overflow: Overflow,
+    output: Vec<u8>,
+    steps: usize,
+}
+
+impl<C: Cell> Evaluator<C> {
+    fn new(config: Config) -> Self {
+        Self {
+            tape: Tape::new(config.tape),
+            overflow: config.overflow,
+            output: Vec::new(),
+            steps: 0,
+        }
+    }
+
+    /// Counts a step, returns `None` once there were too many steps.
+    fn step(&mut self) -> Option<()> {
+        self.steps += 1;
+        (self.steps <= MAX_STEPS).then_some(())
+    }
+
+    /// Changes the cell at `offset` by `value`, using `change` to add or subtract it.
+    fn change_at(
+        &mut self,
+        offset: isize,
+        value: C,
+        change: fn(Overflow, C, C) -> Option<C>,
+    ) -> Option<()> {
+        let overflow = self.overflow;
+        let cell = self.tape.cell_at(offset)?;
+        *cell = change(overflow, *cell, value)?;
+        Some(())
+    }
+
+    /// Changes the cell at `offset` by the current cell times `factor`, if it isn't 0.
+    fn multiply(
+        &mut self,
+        offset: isize,
+        factor: C,
+        change: fn(Overflow, C, C) -> Option<C>,
+    ) -> Option<()> {
+        let value = self.tape.get();
+        if value == C::ZERO {
+            return Some(());
+        }
+        let product = self.overflow.multiply(value, factor)?;
+        self.change_at(offset, product, change)
+    }
+
+    /// Runs `nodes`, returns `None` if they need input, trap or take too many steps.
+    /// The state is left as it was at that point.
+    fn evaluate(&mut self, nodes: &[Node<C>]) -> Option<()> {
+        for node in nodes {
+            self.step()?;
+            match node {
+                Node::AddPointer(value) => self.tape.move_right(*value).then_some(())?,
+                Node::SubtractPointer(value) => self.tape.move_left(*value).then_some(())?,
+                Node::AddValue(value) => self.change_at(0, *value, Overflow::add)?,
+                Node::SubtractValue(value) => self.change_at(0, *value, Overflow::subtract)?,
+                Node::SetValue(value) => self.tape.set(*value),
+                Node::MultiplyAdd { offset, factor } => {
+                    self.multiply(*offset, *factor, Overflow::add)?;
+                }
+                Node::MultiplySubtract { offset, factor } => {
+                    self.multiply(*offset, *factor, Overflow::subtract)?;
+                }
+                // Scan a cell at a time, as scanning a wrapping tape might never stop
+                Node::ScanRight(stride) => {
+                    while self.tape.get() != C::ZERO {
+                        self.step()?;
+                        self.tape.move_right(*stride).then_some(())?;
+                    }
+                }
+                Node::ScanLeft(stride) => {
+                    while self.tape.get() != C::ZERO {
+                        self.step()?;
+                        self.tape.move_left(*stride).then_some(())?;
+                    }
+                }
+                Node::AddAt { offset, value } => {
+                    self.change_at(*offset, *value, Overflow::add)?;
+                }
+                Node::SubtractAt { offset, value } => {
+                    self.change_at(*offset, *value, Overflow::subtract)?;
+                }
+                Node::SetAt { offset, value } => *self.tape.cell_at(*offset)? = *value,
+                Node::OutputAt { offset } => {
+                    let byte = self.tape.cell_at(*offset)?.to_byte();
+                    self.output.push(byte);
+                }
+                Node::Output => self.output.push(self.tape.get().to_byte()),
+                Node::OutputBytes(bytes) => self.output.extend(bytes),
+                Node::Input => return None,
+                Node::Loop(body) => {
+                    while self.tape.get() != C::ZERO {
+                        self.step()?;
+                        self.evaluate(body)?;
+                    }
+                }
+            }
+        }
+        Some(())
+    }
+
+    /// Returns nodes that recreate the output and the tape of the evaluated nodes.
+    fn into_nodes(self) -> Vec<Node<C>> {
+        let mut nodes = Vec::new();
+        if !self.output.is_empty() {
+            nodes.push(Node::OutputBytes(self.output));
+        }
+
+        // Set every cell that isn't 0 relative to the start, then move to the current cell
+        let origin = self.tape.origin();
+        for (index, value) in self.tape.cells().iter().enumerate() {
+            if *value != C::ZERO {
+                nodes.push(Node::SetAt {
+                    offset: index.wrapping_sub(origin).cast_signed(),
+                    value: *value,
+                });
+            }
+        }
+        let position = self.tape.position();
+        match position.cmp(&0) {
+            Ordering::Greater => nodes.push(Node::AddPointer(position.unsigned_abs())),
+            Ordering::Less => nodes.push(Node::SubtractPointer(position.unsigned_abs())),
+            Ordering::Equal => {}
+        }
+        nodes
+    }
+}
+
+/// Runs the start of the program at compile time, up to the first node that needs input.
+/// The output and the resulting tape are turned into nodes that output all bytes at once and set
+/// the cells directly.
+///
+/// Only whole nodes at the top level are replaced, so a loop that reads input is kept entirely.
+/// Nothing is replaced if the program traps or runs too long.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>, config: Config) -> Vec<Node<C>> {
+    // Find the first node that can't be evaluated
+    let mut first_try = Evaluator::new(config);
+    let evaluated = nodes
+        .iter()
+        .position(|node| first_try.evaluate(slice::from_ref(node)).is_none())
+        .unwrap_or(nodes.len());
+    if evaluated == 0 {
+        return nodes;
+    }
+
+    // The failed node could have changed the state, so evaluate the nodes before it again
+    let mut evaluator = Evaluator::new(config);
+    let finished = evaluator.evaluate(&nodes[..evaluated]);
+    debug_assert!(finished.is_some(), "Evaluating the same nodes failed");
+    let mut optimized = evaluator.into_nodes();
+    optimized.extend(nodes.into_iter().skip(evaluated));
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{Config, Overflow},
+        ir::Node,
+    };
+
+    use super::run;
+
+    #[test]
+    fn constant_output() {
+        let commands = crate::parse(*b"++++++++[>+++++++++<-]>.<+++[>+++++++++++<-]>.<<,.")
+            .map(|token| token.command);
+        let nodes = Node::<u8>::build(commands).expect("The loops are balanced");
+        assert_eq!(
+            run(nodes, Config::default()),
+            [
+                Node::OutputBytes(b"Hi".to_vec()),
+                Node::SetAt {
+                    offset: 1,
+                    value: 105
+                },
+                Node::SubtractPointer(1),
+                Node::Input,
+                Node::Output,
+            ]
+        );
+
+        // Loops that never stop and traps are left for the program to run into
+        assert_eq!(
+            run(
+                vec![Node::<u8>::AddValue(1), Node::Loop(vec![])],
+                Config::default()
+            ),
+            [
+                Node::SetAt {
+                    offset: 0,
+                    value: 1
+                },
+                Node::Loop(vec![])
+            ]
+        );
+        let trapping = Config {
+            overflow: Overflow::Trap,
+            ..Config::default()
+        };
+        assert_eq!(
+            run(vec![Node::<u8>::SubtractValue(1)], trapping),
+            [Node::SubtractValue(1)]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{cell::Cell, ir::Node};
+
+/// What is known about the cells at a point in the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Known {
+    /// Every cell is 0, like at the start of the program.
+    AllZero,
+    /// The current cell is 0, like after a loop.
+    CurrentZero,
+    /// Nothing is known about the cells.
+    Nothing,
+}
+
+impl Known {
+    /// Checks whether `node` does nothing, because it only acts on a current cell that isn't 0.
+    fn is_dead<C: Cell>(self, node: &Node<C>) -> bool {
+        self != Self::Nothing
+            && match node {
+                Node::Loop(_)
+                | Node::ScanRight(_)
+                | Node::ScanLeft(_)
+                | Node::MultiplyAdd { .. }
+                | Node::MultiplySubtract { .. } => true,
+                Node::SetValue(value) => *value == C::ZERO,
+                _ => false,
+            }
+    }
+
+    /// Returns what is known after running `node`.
+    fn after<C: Cell>(self, node: &Node<C>) -> Self {
+        match node {
+            Node::AddPointer(_) | Node::SubtractPointer(_) if self == Self::AllZero => self,
+            Node::SetAt { value, .. } if self == Self::AllZero && *value == C::ZERO => self,
+            Node::AddAt { offset, .. }
+            | Node::SubtractAt { offset, .. }
+            | Node::SetAt { offset, .. }
+                if *offset != 0 && self == Self::AllZero =>
+            {
+                Self::CurrentZero
+            }
+            // Changes at offset 0 change the current cell, like their nodes without an offset
+            Node::SetAt { offset: 0, value } => self.after(&Node::SetValue(*value)),
+            Node::SetValue(value) if *value == C::ZERO => {
+                if self == Self::AllZero {
+                    self
+                } else {
+                    Self::CurrentZero
+                }
+            }
+            Node::Loop(_) | Node::ScanRight(_) | Node::ScanLeft(_) => Self::CurrentZero,
+            Node::Output
+            | Node::OutputAt { .. }
+            | Node::OutputBytes(_)
+            | Node::SetAt { .. }
+            | Node::MultiplyAdd { .. }
+            | Node::MultiplySubtract { .. } => self,
+            Node::AddAt { offset, .. } | Node::SubtractAt { offset, .. } if *offset != 0 => self,
+            Node::AddAt { .. }
+            | Node::SubtractAt { .. }
+            | Node::AddPointer(_)
+            | Node::SubtractPointer(_)
+            | Node::AddValue(_)
+            | Node::SubtractValue(_)
+            | Node::SetValue(_)
+            | Node::Input => Self::Nothing,
+        }
+    }
+}
+
+/// Removes the nodes in `nodes` that can't have any effect, starting with what is `known`.
+fn remove_dead_code<C: Cell>(nodes: Vec<Node<C>>, mut known: Known) -> Vec<Node<C>> {
+    let mut optimized = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        if known.is_dead(&node) {
+            continue;
+        }
+        known = known.after(&node);
+
+        // Nothing is known about the cells at the start of a loop body, except that the current
+        // cell isn't 0
+        optimized.push(match node {
+            Node::Loop(body) => Node::Loop(remove_dead_code(body, Known::Nothing)),
+            other => other,
+        });
+    }
+    optimized
+}
+
+/// Removes loops that can never run, because the current cell is known to be 0 at their start,
+/// like comment loops at the start of a program or loops right after another loop.
+/// Other nodes that only act on a current cell that isn't 0 are removed as well.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>) -> Vec<Node<C>> {
+    remove_dead_code(nodes, Known::AllZero)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::Node;
+
+    use super::run;
+
+    #[test]
+    fn unreachable_loops() {
+        let nodes = vec![
+            Node::<u8>::Loop(vec![Node::Output]),
+            Node::AddPointer(2),
+            Node::ScanLeft(1),
+            Node::SetValue(0),
+            Node::Input,
+            Node::Loop(vec![
+                Node::SubtractValue(1),
+                Node::Loop(vec![Node::AddPointer(1)]),
+                Node::Loop(vec![Node::Output]),
+            ]),
+            Node::MultiplyAdd {
+                offset: 1,
+                factor: 2,
+            },
+            Node::SetValue(0),
+            Node::Output,
+        ];
+        assert_eq!(
+            run(nodes),
+            [
+                Node::AddPointer(2),
+                Node::Input,
+                Node::Loop(vec![
+                    Node::SubtractValue(1),
+                    Node::Loop(vec![Node::AddPointer(1)]),
+                ]),
+                Node::Output,
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_at_the_current_cell() {
+        let nodes = vec![
+            Node::<u8>::AddAt {
+                offset: 0,
+                value: 1,
+            },
+            Node::Loop(vec![Node::Output]),
+            Node::Loop(vec![Node::Output]),
+            Node::SetAt {
+                offset: 0,
+                value: 0,
+            },
+            Node::Loop(vec![Node::Output]),
+        ];
+        assert_eq!(
+            run(nodes),
+            [
+                Node::AddAt {
+                    offset: 0,
+                    value: 1,
+                },
+                Node::Loop(vec![Node::Output]),
+                Node::SetAt {
+                    offset: 0,
+                    value: 0,
+                },
+            ]
+        );
+    }
+}
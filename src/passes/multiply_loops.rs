@@ -0,0 +1,117 @@
+use crate::{
+    cell::Cell,
+    config::{Config, Edge, TapeMode},
+    ir::Node,
+};
+
+/// Returns the changes a loop body makes to other cells for every time the current cell is
+/// decremented, if the body is a multiply loop like `[->+>++<<]`.
+///
+/// The body has to return to the current cell, decrement it by exactly 1 and change every other
+/// cell at most once, so the changes can be applied all at once without changing the result.
+fn multiply_loop_changes<C: Cell>(body: &[Node<C>], config: Config) -> Option<Vec<Node<C>>> {
+    let mut offset = 0_isize;
+    let (mut leftmost, mut rightmost) = (0, 0);
+    let mut decremented = false;
+    let mut changes = Vec::new();
+    let mut targets = Vec::new();
+    for node in body {
+        match *node {
+            Node::AddPointer(value) => offset = offset.checked_add_unsigned(value)?,
+            Node::SubtractPointer(value) => offset = offset.checked_sub_unsigned(value)?,
+            Node::SubtractValue(value) if offset == 0 && value == C::ONE && !decremented => {
+                decremented = true;
+            }
+            Node::AddValue(factor) | Node::SubtractValue(factor)
+                if offset != 0 && !targets.contains(&offset) =>
+            {
+                targets.push(offset);
+                changes.push(if matches!(node, Node::AddValue(_)) {
+                    Node::MultiplyAdd { offset, factor }
+                } else {
+                    Node::MultiplySubtract { offset, factor }
+                });
+            }
+            _ => return None,
+        }
+        leftmost = leftmost.min(offset);
+        rightmost = rightmost.max(offset);
+    }
+
+    // The pointer has to visit the edges to change them, otherwise the loop could move past an
+    // edge of the tape without anything left to detect it.
+    // The cells can't wrap around the tape onto each other either.
+    let edges_changed = [leftmost, rightmost]
+        .iter()
+        .all(|edge| *edge == 0 || targets.contains(edge));
+    let overlapping = match config.tape {
+        TapeMode::Bounded {
+            size,
+            edge: Edge::Wrap,
+        } => rightmost.abs_diff(leftmost) >= size,
+        TapeMode::Bounded {
+            edge: Edge::Trap, ..
+        }
+        | TapeMode::RightInfinite
+        | TapeMode::BiInfinite => false,
+    };
+    (offset == 0 && decremented && edges_changed && !overlapping).then_some(changes)
+}
+
+/// Replaces multiply loops, like `[->+>++<<]`, with multiply nodes and setting the cell to 0.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>, config: Config) -> Vec<Node<C>> {
+    let mut optimized = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match node {
+            Node::Loop(body) => match multiply_loop_changes(&body, config) {
+                Some(changes) => {
+                    optimized.extend(changes);
+                    optimized.push(Node::SetValue(C::ZERO));
+                }
+                None => optimized.push(Node::Loop(run(body, config))),
+            },
+            other => optimized.push(other),
+        }
+    }
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{Config, Edge, TapeMode},
+        ir::Node,
+    };
+
+    use super::run;
+
+    #[test]
+    fn wrapping_tape() {
+        let nodes = vec![Node::<u8>::Loop(vec![
+            Node::SubtractValue(1),
+            Node::AddPointer(2),
+            Node::AddValue(1),
+            Node::SubtractPointer(2),
+        ])];
+        assert_eq!(
+            run(nodes.clone(), Config::default()),
+            [
+                Node::MultiplyAdd {
+                    offset: 2,
+                    factor: 1
+                },
+                Node::SetValue(0)
+            ]
+        );
+
+        // The target is the current cell on a tape with 2 cells
+        let wrapping = Config {
+            tape: TapeMode::Bounded {
+                size: 2,
+                edge: Edge::Wrap,
+            },
+            ..Config::default()
+        };
+        assert_eq!(run(nodes.clone(), wrapping), nodes);
+    }
+}
@@ -0,0 +1,100 @@
+use core::cmp::Ordering;
+
+use crate::{cell::Cell, ir::Node};
+
+/// Moves the pointer by the pending `offset` and resets it.
+fn apply_offset<C: Cell>(offset: &mut isize, optimized: &mut Vec<Node<C>>) {
+    match (*offset).cmp(&0) {
+        Ordering::Greater => optimized.push(Node::AddPointer(offset.unsigned_abs())),
+        Ordering::Less => optimized.push(Node::SubtractPointer(offset.unsigned_abs())),
+        Ordering::Equal => {}
+    }
+    *offset = 0;
+}
+
+/// Replaces pointer moves between value changes and output with nodes that use an offset from
+/// the current cell, so the pointer only moves once before the next loop or the end.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>) -> Vec<Node<C>> {
+    let mut optimized = Vec::with_capacity(nodes.len());
+    let mut offset = 0_isize;
+    let mut moved = false;
+    for node in nodes {
+        let node = match node {
+            Node::AddPointer(value) | Node::SubtractPointer(value) => {
+                // Two moves in a row can turn around at a cell no node uses, so moving past the
+                // edge of the tape there wouldn't trap anymore. Keep the first move.
+                if moved {
+                    apply_offset(&mut offset, &mut optimized);
+                }
+                let moved_offset = if matches!(node, Node::AddPointer(_)) {
+                    offset.checked_add_unsigned(value)
+                } else {
+                    offset.checked_sub_unsigned(value)
+                };
+                moved = true;
+                if let Some(moved_offset) = moved_offset {
+                    offset = moved_offset;
+                    continue;
+                }
+                apply_offset(&mut offset, &mut optimized);
+                node
+            }
+            Node::AddValue(value) if offset != 0 => Node::AddAt { offset, value },
+            Node::SubtractValue(value) if offset != 0 => Node::SubtractAt { offset, value },
+            Node::SetValue(value) if offset != 0 => Node::SetAt { offset, value },
+            Node::Output if offset != 0 => Node::OutputAt { offset },
+            Node::AddValue(_)
+            | Node::SubtractValue(_)
+            | Node::SetValue(_)
+            | Node::Output
+            | Node::OutputBytes(_) => node,
+            Node::Loop(body) => {
+                apply_offset(&mut offset, &mut optimized);
+                Node::Loop(run(body))
+            }
+            other => {
+                apply_offset(&mut offset, &mut optimized);
+                other
+            }
+        };
+        moved = false;
+        optimized.push(node);
+    }
+    apply_offset(&mut offset, &mut optimized);
+    optimized
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::Node;
+
+    use super::run;
+
+    #[test]
+    fn loop_bodies() {
+        let nodes = vec![
+            Node::<u8>::AddPointer(2),
+            Node::Loop(vec![
+                Node::SubtractPointer(1),
+                Node::SetValue(3),
+                Node::AddPointer(1),
+                Node::SubtractValue(1),
+            ]),
+            Node::SubtractPointer(2),
+        ];
+        assert_eq!(
+            run(nodes),
+            [
+                Node::AddPointer(2),
+                Node::Loop(vec![
+                    Node::SetAt {
+                        offset: -1,
+                        value: 3
+                    },
+                    Node::SubtractValue(1)
+                ]),
+                Node::SubtractPointer(2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{cell::Cell, ir::Node};
+
+/// Replaces loops that only move the pointer, like `[>>]`, with scans for a cell that is 0.
+pub fn run<C: Cell>(nodes: Vec<Node<C>>) -> Vec<Node<C>> {
+    nodes
+        .into_iter()
+        .map(|node| match node {
+            Node::Loop(body) => match body.as_slice() {
+                [Node::AddPointer(stride)] => Node::ScanRight(*stride),
+                [Node::SubtractPointer(stride)] => Node::ScanLeft(*stride),
+                _ => Node::Loop(run(body)),
+            },
+            other => other,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::Node;
+
+    use super::run;
+
+    #[test]
+    fn nested_scans() {
+        let nodes = vec![Node::<u8>::Loop(vec![
+            Node::Loop(vec![Node::AddPointer(1)]),
+            Node::Loop(vec![Node::SubtractPointer(3)]),
+            Node::SubtractValue(1),
+        ])];
+        assert_eq!(
+            run(nodes),
+            [Node::Loop(vec![
+                Node::ScanRight(1),
+                Node::ScanLeft(3),
+                Node::SubtractValue(1)
+            ])]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::chars::Chars;
+
+/// The location of a character in the source code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// The line number, starting at 1.
+    pub line: usize,
+    /// The character within the line, starting at 1.
+    pub column: usize,
+    /// The number of bytes before the character.
+    pub offset: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self {
+            line: 1,
+            column: 1,
+            offset: 0,
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Pairs every char of a char iterator with its position in the source code.
+pub struct Positions<T: Iterator> {
+    chars: Chars<T>,
+    next: Position,
+}
+
+impl<T: Iterator<Item = u8>> From<Chars<T>> for Positions<T> {
+    fn from(value: Chars<T>) -> Self {
+        Self {
+            chars: value,
+            next: Position::default(),
+        }
+    }
+}
+
+impl<T: Iterator<Item = u8>> Iterator for Positions<T> {
+    type Item = (Position, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The offset is taken from the decoder once it has skipped any invalid bytes, as invalid
+        // or raw bytes can differ in length
+        let character = self.chars.next()?;
+        self.next.offset = self.chars.offset();
+        let position = self.next;
+
+        // Move to the next column, or the start of the next line on a line feed
+        if character == '\n' {
+            self.next.line += 1;
+            self.next.column = 1;
+        } else {
+            self.next.column += 1;
+        }
+        Some((position, character))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Position, Positions};
+    use crate::chars::{Chars, Decoding, InvalidUtf8};
+
+    #[test]
+    fn lines_and_columns() {
+        let positions = Positions::from(Chars::from("+\n>\u{e9}-".bytes()))
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                Position {
+                    line: 1,
+                    column: 1,
+                    offset: 0
+                },
+                Position {
+                    line: 1,
+                    column: 2,
+                    offset: 1
+                },
+                Position {
+                    line: 2,
+                    column: 1,
+                    offset: 2
+                },
+                Position {
+                    line: 2,
+                    column: 2,
+                    offset: 3
+                },
+                Position {
+                    line: 2,
+                    column: 3,
+                    offset: 5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_bytes() {
+        let source = [b'+', 0xFF, 0xFE, b'[', b'\n', 0xC3, 0xA9, b']'];
+        let positions = |decoding| {
+            Positions::from(Chars::new(source.into_iter(), decoding))
+                .filter(|(_, character)| matches!(character, '[' | ']'))
+                .map(|(position, _)| (position.column, position.offset))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            positions(Decoding::Utf8(InvalidUtf8::Skip)),
+            [(2, 3), (2, 7)]
+        );
+        assert_eq!(positions(Decoding::Bytes), [(4, 3), (3, 7)]);
+    }
+}
@@ -1,44 +1,78 @@
-extern crate alloc;
+use std::io::{Read, Write};
 
-use alloc::collections::VecDeque;
-use std::io::{self, Read, Write};
-
-use crate::command::Command;
+use crate::{
+    cell::Cell,
+    command::Command,
+    config::{Config, Overflow},
+    error::Error,
+    tape::Tape,
+};
 
+/// A brainfuck program that executes every command as written.
 #[derive(Debug)]
-pub struct Program {
+pub struct Program<C: Cell = u8> {
     commands: Vec<Command>,
-    data: VecDeque<u8>,
+    /// The index of the matching bracket for every bracket in the commands.
+    jumps: Vec<usize>,
+    tape: Tape<C>,
+    config: Config,
+    /// The number of commands an execution can run, which is unlimited by default.
+    step_limit: u64,
 }
 
-impl From<Vec<Command>> for Program {
-    fn from(value: Vec<Command>) -> Self {
-        // Create a program from the command buffer
-        let result = Self {
-            commands: value,
-            data: VecDeque::new(),
-        };
+impl<C: Cell> TryFrom<Vec<Command>> for Program<C> {
+    type Error = Error;
 
-        // Make sure all loops are opened and closed
-        result.check();
-        result
+    fn try_from(value: Vec<Command>) -> Result<Self, Self::Error> {
+        // Make sure all loops are opened and closed, and create a program from the command buffer
+        Ok(Self {
+            jumps: Self::link(&value)?,
+            commands: value,
+            tape: Tape::new(Config::default().tape),
+            config: Config::default(),
+            step_limit: u64::MAX,
+        })
     }
 }
 
-impl Program {
-    fn check(&self) {
-        let mut active_loops = 0_usize;
+impl<C: Cell> Program<C> {
+    /// Sets the runtime behavior of the program.
+    #[must_use]
+    pub const fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Stops the program with [`Error::StepLimit`] once it has run `limit` commands, counting
+    /// every start and end of a loop it passes too.
+    #[must_use]
+    pub const fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = limit;
+        self
+    }
+
+    /// Returns the tape as it was left by the last execution.
+    #[must_use]
+    pub const fn tape(&self) -> &Tape<C> {
+        &self.tape
+    }
+
+    /// Finds the matching bracket of every bracket, so loops don't have to be searched on every jump.
+    fn link(commands: &[Command]) -> Result<Vec<usize>, Error> {
+        let mut jumps = vec![0; commands.len()];
+        let mut active_loops = Vec::new();
 
         // Iterate through the commands
-        for command in &self.commands {
-            // Increment the number of active loops on the start of loops.
-            // Decrement the number of active loops on the end of loops.
-            // Panic if the end of a loop is found, while there are no active loops.
+        for (index, command) in commands.iter().enumerate() {
+            // Store the index of the start of loops until they're closed.
+            // Link the start and end of loop to each other on the end of loops.
+            // Fail if the end of a loop is found, while there are no active loops.
             match command {
-                Command::StartOfLoop => active_loops += 1,
+                Command::StartOfLoop => active_loops.push(index),
                 Command::EndOfLoop => {
-                    assert_ne!(active_loops, 0, "Unexpected end of loop");
-                    active_loops -= 1;
+                    let start = active_loops.pop().ok_or(Error::UnmatchedEndOfLoop(None))?;
+                    jumps[start] = index;
+                    jumps[index] = start;
                 }
                 Command::IncrementPointer
                 | Command::DecrementPointer
@@ -49,104 +83,60 @@ impl Program {
             }
         }
 
-        // Panic if not all loops were closed
-        assert_eq!(active_loops, 0, "Missing end of loop");
-    }
-
-    fn decrement_pointer(&mut self, pointer: &mut usize) {
-        if *pointer > 0 {
-            *pointer -= 1;
+        // Fail if not all loops were closed
+        if active_loops.is_empty() {
+            Ok(jumps)
         } else {
-            self.data.push_front(0);
+            Err(Error::UnmatchedStartOfLoop(None))
         }
     }
 
-    fn increment_pointer(&mut self, pointer: &mut usize) {
-        *pointer += 1;
-        if *pointer >= self.data.len() {
-            self.data.push_back(0);
-        }
+    fn output<Output: Write>(&self, output: &mut Output) -> Result<(), Error> {
+        output.write_all(&[self.tape.get().to_byte()])?;
+        Ok(())
     }
 
-    fn input(&mut self, pointer: usize) {
-        self.data[pointer] = io::stdin()
-            .bytes()
-            .next()
-            .expect("Failed to read input")
-            .expect("Failed to read input");
-    }
-
-    fn output(&self, pointer: usize) {
-        while io::stdout()
-            .write(&[self.data[pointer]])
-            .expect("Failed to print data")
-            == 0
-        {}
-    }
-
-    #[expect(clippy::unwrap_used, reason = "Every loop has a valid start and end")]
-    fn start_of_loop(&self, pc: &mut usize) {
-        // Go to the end of the loop
-        let mut active_loops = 1;
-        *pc = self
-            .commands
-            .iter()
-            .enumerate()
-            .skip(*pc + 1)
-            .find(|(_, command)| {
-                match command {
-                    Command::StartOfLoop => active_loops += 1,
-                    Command::EndOfLoop => active_loops -= 1,
-                    _ => {}
-                }
-                active_loops == 0
-            })
-            .unwrap()
-            .0;
-    }
-
-    #[expect(clippy::unwrap_used, reason = "Every loop has a valid start and end")]
-    fn end_of_loop(&self, pc: &mut usize) {
-        // Go to the beginning of the loop
-        let mut ended_loops = 1;
-        *pc = self.commands[..*pc]
-            .iter()
-            .enumerate()
-            .rev()
-            .find(|(_, command)| {
-                match command {
-                    Command::StartOfLoop => ended_loops -= 1,
-                    Command::EndOfLoop => ended_loops += 1,
-                    _ => {}
-                }
-                ended_loops == 0
-            })
-            .unwrap()
-            .0;
-    }
-
-    pub fn execute(&mut self) {
-        // Create a program counter, pointer and data buffer
+    /// Runs the program, reading bytes from `input` and writing bytes to `output`.
+    ///
+    /// # Errors
+    /// Returns an error if the input runs out, the program runs past its step limit or reading
+    /// input or writing output fails.
+    pub fn execute<Input: Read, Output: Write>(
+        &mut self,
+        mut input: Input,
+        mut output: Output,
+    ) -> Result<(), Error> {
+        // Create a program counter and an empty tape
         let mut pc = 0;
-        let mut pointer = 0;
-        self.data.clear();
-        self.data.push_back(0);
+        let mut steps = 0;
+        self.tape = Tape::new(self.config.tape);
 
         // Iterate through the commands
         while let Some(command) = self.commands.get(pc) {
+            if steps == self.step_limit {
+                return Err(Error::StepLimit { pc });
+            }
+            steps += 1;
+
             // Execute the command
             match command {
-                Command::DecrementPointer => self.decrement_pointer(&mut pointer),
-                Command::IncrementPointer => self.increment_pointer(&mut pointer),
-                Command::DecrementValue => self.data[pointer] -= 1,
-                Command::IncrementValue => self.data[pointer] += 1,
-                Command::Input => self.input(pointer),
-                Command::Output => self.output(pointer),
-                Command::StartOfLoop if self.data[pointer] == 0 => {
-                    self.start_of_loop(&mut pc);
+                Command::DecrementPointer => self.tape.subtract_pointer(pc, 1)?,
+                Command::IncrementPointer => self.tape.add_pointer(pc, 1)?,
+                Command::DecrementValue => {
+                    self.tape
+                        .change(pc, C::ONE, self.config.overflow, Overflow::subtract)?;
                 }
-                Command::EndOfLoop if self.data[pointer] != 0 => {
-                    self.end_of_loop(&mut pc);
+                Command::IncrementValue => {
+                    self.tape
+                        .change(pc, C::ONE, self.config.overflow, Overflow::add)?;
+                }
+                Command::Input => self.tape.input(&mut input, self.config.end_of_input)?,
+                Command::Output => self.output(&mut output)?,
+                Command::StartOfLoop if self.tape.get() == C::ZERO => {
+                    pc = self.jumps[pc];
+                }
+                Command::EndOfLoop if self.tape.get() != C::ZERO => {
+                    pc = self.jumps[pc];
                 }
                 Command::StartOfLoop | Command::EndOfLoop => {}
             }
@@ -156,7 +146,139 @@ impl Program {
         }
 
         // Flush the output
-        #[expect(clippy::unwrap_used, reason = "Flushing stdout shouldn't go wrong.")]
-        io::stdout().flush().unwrap();
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        compile,
+        config::{Config, Edge, EndOfInput, Overflow, TapeMode},
+        error::Error,
+        parse,
+    };
+
+    #[test]
+    fn hello() {
+        let mut program = compile::<u8, _>(
+            parse(*b"++++++++[>+++++++++<-]>.<+++[>+++++++++++<-]>."),
+            Config::default(),
+        )
+        .expect("The loops are balanced");
+        let mut output = Vec::new();
+        program
+            .execute(&[][..], &mut output)
+            .expect("The program doesn't use input");
+        assert_eq!(output, b"Hi");
+    }
+
+    #[test]
+    fn wide_cells() {
+        let source = format!("{}[>+<-]", "+".repeat(256));
+        let mut program = compile::<u16, _>(parse(source.bytes()), Config::default())
+            .expect("The loops are balanced");
+        program
+            .execute(&[][..], io::sink())
+            .expect("The program doesn't use input or output");
+        assert_eq!(program.tape.cells(), &[0, 256]);
+    }
+
+    #[test]
+    fn overflow() {
+        let run = |overflow| {
+            let config = Config {
+                overflow,
+                ..Config::default()
+            };
+            let mut program = compile::<u8, _>(parse(*b"+--"), config).expect("There are no loops");
+            program
+                .execute(&[][..], io::sink())
+                .map(|()| program.tape.cells().clone())
+        };
+        assert_eq!(run(Overflow::Wrap).expect("Cells wrap"), [255]);
+        assert_eq!(run(Overflow::Saturate).expect("Cells saturate"), [0]);
+        assert!(matches!(
+            run(Overflow::Trap),
+            Err(Error::Overflow { pc: 2, cell: 0 })
+        ));
+    }
+
+    #[test]
+    fn end_of_input() {
+        let run = |end_of_input| {
+            let config = Config {
+                end_of_input,
+                ..Config::default()
+            };
+            let mut program =
+                compile::<u8, _>(parse(*b"+++,"), config).expect("There are no loops");
+            program
+                .execute(&[][..], io::sink())
+                .map(|()| program.tape.cells().clone())
+        };
+        assert_eq!(run(EndOfInput::Zero).expect("EOF stores 0"), [0]);
+        assert_eq!(run(EndOfInput::MinusOne).expect("EOF stores -1"), [255]);
+        assert_eq!(run(EndOfInput::Unchanged).expect("EOF is ignored"), [3]);
+        assert!(matches!(run(EndOfInput::Trap), Err(Error::EndOfInput)));
+    }
+
+    #[test]
+    fn tape_edges() {
+        let run = |tape, source: &[u8]| {
+            let config = Config {
+                tape,
+                ..Config::default()
+            };
+            let mut program = compile::<u8, _>(parse(source.iter().copied()), config)
+                .expect("There are no loops");
+            program
+                .execute(&[][..], io::sink())
+                .map(|()| program.tape.cells().clone())
+        };
+        let wrapping = TapeMode::Bounded {
+            size: 3,
+            edge: Edge::Wrap,
+        };
+        let bounded = TapeMode::Bounded {
+            size: 3,
+            edge: Edge::Trap,
+        };
+        assert_eq!(
+            run(TapeMode::BiInfinite, b"<+").expect("The tape grows"),
+            [1, 0]
+        );
+        assert!(matches!(
+            run(TapeMode::RightInfinite, b"+<"),
+            Err(Error::TapeUnderflow { pc: 1 })
+        ));
+        assert_eq!(run(wrapping, b"<+>++").expect("The tape wraps"), [2, 0, 1]);
+        assert!(matches!(
+            run(bounded, b">>>"),
+            Err(Error::TapeOverflow { pc: 2 })
+        ));
+    }
+
+    #[test]
+    fn step_limit() {
+        let run = |source: &[u8], limit| {
+            compile::<u8, _>(parse(source.iter().copied()), Config::default())
+                .expect("The loops are balanced")
+                .with_step_limit(limit)
+                .execute(io::empty(), io::sink())
+        };
+        run(b"+++", 3).expect("The program runs 3 commands");
+        assert!(matches!(run(b"+++", 2), Err(Error::StepLimit { pc: 2 })));
+        assert!(matches!(run(b"+[]", 100), Err(Error::StepLimit { pc: 2 })));
+    }
+
+    #[test]
+    fn jump_table() {
+        let program = compile::<u8, _>(parse(*b"[+[-]>]"), Config::default())
+            .expect("The loops are balanced");
+        assert_eq!(program.jumps, [6, 0, 4, 0, 2, 0, 0]);
     }
 }
@@ -0,0 +1,574 @@
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use std::{
+    io::{self, Read},
+    thread,
+};
+
+use crate::{
+    cell::Cell,
+    config::{Edge, EndOfInput, Overflow, TapeMode},
+    error::Error,
+};
+
+/// How a scan for a 0 ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scan {
+    /// The pointer is on a 0.
+    Found,
+    /// The pointer would move past an edge of the tape.
+    Edge,
+    /// The pointer came back to where it started on a wrapping tape without finding a 0, so the
+    /// scan would never end.
+    Endless,
+}
+
+/// The cells of a program and the pointer to the current cell.
+///
+/// The tape keeps track of where cell 0 is, so growing to the left doesn't change which cell the
+/// pointer is on.
+#[derive(Debug)]
+pub struct Tape<C: Cell> {
+    cells: VecDeque<C>,
+    origin: usize,
+    pointer: usize,
+    mode: TapeMode,
+}
+
+impl<C: Cell> Tape<C> {
+    /// Creates a tape with the pointer at cell 0.
+    #[must_use]
+    pub fn new(mode: TapeMode) -> Self {
+        let mut cells = VecDeque::new();
+        match mode {
+            TapeMode::Bounded { size, .. } => cells.resize(size.max(1), C::ZERO),
+            TapeMode::RightInfinite | TapeMode::BiInfinite => cells.push_back(C::ZERO),
+        }
+        Self {
+            cells,
+            origin: 0,
+            pointer: 0,
+            mode,
+        }
+    }
+
+    /// Returns the cells that have been allocated so far, from left to right.
+    #[must_use]
+    pub const fn cells(&self) -> &VecDeque<C> {
+        &self.cells
+    }
+
+    /// Returns the index in [`Self::cells`] of the cell the pointer started at.
+    #[must_use]
+    pub const fn origin(&self) -> usize {
+        self.origin
+    }
+
+    /// Returns the index of the current cell, relative to the cell the pointer started at.
+    #[must_use]
+    pub const fn position(&self) -> isize {
+        self.position_of(self.pointer)
+    }
+
+    /// Returns the index of the cell at `index` in [`Self::cells`], relative to the cell the
+    /// pointer started at.
+    const fn position_of(&self, index: usize) -> isize {
+        index.wrapping_sub(self.origin).cast_signed()
+    }
+
+    /// Returns the index in [`Self::cells`] of the current cell.
+    pub(crate) const fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    /// Moves the pointer to the cell at `pointer` in [`Self::cells`], which has to be allocated.
+    pub(crate) fn set_pointer(&mut self, pointer: usize) {
+        debug_assert!(pointer < self.cells.len(), "The cell isn't allocated");
+        self.pointer = pointer;
+    }
+
+    /// Returns the cells that have been allocated so far as one slice.
+    pub(crate) fn contiguous(&mut self) -> &mut [C] {
+        self.cells.make_contiguous()
+    }
+
+    /// Returns the value of the current cell.
+    #[must_use]
+    pub fn get(&self) -> C {
+        self.cells[self.pointer]
+    }
+
+    /// Changes the value of the current cell.
+    pub fn set(&mut self, value: C) {
+        self.cells[self.pointer] = value;
+    }
+
+    /// Returns the cell `offset` cells right of the current cell, without moving the pointer.
+    /// Returns `None` if the pointer couldn't move to that cell.
+    pub fn cell_at(&mut self, offset: isize) -> Option<&mut C> {
+        let index = self.index_at(offset)?;
+        Some(&mut self.cells[index])
+    }
+
+    /// Returns the index in [`Self::cells`] of the cell `offset` cells right of the current cell,
+    /// like [`Self::cell_at`].
+    fn index_at(&mut self, offset: isize) -> Option<usize> {
+        // Move to the cell and back, so the cell is allocated or wrapped like on a real move.
+        // Moving back never grows the front of the tape, so the index stays valid.
+        let amount = offset.unsigned_abs();
+        let index = if offset < 0 {
+            if !self.move_left(amount) {
+                return None;
+            }
+            let index = self.pointer;
+            let moved_back = self.move_right(amount);
+            debug_assert!(moved_back, "Moving back to the current cell failed");
+            index
+        } else {
+            if !self.move_right(amount) {
+                return None;
+            }
+            let index = self.pointer;
+            let moved_back = self.move_left(amount);
+            debug_assert!(moved_back, "Moving back to the current cell failed");
+            index
+        };
+        Some(index)
+    }
+
+    /// Moves the pointer to the right in steps of `stride` cells, until the current cell is 0.
+    /// Stops at the edge if the pointer would move right of the last cell of a bounded tape.
+    #[must_use]
+    pub fn scan_right(&mut self, stride: usize) -> Scan {
+        let pointer = self.pointer;
+        let cells = &self.cells.make_contiguous()[pointer..];
+        let found = if stride == 1 {
+            find_zero(cells)
+        } else {
+            cells
+                .iter()
+                .step_by(stride)
+                .position(|cell| *cell == C::ZERO)
+                .map(|steps| steps * stride)
+        };
+        if let Some(distance) = found {
+            self.pointer += distance;
+            return Scan::Found;
+        }
+        match self.mode {
+            TapeMode::BiInfinite | TapeMode::RightInfinite => {
+                // The cells that haven't been allocated yet are 0, so stop at the first one
+                let distance = cells.len().div_ceil(stride) * stride;
+                let moved = self.move_right(distance);
+                debug_assert!(moved, "Moving right on an infinite tape failed");
+                Scan::Found
+            }
+            TapeMode::Bounded {
+                edge: Edge::Wrap, ..
+            } => self.scan_around(stride, Self::move_right),
+            TapeMode::Bounded {
+                edge: Edge::Trap, ..
+            } => Scan::Edge,
+        }
+    }
+
+    /// Moves the pointer to the left in steps of `stride` cells, until the current cell is 0.
+    /// Stops at the edge if the pointer would move left of the first cell of the tape.
+    #[must_use]
+    pub fn scan_left(&mut self, stride: usize) -> Scan {
+        let pointer = self.pointer;
+        let cells = &self.cells.make_contiguous()[..=pointer];
+        let found = if stride == 1 {
+            find_last_zero(cells).map(|index| pointer - index)
+        } else {
+            cells
+                .iter()
+                .rev()
+                .step_by(stride)
+                .position(|cell| *cell == C::ZERO)
+                .map(|steps| steps * stride)
+        };
+        if let Some(distance) = found {
+            self.pointer -= distance;
+            return Scan::Found;
+        }
+        match self.mode {
+            TapeMode::BiInfinite => {
+                // The cells that haven't been allocated yet are 0, so stop at the first one
+                let distance = (pointer + 1).div_ceil(stride) * stride;
+                let moved = self.move_left(distance);
+                debug_assert!(moved, "Moving left on a bi-infinite tape failed");
+                Scan::Found
+            }
+            TapeMode::Bounded {
+                edge: Edge::Wrap, ..
+            } => self.scan_around(stride, Self::move_left),
+            TapeMode::Bounded {
+                edge: Edge::Trap, ..
+            }
+            | TapeMode::RightInfinite => Scan::Edge,
+        }
+    }
+
+    /// Scans a wrapping tape by moving with `step` in steps of `stride` cells.
+    fn scan_around(&mut self, stride: usize, step: fn(&mut Self, usize) -> bool) -> Scan {
+        // The pointer is back where it started after visiting every cell it can reach, which
+        // are `size / gcd(size, stride)` cells
+        let size = self.cells.len();
+        for _ in 0..size.div_euclid(gcd(size, stride)) {
+            if self.get() == C::ZERO {
+                return Scan::Found;
+            }
+            let moved = step(self, stride);
+            debug_assert!(moved, "Moving on a wrapping tape failed");
+        }
+        Scan::Endless
+    }
+
+    /// Moves the pointer `amount` cells to the left.
+    /// Returns false if the pointer would move left of the first cell of the tape.
+    #[must_use]
+    pub fn move_left(&mut self, amount: usize) -> bool {
+        if let Some(pointer) = self.pointer.checked_sub(amount) {
+            self.pointer = pointer;
+            return true;
+        }
+        match self.mode {
+            TapeMode::BiInfinite => {
+                // Add the missing cells to the front, cell 0 moves along with the other cells
+                let missing = amount - self.pointer;
+                self.cells.reserve(missing);
+                for _ in 0..missing {
+                    self.cells.push_front(C::ZERO);
+                }
+                self.origin += missing;
+                self.pointer = 0;
+                true
+            }
+            TapeMode::Bounded {
+                edge: Edge::Wrap, ..
+            } => {
+                let size = self.cells.len();
+                self.pointer = (self.pointer + size - amount % size) % size;
+                true
+            }
+            TapeMode::Bounded {
+                edge: Edge::Trap, ..
+            }
+            | TapeMode::RightInfinite => false,
+        }
+    }
+
+    /// Moves the pointer `amount` cells to the right.
+    /// Returns false if the pointer would move right of the last cell of a bounded tape.
+    #[must_use]
+    pub fn move_right(&mut self, amount: usize) -> bool {
+        let pointer = self.pointer + amount;
+        if pointer < self.cells.len() {
+            self.pointer = pointer;
+            return true;
+        }
+        match self.mode {
+            TapeMode::BiInfinite | TapeMode::RightInfinite => {
+                self.cells.resize(pointer + 1, C::ZERO);
+                self.pointer = pointer;
+                true
+            }
+            TapeMode::Bounded {
+                edge: Edge::Wrap, ..
+            } => {
+                self.pointer = pointer % self.cells.len();
+                true
+            }
+            TapeMode::Bounded {
+                edge: Edge::Trap, ..
+            } => false,
+        }
+    }
+
+    /// Moves the pointer `amount` cells to the left for the command at `pc`.
+    pub(crate) fn subtract_pointer(&mut self, pc: usize, amount: usize) -> Result<(), Error> {
+        if self.move_left(amount) {
+            Ok(())
+        } else {
+            Err(Error::TapeUnderflow { pc })
+        }
+    }
+
+    /// Moves the pointer `amount` cells to the right for the command at `pc`.
+    pub(crate) fn add_pointer(&mut self, pc: usize, amount: usize) -> Result<(), Error> {
+        if self.move_right(amount) {
+            Ok(())
+        } else {
+            Err(Error::TapeOverflow { pc })
+        }
+    }
+
+    /// Scans to the left for the command at `pc`, like [`Self::scan_left`].
+    /// A scan that would never end stops the program at once if it has a step limit, which it
+    /// would reach anyway, and otherwise never returns, like the loop the scan replaces.
+    pub(crate) fn checked_scan_left(
+        &mut self,
+        pc: usize,
+        stride: usize,
+        step_limit: u64,
+    ) -> Result<(), Error> {
+        match self.scan_left(stride) {
+            Scan::Found => Ok(()),
+            Scan::Edge => Err(Error::TapeUnderflow { pc }),
+            Scan::Endless => endless_scan(pc, step_limit),
+        }
+    }
+
+    /// Scans to the right for the command at `pc`, like [`Self::checked_scan_left`].
+    pub(crate) fn checked_scan_right(
+        &mut self,
+        pc: usize,
+        stride: usize,
+        step_limit: u64,
+    ) -> Result<(), Error> {
+        match self.scan_right(stride) {
+            Scan::Found => Ok(()),
+            Scan::Edge => Err(Error::TapeOverflow { pc }),
+            Scan::Endless => endless_scan(pc, step_limit),
+        }
+    }
+
+    /// Returns the index in [`Self::cells`] of the cell `offset` cells right of the current cell
+    /// for the command at `pc`.
+    fn checked_index(&mut self, pc: usize, offset: isize) -> Result<usize, Error> {
+        self.index_at(offset).ok_or(if offset < 0 {
+            Error::TapeUnderflow { pc }
+        } else {
+            Error::TapeOverflow { pc }
+        })
+    }
+
+    /// Returns the cell `offset` cells right of the current cell for the command at `pc`.
+    pub(crate) fn checked_cell_at(&mut self, pc: usize, offset: isize) -> Result<&mut C, Error> {
+        let index = self.checked_index(pc, offset)?;
+        Ok(&mut self.cells[index])
+    }
+
+    /// Changes the cell at `index` in [`Self::cells`] by `value` for the command at `pc`, using
+    /// `change` to add or subtract it.
+    fn change_index(
+        &mut self,
+        pc: usize,
+        index: usize,
+        value: C,
+        overflow: Overflow,
+        change: fn(Overflow, C, C) -> Option<C>,
+    ) -> Result<(), Error> {
+        self.cells[index] =
+            change(overflow, self.cells[index], value).ok_or_else(|| Error::Overflow {
+                pc,
+                cell: self.position_of(index),
+            })?;
+        Ok(())
+    }
+
+    /// Changes the current cell by `value` for the command at `pc`, using `change` to add or
+    /// subtract it.
+    pub(crate) fn change(
+        &mut self,
+        pc: usize,
+        value: C,
+        overflow: Overflow,
+        change: fn(Overflow, C, C) -> Option<C>,
+    ) -> Result<(), Error> {
+        self.change_index(pc, self.pointer, value, overflow, change)
+    }
+
+    /// Changes the cell at `offset` by `value` for the command at `pc`, using `change` to add or
+    /// subtract it.
+    pub(crate) fn change_at(
+        &mut self,
+        pc: usize,
+        offset: isize,
+        value: C,
+        overflow: Overflow,
+        change: fn(Overflow, C, C) -> Option<C>,
+    ) -> Result<(), Error> {
+        let index = self.checked_index(pc, offset)?;
+        self.change_index(pc, index, value, overflow, change)
+    }
+
+    /// Changes the cell at `offset` by the current cell times `factor` for the command at `pc`,
+    /// using `change` to add or subtract the product.
+    pub(crate) fn multiply(
+        &mut self,
+        pc: usize,
+        offset: isize,
+        factor: C,
+        overflow: Overflow,
+        change: fn(Overflow, C, C) -> Option<C>,
+    ) -> Result<(), Error> {
+        // A loop that doesn't run doesn't move the pointer either, so it can't leave the tape
+        let value = self.get();
+        if value == C::ZERO {
+            return Ok(());
+        }
+        let index = self.checked_index(pc, offset)?;
+        let product = overflow
+            .multiply(value, factor)
+            .ok_or_else(|| Error::Overflow {
+                pc,
+                cell: self.position_of(index),
+            })?;
+        self.change_index(pc, index, product, overflow, change)
+    }
+
+    /// Runs the `,` command: reads a byte from `input` into the current cell, or changes the
+    /// cell as `end_of_input` says once `input` has ended.
+    pub(crate) fn input<Input: Read + ?Sized>(
+        &mut self,
+        input: &mut Input,
+        end_of_input: EndOfInput,
+    ) -> Result<(), Error> {
+        let mut buffer = [0];
+        let value = match input.read_exact(&mut buffer) {
+            Ok(()) => C::from_byte(buffer[0]),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                end_of_input.value(self.get()).ok_or(Error::EndOfInput)?
+            }
+            Err(error) => return Err(Error::Io(error)),
+        };
+        self.set(value);
+        Ok(())
+    }
+}
+
+/// Returns the greatest common divisor of `a` and `b`.
+const fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Ends a scan at `pc` that would never end, see [`Tape::checked_scan_left`].
+fn endless_scan(pc: usize, step_limit: u64) -> Result<(), Error> {
+    if step_limit == u64::MAX {
+        never_return();
+    }
+    Err(Error::StepLimit { pc })
+}
+
+/// Blocks the current thread forever, without using the processor.
+fn never_return() -> ! {
+    loop {
+        thread::park();
+    }
+}
+
+/// The number of cells that are compared at once while searching for a 0.
+const CHUNK_SIZE: usize = 32;
+
+/// Checks whether any cell of `chunk` is 0.
+///
+/// All cells are compared without stopping at the first 0, which lets the compiler vectorize the
+/// comparisons.
+#[expect(
+    clippy::needless_bitwise_bool,
+    reason = "Stopping at the first 0 would keep the comparisons from being vectorized"
+)]
+fn contains_zero<C: Cell>(chunk: &[C]) -> bool {
+    chunk
+        .iter()
+        .fold(false, |found, cell| found | (*cell == C::ZERO))
+}
+
+/// Returns the index of the first cell that is 0, searching a chunk at a time.
+fn find_zero<C: Cell>(cells: &[C]) -> Option<usize> {
+    let (index, chunk) = cells
+        .chunks(CHUNK_SIZE)
+        .enumerate()
+        .find(|(_, chunk)| contains_zero(chunk))?;
+    let offset = chunk.iter().position(|cell| *cell == C::ZERO)?;
+    Some(index * CHUNK_SIZE + offset)
+}
+
+/// Returns the index of the last cell that is 0, searching a chunk at a time like [`find_zero`].
+fn find_last_zero<C: Cell>(cells: &[C]) -> Option<usize> {
+    let (index, chunk) = cells
+        .rchunks(CHUNK_SIZE)
+        .enumerate()
+        .find(|(_, chunk)| contains_zero(chunk))?;
+    let offset = chunk.iter().rposition(|cell| *cell == C::ZERO)?;
+    Some(cells.len() - (index * CHUNK_SIZE + chunk.len()) + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scan, Tape};
+    use crate::config::{Edge, TapeMode};
+
+    #[test]
+    fn growing_left() {
+        let mut tape = Tape::<u8>::new(TapeMode::BiInfinite);
+        tape.set(1);
+        assert!(tape.move_left(3));
+        tape.set(2);
+        assert_eq!(tape.position(), -3);
+        assert_eq!(tape.cells(), &[2, 0, 0, 1]);
+        assert!(tape.move_right(4));
+        assert_eq!(tape.position(), 1);
+        assert_eq!(tape.cells(), &[2, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn cells_at_offsets() {
+        let mut tape = Tape::<u8>::new(TapeMode::BiInfinite);
+        *tape.cell_at(-2).expect("The tape grows left") = 1;
+        *tape.cell_at(1).expect("The tape grows right") = 2;
+        assert_eq!(tape.position(), 0);
+        assert_eq!(tape.cells(), &[1, 0, 0, 2]);
+
+        let mut right_infinite = Tape::<u8>::new(TapeMode::RightInfinite);
+        assert!(right_infinite.cell_at(-1).is_none());
+    }
+
+    #[test]
+    fn scanning() {
+        let mut tape = Tape::<u8>::new(TapeMode::BiInfinite);
+        for _ in 0..100 {
+            tape.set(1);
+            assert!(tape.move_right(1));
+        }
+        assert!(tape.move_left(100));
+        assert_eq!(tape.scan_right(1), Scan::Found);
+        assert_eq!(tape.position(), 100);
+        assert!(tape.move_left(40));
+        assert_eq!(tape.scan_right(3), Scan::Found);
+        assert_eq!(tape.position(), 102);
+        assert!(tape.move_left(42));
+        assert_eq!(tape.scan_left(1), Scan::Found);
+        assert_eq!(tape.position(), -1);
+        assert!(tape.move_right(60));
+        assert_eq!(tape.scan_left(7), Scan::Found);
+        assert_eq!(tape.position(), -4);
+        assert_eq!(tape.cells().len(), 107);
+    }
+
+    #[test]
+    fn scanning_around() {
+        let mut tape = Tape::<u8>::new(TapeMode::Bounded {
+            size: 6,
+            edge: Edge::Wrap,
+        });
+        for _ in 0..5 {
+            tape.set(1);
+            assert!(tape.move_right(1));
+        }
+        assert!(tape.move_right(2));
+        assert_eq!(tape.scan_right(3), Scan::Endless);
+        assert_eq!(tape.scan_left(3), Scan::Endless);
+        assert_eq!(tape.position(), 1);
+        assert_eq!(tape.scan_left(1), Scan::Found);
+        assert_eq!(tape.position(), 5);
+        tape.set(1);
+        assert_eq!(tape.scan_right(1), Scan::Endless);
+    }
+}